@@ -0,0 +1,7 @@
+WHERE
+    ($1::TEXT IS NULL OR actor = $1)
+    AND ($2::TEXT IS NULL OR action = $2)
+    AND ($3::TEXT IS NULL OR target = $3)
+    AND ($4::TEXT IS NULL OR outcome = $4)
+    AND ($5::TIMESTAMPTZ IS NULL OR occurred_at >= $5)
+    AND ($6::TIMESTAMPTZ IS NULL OR occurred_at < $6)
@@ -0,0 +1,13 @@
+INSERT INTO audit.events(occurred_at, actor, actor_id, action, target, ip, user_agent, outcome, details)
+SELECT occurred_at, actor, actor_id, action, target, ip, user_agent, outcome, details::JSONB
+FROM UNNEST(
+    $1::TIMESTAMPTZ[],
+    $2::TEXT[],
+    $3::UUID[],
+    $4::TEXT[],
+    $5::TEXT[],
+    $6::TEXT[],
+    $7::TEXT[],
+    $8::TEXT[],
+    $9::TEXT[]
+) AS e(occurred_at, actor, actor_id, action, target, ip, user_agent, outcome, details);
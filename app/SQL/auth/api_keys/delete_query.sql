@@ -0,0 +1,4 @@
+DELETE FROM auth.api_keys
+WHERE
+    id = $1
+    AND user_id = $2;
@@ -0,0 +1,9 @@
+UPDATE auth.api_keys
+SET
+    name = COALESCE($3, name),
+    scopes = COALESCE($4, scopes),
+    expires_at = COALESCE($5, expires_at)
+WHERE
+    id = $1
+    AND user_id = $2
+RETURNING *;
@@ -0,0 +1,5 @@
+-- Drops unused tokens, so only the latest mail stays valid
+DELETE FROM auth.email_verifications
+WHERE
+    user_id = $1
+    AND used_at IS NULL;
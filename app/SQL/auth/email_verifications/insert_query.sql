@@ -0,0 +1 @@
+INSERT INTO auth.email_verifications(user_id, email, token_hash, expires_at) VALUES ($1, $2, $3, $4);
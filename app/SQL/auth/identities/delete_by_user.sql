@@ -0,0 +1,4 @@
+DELETE FROM auth.identities
+WHERE
+    provider = $1
+    AND user_id = $2;
@@ -0,0 +1 @@
+INSERT INTO auth.identities(provider, subject, user_id, email) VALUES ($1, $2, $3, $4);
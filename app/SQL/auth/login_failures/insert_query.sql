@@ -0,0 +1 @@
+INSERT INTO auth.login_failures(email, user_id, ip, user_agent, reason) VALUES ($1, $2, $3, $4, $5);
@@ -0,0 +1,3 @@
+DELETE FROM auth.mcp_clients
+WHERE
+    id = $1;
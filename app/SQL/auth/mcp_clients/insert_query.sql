@@ -0,0 +1,3 @@
+INSERT INTO auth.mcp_clients(name, prefix, token_hash, allowed_tools, expires_at)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING *;
@@ -0,0 +1,2 @@
+SELECT *
+FROM auth.mcp_clients
@@ -0,0 +1,7 @@
+-- Written at most once a minute per client
+UPDATE auth.mcp_clients
+SET
+    last_used_at = NOW()
+WHERE
+    id = $1
+    AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute');
@@ -0,0 +1,8 @@
+UPDATE auth.mcp_clients
+SET
+    name = COALESCE($2, name),
+    allowed_tools = COALESCE($3, allowed_tools),
+    expires_at = COALESCE($4, expires_at)
+WHERE
+    id = $1
+RETURNING *;
@@ -0,0 +1,3 @@
+DELETE FROM auth.recovery_codes
+WHERE
+    user_id = $1;
@@ -0,0 +1,2 @@
+INSERT INTO auth.recovery_codes(user_id, code_hash)
+SELECT $1, h FROM UNNEST($2::TEXT[]) AS h;
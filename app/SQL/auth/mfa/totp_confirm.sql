@@ -0,0 +1,7 @@
+UPDATE auth.totp
+SET
+    confirmed_at = NOW(),
+    last_step = $2
+WHERE
+    user_id = $1
+    AND confirmed_at IS NULL;
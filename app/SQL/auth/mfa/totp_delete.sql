@@ -0,0 +1,3 @@
+DELETE FROM auth.totp
+WHERE
+    user_id = $1;
@@ -0,0 +1,8 @@
+-- Restarts an unconfirmed enrollment; confirmed ones are left untouched
+INSERT INTO auth.totp(user_id, secret) VALUES ($1, $2)
+ON CONFLICT (user_id) DO UPDATE
+SET
+    secret = EXCLUDED.secret,
+    last_step = NULL
+WHERE
+    auth.totp.confirmed_at IS NULL;
@@ -0,0 +1,8 @@
+-- Accepts each step once, so an observed code cannot be replayed
+UPDATE auth.totp
+SET
+    last_step = $2
+WHERE
+    user_id = $1
+    AND confirmed_at IS NOT NULL
+    AND (last_step IS NULL OR last_step < $2);
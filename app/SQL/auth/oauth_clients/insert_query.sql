@@ -0,0 +1,3 @@
+INSERT INTO auth.oauth_clients(client_id, client_secret_hash, client_name, redirect_uris, token_endpoint_auth_method)
+VALUES ($1, $2, $3, $4, $5)
+RETURNING *;
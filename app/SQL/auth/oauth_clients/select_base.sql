@@ -0,0 +1,2 @@
+SELECT *
+FROM auth.oauth_clients
@@ -0,0 +1,9 @@
+-- Marks a token used; returns nothing when it is unknown, expired or already used
+UPDATE auth.password_resets
+SET
+    used_at = NOW()
+WHERE
+    token_hash = $1
+    AND used_at IS NULL
+    AND expires_at > NOW()
+RETURNING *;
@@ -0,0 +1,5 @@
+-- Drops unused tokens, so only the latest request stays valid
+DELETE FROM auth.password_resets
+WHERE
+    user_id = $1
+    AND used_at IS NULL;
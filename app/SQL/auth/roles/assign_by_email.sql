@@ -0,0 +1,6 @@
+INSERT INTO auth.user_roles (user_id, role_id)
+SELECT u.id, r.id
+FROM auth.users u
+CROSS JOIN auth.roles r
+WHERE u.email = $1 AND r.name = ANY($2)
+ON CONFLICT DO NOTHING;
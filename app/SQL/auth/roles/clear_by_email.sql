@@ -0,0 +1,2 @@
+DELETE FROM auth.user_roles
+WHERE user_id = (SELECT id FROM auth.users WHERE email = $1);
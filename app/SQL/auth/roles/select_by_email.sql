@@ -0,0 +1,6 @@
+SELECT r.*
+FROM auth.roles r
+INNER JOIN auth.user_roles ur ON ur.role_id = r.id
+INNER JOIN auth.users u ON u.id = ur.user_id
+WHERE u.email = $1
+ORDER BY r.name;
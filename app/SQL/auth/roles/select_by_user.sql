@@ -0,0 +1,5 @@
+SELECT r.*
+FROM auth.roles r
+INNER JOIN auth.user_roles ur ON ur.role_id = r.id
+WHERE ur.user_id = $1
+ORDER BY r.name;
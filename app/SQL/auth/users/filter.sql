@@ -0,0 +1,5 @@
+WHERE
+    ($1::TEXT IS NULL OR email ILIKE '%' || $1 || '%')
+    AND ($2::TEXT IS NULL OR name ILIKE '%' || $2 || '%')
+    AND ($3::BOOLEAN IS NULL OR (disabled_at IS NOT NULL) = $3)
+    AND ($4::BOOLEAN OR deleted_at IS NULL)
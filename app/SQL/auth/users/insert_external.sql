@@ -0,0 +1 @@
+INSERT INTO auth.users(name, email, password_hash, email_verified_at) VALUES ($1, $2, NULL, NOW()) RETURNING *;
@@ -0,0 +1,9 @@
+-- Verifies the address only while it is still the one the token was mailed to
+UPDATE auth.users
+SET
+    email_verified_at = COALESCE(email_verified_at, NOW())
+WHERE
+    id = $1
+    AND email = $2
+    AND deleted_at IS NULL
+RETURNING *;
@@ -0,0 +1,4 @@
+SELECT u.*
+FROM auth.users u
+INNER JOIN auth.identities i ON i.user_id = u.id
+WHERE i.provider = $1 AND i.subject = $2;
@@ -0,0 +1,7 @@
+UPDATE auth.users
+SET
+    disabled_at = CASE WHEN $2 THEN COALESCE(disabled_at, NOW()) ELSE NULL END
+WHERE
+    id = $1
+    AND deleted_at IS NULL
+RETURNING *;
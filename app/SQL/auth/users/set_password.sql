@@ -0,0 +1,7 @@
+UPDATE auth.users
+SET
+    password_hash = $2
+WHERE
+    id = $1
+    AND deleted_at IS NULL
+RETURNING *;
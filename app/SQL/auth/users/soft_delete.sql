@@ -0,0 +1,7 @@
+UPDATE auth.users
+SET
+    deleted_at = NOW()
+WHERE
+    id = $1
+    AND deleted_at IS NULL
+RETURNING *;
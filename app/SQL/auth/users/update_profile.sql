@@ -0,0 +1,10 @@
+UPDATE auth.users
+SET
+    name = COALESCE($2, name),
+    email = COALESCE($3, email),
+    -- A new address has to be verified again
+    email_verified_at = CASE WHEN $3 IS NULL OR $3 = email THEN email_verified_at ELSE NULL END
+WHERE
+    id = $1
+    AND deleted_at IS NULL
+RETURNING *;
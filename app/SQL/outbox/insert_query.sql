@@ -0,0 +1 @@
+INSERT INTO app.outbox(sender, recipient, subject, body) VALUES ($1, $2, $3, $4);
@@ -0,0 +1,282 @@
+use adk_rust::prelude::*;
+use async_trait::async_trait;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// `(user_id, session_id, call_id)` of a waiting call; see `approval_call_id`
+type WaitKey = (String, String, String);
+
+fn wait_key(user_id: &str, session_id: &str, call_id: &str) -> WaitKey {
+    (
+        user_id.to_owned(),
+        session_id.to_owned(),
+        call_id.to_owned(),
+    )
+}
+
+/// Id of a call to `tool_name` made in the invocation: ADK hands tools
+/// `{invocation_id}_{tool_name}` as their call id and lists long-running calls in
+/// `long_running_tool_ids` by tool name, so the id the model gave the call never reaches the
+/// tool. The gate, the `approval_required` frame and the pending list all use this one.
+pub fn approval_call_id(invocation_id: &str, tool_name: &str) -> String {
+    format!("{}_{}", invocation_id, tool_name)
+}
+
+/// A tool call waiting for its user
+struct Waiting {
+    id: u64, // Tells apart runs that reuse a key, so one never removes the other
+    decision: oneshot::Sender<bool>,
+}
+
+/// Tool calls paused until their user approves or rejects them, keyed by user, session and
+/// call id. Decisions reach the run in memory, so a call waits only while the process that
+/// runs it is up.
+pub struct ApprovalGate {
+    waiting: Mutex<HashMap<WaitKey, Waiting>>,
+    next_id: AtomicU64,
+    timeout: Duration,
+}
+
+/// Removes the call from the gate when its run stops waiting, also when the run is dropped
+struct WaitGuard<'a> {
+    gate: &'a ApprovalGate,
+    key: WaitKey,
+    id: u64,
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        let mut waiting = match self.gate.waiting.lock() {
+            Err(_) => return,
+            Ok(waiting) => waiting,
+        };
+        if waiting.get(&self.key).is_some_and(|w| w.id == self.id) {
+            waiting.remove(&self.key);
+        }
+    }
+}
+
+impl ApprovalGate {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            waiting: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            timeout,
+        }
+    }
+
+    /// Waits for the decision on a call; `None` when nobody decided in time
+    pub async fn wait(&self, user_id: &str, session_id: &str, call_id: &str) -> Option<bool> {
+        let (tx, rx) = oneshot::channel();
+        let key = wait_key(user_id, session_id, call_id);
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        match self.waiting.lock() {
+            Err(_) => return None,
+            Ok(mut waiting) => waiting.insert(key.clone(), Waiting { id, decision: tx }),
+        };
+        let _guard = WaitGuard {
+            gate: self,
+            key,
+            id,
+        };
+        match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(approved)) => Some(approved),
+            _ => None,
+        }
+    }
+
+    /// Whether a run of this user and session waits for the decision on the call
+    pub fn is_waiting(&self, user_id: &str, session_id: &str, call_id: &str) -> bool {
+        self.waiting
+            .lock()
+            .is_ok_and(|waiting| waiting.contains_key(&wait_key(user_id, session_id, call_id)))
+    }
+
+    /// Hands the decision to the waiting call; `false` when no run of this user and session
+    /// waits for it
+    pub fn decide(&self, user_id: &str, session_id: &str, call_id: &str, approved: bool) -> bool {
+        let mut waiting = match self.waiting.lock() {
+            Err(_) => return false,
+            Ok(waiting) => waiting,
+        };
+        match waiting.remove(&wait_key(user_id, session_id, call_id)) {
+            None => false,
+            Some(w) => w.decision.send(approved).is_ok(),
+        }
+    }
+}
+
+/// Runs `inner` only after the user approves the call. The tool is long-running, so ADK lists
+/// its name in `long_running_tool_ids` of the event that makes the call.
+pub struct AdkApprovalTool {
+    pub inner: Arc<dyn Tool>,
+    pub gate: Arc<ApprovalGate>,
+}
+
+impl AdkApprovalTool {
+    pub fn new(inner: Arc<dyn Tool>, gate: Arc<ApprovalGate>) -> Self {
+        Self { inner, gate }
+    }
+}
+
+fn declined(status: &str, message: &str) -> Value {
+    json!({ "status": status, "message": message })
+}
+
+#[async_trait]
+impl Tool for AdkApprovalTool {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn description(&self) -> &str {
+        self.inner.description()
+    }
+
+    fn is_long_running(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, ctx: Arc<dyn ToolContext>, args: Value) -> Result<Value> {
+        // Already `approval_call_id(invocation_id, name)`
+        let decision = self
+            .gate
+            .wait(ctx.user_id(), ctx.session_id(), ctx.function_call_id())
+            .await;
+        match decision {
+            Some(true) => self.inner.execute(ctx, args).await,
+            Some(false) => Ok(declined(
+                "rejected",
+                "The user rejected this call. Do not call the tool again for it.",
+            )),
+            None => Ok(declined(
+                "expired",
+                "The user did not approve this call in time.",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use adk_rust::futures::{StreamExt, stream};
+    use adk_rust::session::{CreateRequest, InMemorySessionService, SessionService};
+    use adk_rust::{LlmRequest, LlmResponse, LlmResponseStream};
+
+    /// Calls `save_note` until a tool response comes back, then answers
+    struct ScriptedLlm;
+
+    #[async_trait]
+    impl Llm for ScriptedLlm {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        async fn generate_content(
+            &self,
+            req: LlmRequest,
+            _stream: bool,
+        ) -> Result<LlmResponseStream> {
+            let answered = req
+                .contents
+                .iter()
+                .flat_map(|c| c.parts.iter())
+                .any(|p| matches!(p, Part::FunctionResponse { .. }));
+            let content = match answered {
+                true => Content::new("model").with_text("Saved"),
+                false => Content {
+                    role: "model".into(),
+                    parts: vec![Part::FunctionCall {
+                        name: "save_note".into(),
+                        args: json!({"text": "hi"}),
+                        id: Some("call_1".into()),
+                    }],
+                },
+            };
+            Ok(Box::pin(stream::iter(vec![Ok(LlmResponse::new(content))])))
+        }
+    }
+
+    // cargo test --package adk_utils check_approval_turn -- --nocapture
+    #[tokio::test]
+    async fn check_approval_turn() {
+        let gate = Arc::new(ApprovalGate::new(Duration::from_secs(5)));
+        let inner = Arc::new(FunctionTool::new(
+            "save_note",
+            "Saves a note",
+            |_ctx, args| async move { Ok(json!({"saved": args["text"]})) },
+        ));
+        let agent = LlmAgentBuilder::new("notes")
+            .model(Arc::new(ScriptedLlm))
+            .tool(Arc::new(AdkApprovalTool::new(inner, gate.clone())))
+            .build()
+            .unwrap();
+        let sessions = Arc::new(InMemorySessionService::new());
+        sessions
+            .create(CreateRequest {
+                app_name: "app".into(),
+                user_id: "u".into(),
+                session_id: Some("s".into()),
+                state: HashMap::new(),
+            })
+            .await
+            .unwrap();
+        let runner = Runner::new(RunnerConfig {
+            app_name: "app".into(),
+            agent: Arc::new(agent),
+            session_service: sessions,
+            artifact_service: None,
+            memory_service: None,
+            run_config: None,
+        })
+        .unwrap();
+        let mut events = runner
+            .run(
+                "u".into(),
+                "s".into(),
+                Content::new("user").with_text("Save hi"),
+            )
+            .await
+            .unwrap();
+
+        let mut responses = vec![];
+        let mut reply = String::new();
+        while let Some(ev) = events.next().await {
+            let ev = ev.unwrap();
+            let parts = match ev.content() {
+                None => continue,
+                Some(content) => content.parts.clone(),
+            };
+            for part in parts {
+                match part {
+                    // The run blocks in the tool until the decision, so it comes from aside
+                    Part::FunctionCall { name, .. } if ev.long_running_tool_ids.contains(&name) => {
+                        let gate = gate.clone();
+                        let call_id = approval_call_id(&ev.invocation_id, &name);
+                        println!("waiting call: {}", &call_id);
+                        tokio::spawn(async move {
+                            while !gate.is_waiting("u", "s", &call_id) {
+                                tokio::time::sleep(Duration::from_millis(10)).await;
+                            }
+                            assert!(gate.decide("u", "s", &call_id, true));
+                        });
+                    }
+                    Part::FunctionResponse {
+                        function_response, ..
+                    } => responses.push(function_response.response),
+                    Part::Text { text } => reply.push_str(&text),
+                    _ => (),
+                }
+            }
+        }
+        assert_eq!(responses, vec![json!({"saved": "hi"})]);
+        assert_eq!(reply, "Saved");
+    }
+}
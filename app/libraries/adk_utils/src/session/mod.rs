@@ -1,2 +1,3 @@
+pub mod approval;
 pub mod postgres;
 pub mod tools;
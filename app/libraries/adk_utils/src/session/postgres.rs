@@ -1,3 +1,4 @@
+use super::approval::{ApprovalGate, approval_call_id};
 use adk_rust::{
     anyhow,
     prelude::*,
@@ -7,9 +8,10 @@ use adk_rust::{
     },
 };
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use serde_json::Value;
 use sqlx::{PgPool, Row};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
 use uuid::Uuid;
 
@@ -98,6 +100,16 @@ impl PgSessionService {
         )
         .execute(&self.pool)
         .await?;
+
+        // Set by users renaming a conversation; the first message stands in until then
+        sqlx::query("ALTER TABLE adk.sessions ADD COLUMN IF NOT EXISTS title TEXT;")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS sessions_user_updated_idx ON adk.sessions (app_name, user_id, updated_at DESC);",
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
@@ -128,6 +140,283 @@ impl PgSessionService {
         }
         merged
     }
+
+    /// One page of the sessions of a user, most recently updated first, and the total count
+    pub async fn list_summaries(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        limit: i64,
+        offset: i64,
+    ) -> adk_core::Result<(Vec<SessionSummary>, i64)> {
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM adk.sessions WHERE app_name=$1 AND user_id=$2",
+        )
+        .bind(app_name)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("query failed: {e}")))?;
+
+        // The first user message names untitled sessions; the last text event is the preview
+        let rows = sqlx::query(
+            r#"
+            SELECT s.session_id, s.title, s.created_at, s.updated_at,
+                first_event.llm_response AS first_response,
+                last_event.llm_response AS last_response
+            FROM adk.sessions s
+            LEFT JOIN LATERAL (
+                SELECT e.llm_response FROM adk.events e
+                WHERE e.session_id = s.session_id AND e.author = 'user'
+                ORDER BY e.ts LIMIT 1
+            ) first_event ON TRUE
+            LEFT JOIN LATERAL (
+                SELECT e.llm_response FROM adk.events e
+                WHERE e.session_id = s.session_id
+                    AND jsonb_path_exists(e.llm_response, '$.content.parts[*].text')
+                ORDER BY e.ts DESC LIMIT 1
+            ) last_event ON TRUE
+            WHERE s.app_name=$1 AND s.user_id=$2
+            ORDER BY s.updated_at DESC, s.session_id
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+        .bind(app_name)
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("query failed: {e}")))?;
+
+        let items = rows
+            .into_iter()
+            .map(|r| {
+                let first: Option<Value> = r.get("first_response");
+                let last: Option<Value> = r.get("last_response");
+                let title: Option<String> = r.get("title");
+                SessionSummary {
+                    session_id: r.get("session_id"),
+                    title: title
+                        .or_else(|| first.map(|v| snippet(&response_text(&v), TITLE_CHARS))),
+                    preview: last.map(|v| snippet(&response_text(&v), PREVIEW_CHARS)),
+                    created_at: r.get("created_at"),
+                    updated_at: r.get("updated_at"),
+                }
+            })
+            .collect();
+        Ok((items, total))
+    }
+
+    async fn has_session(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        session_id: &str,
+    ) -> adk_core::Result<bool> {
+        sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM adk.sessions WHERE app_name=$1 AND user_id=$2 AND session_id=$3)",
+        )
+        .bind(app_name)
+        .bind(user_id)
+        .bind(session_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("query failed: {e}")))
+    }
+
+    /// Events of a session of the user in order; `None` when the user has no such session
+    pub async fn transcript(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        session_id: &str,
+    ) -> adk_core::Result<Option<Vec<TranscriptEvent>>> {
+        if !self.has_session(app_name, user_id, session_id).await? {
+            return Ok(None);
+        }
+        let events = sqlx::query(
+            "SELECT id, author, ts, llm_response FROM adk.events WHERE session_id=$1 ORDER BY ts",
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("events query failed: {e}")))?
+        .into_iter()
+        .map(|r| {
+            let llm_response: Value = r.get("llm_response");
+            TranscriptEvent {
+                id: r.get("id"),
+                author: r.get("author"),
+                timestamp: r.get("ts"),
+                text: response_text(&llm_response),
+                parts: llm_response["content"]["parts"].clone(),
+            }
+        })
+        .collect();
+        Ok(Some(events))
+    }
+
+    /// Tool calls of a session of the user that wait for approval: calls of tools named in
+    /// `long_running_tool_ids` that no tool response answered yet and that a run still waits
+    /// for in `gate`, under their `approval_call_id`. A turn dropped by a restart, timeout or
+    /// cancel never stores a response, so its calls are only left out. `None` when the user
+    /// has no such session.
+    pub async fn pending_approvals(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        session_id: &str,
+        gate: &ApprovalGate,
+    ) -> adk_core::Result<Option<Vec<PendingApproval>>> {
+        if !self.has_session(app_name, user_id, session_id).await? {
+            return Ok(None);
+        }
+        let rows = sqlx::query(
+            "SELECT ts, invocation_id, llm_response, long_running_tool_ids FROM adk.events WHERE session_id=$1 ORDER BY ts",
+        )
+        .bind(session_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("events query failed: {e}")))?;
+
+        let mut pending: Vec<PendingApproval> = vec![];
+        for r in rows {
+            let invocation_id: String = r.get("invocation_id");
+            let llm_response: Value = r.get("llm_response");
+            let tool_names: Value = r.get("long_running_tool_ids");
+            let tool_names: Vec<String> = serde_json::from_value(tool_names).unwrap_or_default();
+            let parts = match serde_json::from_value::<adk_core::LlmResponse>(llm_response)
+                .ok()
+                .and_then(|res| res.content)
+            {
+                None => continue,
+                Some(content) => content.parts,
+            };
+            for part in parts {
+                match part {
+                    Part::FunctionCall { name, args, .. } if tool_names.contains(&name) => pending
+                        .push(PendingApproval {
+                            call_id: approval_call_id(&invocation_id, &name),
+                            name,
+                            args,
+                            requested_at: r.get("ts"),
+                        }),
+                    // Calls of one invocation run in order, so a response answers the first
+                    // open call of its tool
+                    Part::FunctionResponse {
+                        function_response, ..
+                    } => {
+                        let call_id = approval_call_id(&invocation_id, &function_response.name);
+                        if let Some(i) = pending.iter().position(|p| p.call_id == call_id) {
+                            pending.remove(i);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        // Only the first open call of a tool waits; the ones after it run once it is done
+        let mut listed = HashSet::new();
+        pending.retain(|p| {
+            gate.is_waiting(user_id, session_id, &p.call_id) && listed.insert(p.call_id.clone())
+        });
+        Ok(Some(pending))
+    }
+
+    /// Deletes a session of the user with its events; `false` when the user has no such session
+    pub async fn delete_session(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        session_id: &str,
+    ) -> adk_core::Result<bool> {
+        let res = sqlx::query(
+            "DELETE FROM adk.sessions WHERE app_name=$1 AND user_id=$2 AND session_id=$3",
+        )
+        .bind(app_name)
+        .bind(user_id)
+        .bind(session_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("delete failed: {e}")))?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Sets the title of a session of the user; `false` when the user has no such session
+    pub async fn rename(
+        &self,
+        app_name: &str,
+        user_id: &str,
+        session_id: &str,
+        title: &str,
+    ) -> adk_core::Result<bool> {
+        let res = sqlx::query(
+            "UPDATE adk.sessions SET title=$4 WHERE app_name=$1 AND user_id=$2 AND session_id=$3",
+        )
+        .bind(app_name)
+        .bind(user_id)
+        .bind(session_id)
+        .bind(title)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| adk_core::AdkError::Session(format!("update failed: {e}")))?;
+        Ok(res.rows_affected() > 0)
+    }
+}
+
+const TITLE_CHARS: usize = 80;
+const PREVIEW_CHARS: usize = 160;
+
+/// A conversation in the list of a user's sessions
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub title: Option<String>,   // None until the first message
+    pub preview: Option<String>, // Start of the latest text
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An event of a conversation as shown to its user
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscriptEvent {
+    pub id: String,
+    pub author: String, // `user` or the agent name
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+    pub parts: Value, // All content parts, including tool calls and results
+}
+
+/// A tool call waiting for its user to approve or reject it
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingApproval {
+    pub call_id: String,
+    pub name: String,
+    pub args: Value,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Text parts of a stored `llm_response`
+fn response_text(llm_response: &Value) -> String {
+    llm_response["content"]["parts"]
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p["text"].as_str())
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+/// `text` on one line, cut after `chars` characters
+fn snippet(text: &str, chars: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(chars) {
+        None => text,
+        Some((end, _)) => format!("{}…", &text[..end]),
+    }
 }
 
 impl SessionService for PgSessionService {
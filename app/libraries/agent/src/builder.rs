@@ -1,13 +1,18 @@
-use adk_core::{ReadonlyContext, Toolset};
+use adk_core::{BeforeModelResult, GenerateContentConfig, ReadonlyContext, Toolset};
 use adk_model::{OpenAIClient, OpenAIConfig};
 use adk_runner::Runner;
-use adk_rust::prelude::{GoogleSearchTool, LlmAgentBuilder, McpToolset, RunnerConfig};
+use adk_rust::prelude::{GoogleSearchTool, LlmAgentBuilder, McpToolset, RunnerConfig, Tool};
 use app_adk_utils::{
     content::SimpleContext,
-    session::{postgres::PgSessionService, tools::AdkInjectSessionTool},
+    session::{
+        approval::{AdkApprovalTool, ApprovalGate},
+        postgres::PgSessionService,
+        tools::AdkInjectSessionTool,
+    },
 };
-use app_config::AppConfig;
+use app_config::{AgentConfig, AppConfig};
 use app_error::AppError;
+use app_state::{AgentEntry, AgentRegistry};
 use rmcp09::{
     ServiceExt,
     transport::streamable_http_client::{
@@ -16,15 +21,39 @@ use rmcp09::{
 };
 use sqlx::postgres::PgPoolOptions;
 use std::{env, sync::Arc};
+use tracing::*;
 
 // This is default sqlx parameter
 // It can be replace with some param from config or other env
 // if we want to use seperate postgresql database for agent session
-const DATABASE_URL: &'static str = "DATABASE_URL";
+const DATABASE_URL: &str = "DATABASE_URL";
+
+/// Allows every MCP tool in `AgentConfig::tools`
+const ALL_TOOLS: &str = "*";
+/// Adds Google Search in `AgentConfig::tools`
+const GOOGLE_SEARCH: &str = "google_search";
 
+/// The configured agents, or the single agent described by `agent_*` with every tool
+fn agent_configs(config: &AppConfig) -> Vec<AgentConfig> {
+    if !config.agents.is_empty() {
+        return config.agents.clone();
+    }
+    vec![AgentConfig {
+        name: config.agent_app_name.clone(),
+        description: config.agent_description.clone(),
+        instruction: config.agent_instruction.clone(),
+        model: None,
+        temperature: None,
+        tools: vec![ALL_TOOLS.to_owned(), GOOGLE_SEARCH.to_owned()],
+    }]
+}
+
+/// Builds the agents; with `approvals` the tools of `agent_approval` wait for the user's
+/// consent, without (the console) they run directly
 pub async fn agent_builder(
     config: &AppConfig,
-) -> Result<(Arc<Runner>, Arc<PgSessionService>), AppError> {
+    approvals: Option<Arc<ApprovalGate>>,
+) -> Result<(AgentRegistry, Arc<PgSessionService>), AppError> {
     // Agent makes its own postgresql connection
     let database_url = env::var(DATABASE_URL)?;
     let pg_connection = config.pg_connection;
@@ -37,16 +66,7 @@ pub async fn agent_builder(
     let agent_sessions = Arc::new(PgSessionService::new(pg).await?);
     // This deploy agent session schema if not exists
     agent_sessions.migrate().await?;
-    // LLM Load
-    let model_config = OpenAIConfig {
-        api_key: config.llm_token.clone(),
-        model: config.llm_model.clone(),
-        base_url: Some(config.llm_base_url.clone()),
-        project_id: None,
-        organization_id: None,
-    };
-    let llm_model = OpenAIClient::new(model_config).unwrap();
-    // MCP Tool Config
+    // MCP Tool Config; the tools are loaded once and shared by the agents
     let mcp_cfg = StreamableHttpClientTransportConfig::with_uri(config.mcp_base_url.clone())
         .auth_header(&config.mcp_token.clone());
     let transport = StreamableHttpClientTransport::from_config(mcp_cfg);
@@ -58,28 +78,93 @@ pub async fn agent_builder(
         ..Default::default()
     });
     let mcp_tools = toolset.tools(ctx).await?;
-    // Agent Builder
-    let mut builder = LlmAgentBuilder::new(config.agent_app_name.clone())
-        .description(config.agent_description.clone())
-        .instruction(config.agent_instruction.clone())
-        .model(Arc::new(llm_model));
-    for t in mcp_tools {
-        builder = builder.tool(Arc::new(AdkInjectSessionTool::new(t)));
+
+    let mut entries = vec![];
+    for agent_config in agent_configs(config) {
+        // LLM Load
+        let model_config = OpenAIConfig {
+            api_key: config.llm_token.clone(),
+            model: agent_config
+                .model
+                .clone()
+                .unwrap_or_else(|| config.llm_model.clone()),
+            base_url: Some(config.llm_base_url.clone()),
+            project_id: None,
+            organization_id: None,
+        };
+        let llm_model = OpenAIClient::new(model_config).unwrap();
+        // Agent Builder
+        let mut builder = LlmAgentBuilder::new(agent_config.name.clone())
+            .description(agent_config.description.clone())
+            .instruction(agent_config.instruction.clone())
+            .model(Arc::new(llm_model));
+        // The builder has no generation settings, so the temperature is set on every request
+        if let Some(temperature) = agent_config.temperature {
+            builder = builder.before_model_callback(Box::new(move |_ctx, mut request| {
+                Box::pin(async move {
+                    let config = request.config.get_or_insert(GenerateContentConfig {
+                        temperature: None,
+                        top_p: None,
+                        top_k: None,
+                        max_output_tokens: None,
+                        response_schema: None,
+                    });
+                    config.temperature = Some(temperature);
+                    Ok(BeforeModelResult::Continue(request))
+                })
+            }));
+        }
+        let allowed = |name: &str| {
+            agent_config
+                .tools
+                .iter()
+                .any(|t| t == ALL_TOOLS || t == name)
+        };
+        for name in agent_config.tools.iter() {
+            if name != ALL_TOOLS
+                && name != GOOGLE_SEARCH
+                && !mcp_tools.iter().any(|t| t.name() == name)
+            {
+                warn!("Agent {} names unknown tool {}", &agent_config.name, name);
+            }
+        }
+        for t in mcp_tools.iter().filter(|t| allowed(t.name())) {
+            let tool: Arc<dyn Tool> = Arc::new(AdkInjectSessionTool::new(t.clone()));
+            let needs_approval = config.agent_approval.tools.iter().any(|n| n == t.name());
+            builder = match &approvals {
+                Some(gate) if needs_approval => {
+                    builder.tool(Arc::new(AdkApprovalTool::new(tool, gate.clone())))
+                }
+                _ => builder.tool(tool),
+            };
+        }
+        // Add Google Search Tools
+        // Note: compatible with Gemini 2 models
+        // Need export GOOGLE_API_KEY="YOUR_GOOGLE_KEY"
+        if agent_config.tools.iter().any(|t| t == GOOGLE_SEARCH) {
+            builder = builder.tool(Arc::new(GoogleSearchTool::new()));
+        }
+        let agent = Arc::new(builder.build()?);
+        // Agent Runner
+        // Agents share the sessions of the app, so a conversation can move between them
+        // let artifacts = Arc::new(InMemoryArtifactService::new());
+        let agent_runner = Arc::new(Runner::new(RunnerConfig {
+            app_name: config.agent_app_name.to_string(),
+            agent,
+            session_service: agent_sessions.clone(),
+            artifact_service: None,
+            memory_service: None,
+            run_config: None, // Uses default SSE streaming
+        })?);
+        info!("Agent {} is ready", &agent_config.name);
+        entries.push(AgentEntry {
+            name: agent_config.name,
+            description: agent_config.description,
+            runner: agent_runner,
+        });
+    }
+    match AgentRegistry::new(entries) {
+        None => Err(AppError::internal("No agent is configured")),
+        Some(registry) => Ok((registry, agent_sessions)),
     }
-    // Add Google Search Tools
-    // Note: compatible with Gemini 2 models
-    // Need export GOOGLE_API_KEY="YOUR_GOOGLE_KEY"
-    builder = builder.tool(Arc::new(GoogleSearchTool::new()));
-    let agent = Arc::new(builder.build()?);
-    // Agent Runner
-    // let artifacts = Arc::new(InMemoryArtifactService::new());
-    let agnet_runner = Arc::new(Runner::new(RunnerConfig {
-        app_name: config.agent_app_name.to_string(),
-        agent: agent,
-        session_service: agent_sessions.clone(),
-        artifact_service: None,
-        memory_service: None,
-        run_config: None, // Uses default SSE streaming
-    })?);
-    Ok((agnet_runner, agent_sessions))
 }
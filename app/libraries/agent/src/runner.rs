@@ -1,6 +1,12 @@
 use adk_core::{AdkError, EventStream};
 use adk_rust::prelude::{Event, Part};
+use app_adk_utils::session::approval::approval_call_id;
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::*;
 
 pub async fn stream_response_parser(
     stream: &mut EventStream,
@@ -28,3 +34,243 @@ pub async fn stream_response_parser(
     }
     Ok(buf)
 }
+
+/// What a streaming client is told about a run, one frame per step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentFrame {
+    /// Text to append to the reply
+    Text {
+        text: String,
+    },
+    /// The model called a tool
+    ToolCall {
+        id: Option<String>,
+        name: String,
+        args: Value,
+    },
+    /// The call waits for the user: `POST /auth/agent/sessions/{id}/approvals/{call_id}`
+    /// approves or rejects it and the run goes on
+    ApprovalRequired {
+        session_id: String,
+        id: String,
+        name: String,
+        args: Value,
+    },
+    /// A tool answered
+    ToolResult {
+        id: Option<String>,
+        name: String,
+        response: Value,
+    },
+    /// The run is over; `content` is the whole reply
+    Final {
+        session_id: String,
+        content: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl AgentFrame {
+    /// Name of the frame type, e.g. the SSE `event` field
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AgentFrame::Text { .. } => "text",
+            AgentFrame::ToolCall { .. } => "tool_call",
+            AgentFrame::ApprovalRequired { .. } => "approval_required",
+            AgentFrame::ToolResult { .. } => "tool_result",
+            AgentFrame::Final { .. } => "final",
+            AgentFrame::Error { .. } => "error",
+        }
+    }
+}
+
+/// Turns the events of a run into frames and collects the reply like `stream_response_parser`.
+/// When the model streams partial events, the aggregated event that closes them repeats their
+/// text, which is then not sent again.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    session_id: String,
+    content: String,
+    streamed: bool, // Text of the current turn already came as partial events
+}
+
+impl FrameParser {
+    pub fn new(session_id: &str) -> Self {
+        Self {
+            session_id: session_id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn frames(&mut self, ev: &Event) -> Vec<AgentFrame> {
+        let mut frames = vec![];
+        let partial = ev.llm_response.partial;
+        let parts = match ev.content() {
+            None => return frames,
+            Some(content) => &content.parts,
+        };
+        for part in parts.iter() {
+            match part {
+                Part::Text { text } => {
+                    if text.is_empty() || (!partial && self.streamed) {
+                        continue;
+                    }
+                    self.content.push_str(text);
+                    frames.push(AgentFrame::Text { text: text.clone() });
+                }
+                Part::FunctionCall { name, args, id } => {
+                    frames.push(AgentFrame::ToolCall {
+                        id: id.clone(),
+                        name: name.clone(),
+                        args: args.clone(),
+                    });
+                    // Calls of long-running tools wait for approval; ADK lists them by name
+                    if ev.long_running_tool_ids.contains(name) {
+                        frames.push(AgentFrame::ApprovalRequired {
+                            session_id: self.session_id.clone(),
+                            id: approval_call_id(&ev.invocation_id, name),
+                            name: name.clone(),
+                            args: args.clone(),
+                        });
+                    }
+                }
+                Part::FunctionResponse {
+                    function_response,
+                    id,
+                } => frames.push(AgentFrame::ToolResult {
+                    id: id.clone(),
+                    name: function_response.name.clone(),
+                    response: function_response.response.clone(),
+                }),
+                _ => (),
+            }
+        }
+        self.streamed = partial;
+        frames
+    }
+
+    /// The reply so far
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+/// Drives a run in the background and sends its frames, wrapped by `wrap`, to `tx`, ending
+/// with `final` or `error`. The run stops when `cancel` fires or the receiver is dropped;
+/// dropping the event stream stops it also in the middle of a tool call.
+pub fn spawn_run<T, F>(
+    mut stream: EventStream,
+    session_id: String,
+    tx: mpsc::Sender<T>,
+    wrap: F,
+    cancel: CancellationToken,
+) -> JoinHandle<()>
+where
+    T: Send + 'static,
+    F: Fn(AgentFrame) -> T + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut parser = FrameParser::new(&session_id);
+        loop {
+            let ev = tokio::select! {
+                _ = cancel.cancelled() => {
+                    info!("Run of agent session {} cancelled", &session_id);
+                    return;
+                }
+                _ = tx.closed() => {
+                    info!("Client of agent session {} disconnected; run cancelled", &session_id);
+                    return;
+                }
+                ev = stream.next() => ev,
+            };
+            let frames = match ev {
+                None => break,
+                Some(Ok(ev)) => parser.frames(&ev),
+                Some(Err(e)) => {
+                    error!("Agent run of session {} failed: {}", &session_id, &e);
+                    let message = e.to_string();
+                    let _ = tx.send(wrap(AgentFrame::Error { message })).await;
+                    return;
+                }
+            };
+            for frame in frames {
+                if tx.send(wrap(frame)).await.is_err() {
+                    return;
+                }
+            }
+        }
+        let content = parser.content().to_owned();
+        let _ = tx
+            .send(wrap(AgentFrame::Final {
+                session_id,
+                content,
+            }))
+            .await;
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package agent check_agent_frame -- --nocapture
+    #[test]
+    fn check_agent_frame() {
+        let frame = AgentFrame::ToolCall {
+            id: Some("call_1".into()),
+            name: "sum".into(),
+            args: serde_json::json!({"a": 1, "b": 2}),
+        };
+        let json = serde_json::to_value(&frame).unwrap();
+        println!("{}", &json);
+        assert_eq!(json["type"], frame.kind());
+        assert_eq!(json["name"], "sum");
+
+        let frame = AgentFrame::ApprovalRequired {
+            session_id: "s".into(),
+            id: "call_2".into(),
+            name: "add_content_knowledge_based".into(),
+            args: serde_json::json!({"content": "x"}),
+        };
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["type"], "approval_required");
+        assert_eq!(json["id"], "call_2");
+
+        let frame = AgentFrame::Final {
+            session_id: "s".into(),
+            content: "done".into(),
+        };
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(serde_json::from_str::<AgentFrame>(&json).unwrap(), frame);
+    }
+
+    // cargo test --package agent check_frame_parser_approval -- --nocapture
+    #[test]
+    fn check_frame_parser_approval() {
+        let mut ev = Event::new("inv_1");
+        ev.llm_response.content = Some(adk_rust::prelude::Content {
+            role: "model".into(),
+            parts: vec![Part::FunctionCall {
+                name: "add_content_knowledge_based".into(),
+                args: serde_json::json!({"content": "x"}),
+                id: Some("call_1".into()),
+            }],
+        });
+        ev.long_running_tool_ids = vec!["add_content_knowledge_based".into()];
+
+        let frames = FrameParser::new("s").frames(&ev);
+        println!("{:?}", &frames);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[1],
+            AgentFrame::ApprovalRequired {
+                session_id: "s".into(),
+                id: approval_call_id("inv_1", "add_content_knowledge_based"),
+                name: "add_content_knowledge_based".into(),
+                args: serde_json::json!({"content": "x"}),
+            }
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use adk_runner::Runner;
-use adk_rust::session::SessionService;
+use app_adk_utils::session::{approval::ApprovalGate, postgres::PgSessionService};
+use app_audit::AuditLog;
+use app_auth::password_policy::PasswordPolicy;
 use app_config::AppConfig;
+use app_cryptography::{hash::Hasher, jwt::JwtKeySet};
+use app_mailer::MailSender;
 use deadpool_redis::Pool as RedisPool;
 use sqlx::Pool as PostgresPool;
 use sqlx::postgres::Postgres;
@@ -11,6 +15,43 @@ pub struct AppState {
     pub redis: RedisPool,
     pub config: AppConfig,
     pub pg: PostgresPool<Postgres>,
-    pub agent_runner: Option<Arc<Runner>>,
-    pub agent_session: Option<Arc<dyn SessionService>>,
+    pub jwt_keys: Arc<JwtKeySet>,
+    pub mailer: Arc<dyn MailSender>,
+    pub hasher: Arc<Hasher>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub audit: AuditLog,
+    pub agents: Option<Arc<AgentRegistry>>,
+    pub agent_session: Option<Arc<PgSessionService>>,
+    pub agent_approvals: Option<Arc<ApprovalGate>>,
+}
+
+/// A configured agent and the runner driving it
+pub struct AgentEntry {
+    pub name: String,
+    pub description: String,
+    pub runner: Arc<Runner>,
+}
+
+/// Runners of the configured agents; the first one is the default
+pub struct AgentRegistry {
+    entries: Vec<AgentEntry>,
+}
+
+impl AgentRegistry {
+    /// `None` without any agent
+    pub fn new(entries: Vec<AgentEntry>) -> Option<Self> {
+        (!entries.is_empty()).then_some(Self { entries })
+    }
+
+    /// The agent called `name`, or the default one when no name is given
+    pub fn get(&self, name: Option<&str>) -> Option<&AgentEntry> {
+        match name {
+            None => self.entries.first(),
+            Some(name) => self.entries.iter().find(|e| e.name == name),
+        }
+    }
+
+    pub fn entries(&self) -> &[AgentEntry] {
+        &self.entries
+    }
 }
@@ -0,0 +1,243 @@
+//! Security audit log (`audit.events`).
+//!
+//! Handlers hand events to [`AuditLog::record`], which never waits: events go through a
+//! bounded channel to a background task that inserts them in batches of `batch_size`, or
+//! every `flush_interval_ms` when fewer are waiting. When the channel is full the event is
+//! dropped and logged, so a slow database cannot slow requests down.
+
+use app_config::AuditConfig;
+use app_schema::audit::AuditEvent;
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender, error::TrySendError};
+use tracing::*;
+use uuid::Uuid;
+
+/// Names of audited actions
+pub mod action {
+    pub const LOGIN: &str = "login";
+    pub const LOGIN_MFA: &str = "login.mfa";
+    pub const LOGOUT: &str = "logout";
+    pub const TOKEN_REFRESH: &str = "token.refresh";
+    pub const ACCESS: &str = "access";
+    pub const OAUTH_CALLBACK: &str = "oauth.callback";
+    pub const PASSWORD_CHANGE: &str = "password.change";
+    pub const PASSWORD_FORGOT: &str = "password.forgot";
+    pub const PASSWORD_RESET: &str = "password.reset";
+    pub const USER_CREATE: &str = "user.create";
+    pub const USER_ROLES: &str = "user.roles";
+    pub const USER_UPDATE: &str = "user.update";
+    pub const USER_DISABLE: &str = "user.disable";
+    pub const USER_ENABLE: &str = "user.enable";
+    pub const USER_DELETE: &str = "user.delete";
+    pub const USER_PASSWORD: &str = "user.password";
+    pub const LOGIN_UNLOCK: &str = "login.unlock";
+    pub const EMAIL_VERIFY: &str = "email.verify";
+    pub const EMAIL_VERIFY_SEND: &str = "email.verify_send";
+    pub const MCP_CLIENT_CREATE: &str = "mcp_client.create";
+    pub const MCP_CLIENT_UPDATE: &str = "mcp_client.update";
+    pub const MCP_CLIENT_DELETE: &str = "mcp_client.delete";
+    pub const OAUTH2_REGISTER: &str = "oauth2.register";
+    pub const OAUTH2_AUTHORIZE: &str = "oauth2.authorize";
+    pub const OAUTH2_TOKEN: &str = "oauth2.token";
+    pub const AGENT_TOOL_APPROVAL: &str = "agent.tool_approval";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Success,
+    /// The caller failed to authenticate or the action failed
+    Failure,
+    /// The caller was refused by a permission, throttle or revocation
+    Denied,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+            Outcome::Denied => "denied",
+        }
+    }
+}
+
+/// An event waiting to be written
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub occurred_at: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: Outcome,
+    pub details: Map<String, Value>,
+}
+
+impl AuditEntry {
+    pub fn new(action: &str, outcome: Outcome) -> Self {
+        Self {
+            occurred_at: Utc::now(),
+            actor: None,
+            actor_id: None,
+            action: action.to_owned(),
+            target: None,
+            ip: None,
+            user_agent: None,
+            outcome,
+            details: Map::new(),
+        }
+    }
+
+    pub fn actor(mut self, email: &str) -> Self {
+        self.actor = Some(email.to_owned());
+        self
+    }
+
+    /// Ignored when `id` is not a UUID (e.g. empty)
+    pub fn actor_id(mut self, id: &str) -> Self {
+        self.actor_id = Uuid::parse_str(id).ok();
+        self
+    }
+
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_owned());
+        self
+    }
+
+    pub fn client(mut self, ip: &str, user_agent: &str) -> Self {
+        self.ip = Some(ip.to_owned());
+        self.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_owned(), value.into());
+        self
+    }
+}
+
+/// Handle of the background writer; cheap to clone
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    tx: Sender<AuditEntry>,
+}
+
+impl AuditLog {
+    /// Starts the writer task on the current Tokio runtime
+    pub fn spawn(pg: Pool<Postgres>, config: &AuditConfig) -> Self {
+        let (tx, rx) = mpsc::channel(config.buffer_size.max(1));
+        tokio::spawn(run_writer(
+            pg,
+            rx,
+            config.batch_size.max(1),
+            Duration::from_millis(config.flush_interval_ms.max(1)),
+        ));
+        Self { tx }
+    }
+
+    /// Queues an event without waiting
+    pub fn record(&self, entry: AuditEntry) {
+        match self.tx.try_send(entry) {
+            Ok(()) => (),
+            Err(TrySendError::Full(entry)) => warn!(
+                "Audit buffer is full, dropping {} event of {:?}",
+                &entry.action, &entry.actor
+            ),
+            Err(TrySendError::Closed(entry)) => error!(
+                "Audit writer has stopped, dropping {} event of {:?}",
+                &entry.action, &entry.actor
+            ),
+        }
+    }
+}
+
+async fn run_writer(
+    pg: Pool<Postgres>,
+    mut rx: Receiver<AuditEntry>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch: Vec<AuditEntry> = Vec::with_capacity(batch_size);
+    let mut ticker = tokio::time::interval(flush_interval);
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Some(entry) => {
+                    batch.push(entry);
+                    if batch.len() >= batch_size {
+                        write_batch(&pg, &mut batch).await;
+                    }
+                }
+                None => {
+                    // Every handle is gone; write what is left and stop
+                    write_batch(&pg, &mut batch).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => write_batch(&pg, &mut batch).await,
+        }
+    }
+}
+
+async fn write_batch(pg: &Pool<Postgres>, batch: &mut Vec<AuditEntry>) {
+    if batch.is_empty() {
+        return;
+    }
+    let entries = std::mem::take(batch);
+    let res = sqlx::query(AuditEvent::insert_batch_query())
+        .bind(entries.iter().map(|e| e.occurred_at).collect::<Vec<_>>())
+        .bind(entries.iter().map(|e| e.actor.clone()).collect::<Vec<_>>())
+        .bind(entries.iter().map(|e| e.actor_id).collect::<Vec<_>>())
+        .bind(entries.iter().map(|e| e.action.clone()).collect::<Vec<_>>())
+        .bind(entries.iter().map(|e| e.target.clone()).collect::<Vec<_>>())
+        .bind(entries.iter().map(|e| e.ip.clone()).collect::<Vec<_>>())
+        .bind(
+            entries
+                .iter()
+                .map(|e| e.user_agent.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            entries
+                .iter()
+                .map(|e| e.outcome.as_str())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            entries
+                .iter()
+                .map(|e| Value::Object(e.details.clone()).to_string())
+                .collect::<Vec<_>>(),
+        )
+        .execute(pg)
+        .await;
+    if let Err(err) = res {
+        error!("Cannot write {} audit events: {}", entries.len(), err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package audit check_audit_entry -- --nocapture
+    #[test]
+    fn check_audit_entry() {
+        let entry = AuditEntry::new(action::LOGIN, Outcome::Failure)
+            .actor("test@unit")
+            .actor_id("not a uuid")
+            .client("127.0.0.1", "unit")
+            .detail("reason", "wrong_password");
+        println!("{:?}", &entry);
+        assert_eq!(entry.actor_id, None);
+        assert_eq!(entry.outcome.as_str(), "failure");
+        assert_eq!(
+            Value::Object(entry.details).to_string(),
+            r#"{"reason":"wrong_password"}"#
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! API keys for machine clients (`auth.api_keys`).
+//!
+//! Keys look like `ak_<prefix>_<secret>`. The prefix is stored in clear to find the row and
+//! to show the key in listings; the whole key is only stored as a SHA-256 hash, which is
+//! enough for 256-bit random secrets. A key acts as its owner, limited to its scopes: the
+//! effective permissions are the scopes the owner still holds at request time.
+
+use crate::rbac::load_access;
+use app_cryptography::{base64::base64_encode_from_u8, jwt::Claims};
+use app_error::AppError;
+use app_schema::auth::{api_keys::ApiKey, users::User};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+/// `source` of identities authenticated with an API key
+pub const API_KEY_SOURCE: &str = "api_key";
+
+const KEY_PREFIX: &str = "ak_";
+
+fn unauthorized() -> AppError {
+    AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0)
+}
+
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-256 of the whole key, hex encoded
+pub fn hash_api_key(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// Returns `(key, prefix)` of a new random key
+pub fn generate_api_key() -> (String, String) {
+    let prefix = to_hex(&random_bytes(6));
+    let secret = base64_encode_from_u8(&random_bytes(32));
+    (format!("{}{}_{}", KEY_PREFIX, &prefix, secret), prefix)
+}
+
+fn key_prefix(key: &str) -> Option<&str> {
+    // The secret may contain `_`, the hex prefix never does
+    key.strip_prefix(KEY_PREFIX)?
+        .split_once('_')
+        .map(|(prefix, _)| prefix)
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Stores a new key for `user_id` and returns it with the clear key, which is never shown again
+pub async fn create_api_key(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+    name: &str,
+    scopes: &[String],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(String, ApiKey), AppError> {
+    let (key, prefix) = generate_api_key();
+    let mut scopes = scopes.to_vec();
+    scopes.sort();
+    scopes.dedup();
+    let api_key = sqlx::query_as::<_, ApiKey>(ApiKey::insert_query())
+        .bind(user_id)
+        .bind(name)
+        .bind(&prefix)
+        .bind(hash_api_key(&key))
+        .bind(&scopes)
+        .bind(expires_at)
+        .fetch_one(pg)
+        .await?;
+    Ok((key, api_key))
+}
+
+/// Resolves an `Authorization: ApiKey <key>` header to the identity of the key owner
+pub async fn authenticate_api_key(pg: &Pool<Postgres>, key: &str) -> Result<Claims, AppError> {
+    let prefix = match key_prefix(key) {
+        None => return Err(unauthorized()),
+        Some(prefix) => prefix,
+    };
+    let api_key =
+        match sqlx::query_as::<_, ApiKey>(&format!("{} WHERE prefix = $1", ApiKey::select_query()))
+            .bind(prefix)
+            .fetch_optional(pg)
+            .await?
+        {
+            None => {
+                info!("Unknown API key prefix {}", prefix);
+                return Err(unauthorized());
+            }
+            Some(api_key) => api_key,
+        };
+    if !constant_time_eq(hash_api_key(key).as_bytes(), api_key.key_hash.as_bytes()) {
+        warn!("Invalid secret for API key {}", &api_key.prefix);
+        return Err(unauthorized());
+    }
+    if let Some(expires_at) = api_key.expires_at
+        && expires_at <= Utc::now()
+    {
+        info!("API key {} has expired", &api_key.prefix);
+        return Err(unauthorized());
+    }
+    let user = match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+        .bind(api_key.user_id)
+        .fetch_optional(pg)
+        .await?
+    {
+        None => return Err(unauthorized()),
+        Some(user) if !user.is_active() => {
+            info!("API key {} belongs to inactive user", &api_key.prefix);
+            return Err(unauthorized());
+        }
+        Some(user) => user,
+    };
+    let id = format!("{:?}", &user.id);
+    let access = load_access(pg, &id).await?;
+    // Scopes never grant more than the owner currently holds
+    let permissions = api_key
+        .scopes
+        .iter()
+        .filter(|s| access.permissions.contains(s))
+        .cloned()
+        .collect();
+    sqlx::query(ApiKey::touch_query())
+        .bind(api_key.id)
+        .execute(pg)
+        .await?;
+    Ok(Claims {
+        exp: api_key
+            .expires_at
+            .map(|e| e.timestamp())
+            .unwrap_or(i64::MAX),
+        iat: api_key.created_at.timestamp(),
+        id,
+        name: user.name,
+        email: user.email,
+        // API keys are not bound to a login session
+        session: 0,
+        source: API_KEY_SOURCE.to_owned(),
+        roles: access.roles,
+        permissions,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_api_key_format -- --nocapture
+    #[test]
+    fn check_api_key_format() {
+        let (key, prefix) = generate_api_key();
+        println!("{} {}", &key, &prefix);
+        assert_eq!(key_prefix(&key), Some(prefix.as_str()));
+        assert_eq!(hash_api_key(&key).len(), 64);
+        assert_eq!(key_prefix("Bearer abc"), None);
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+    }
+}
@@ -0,0 +1,320 @@
+//! Browser session mode (`session_cookie` in the config).
+//!
+//! Instead of handing the token pair to page scripts, the tokens are set as `HttpOnly`
+//! cookies: `access_token` for every path and `refresh_token` for `/auth/refresh` only.
+//! Requests authenticated by cookie must echo the readable `csrf_token` cookie in the
+//! `X-CSRF-Token` header for every unsafe method (double submit). The CSRF token is signed
+//! for the session, so a cookie planted by another site or subdomain is not accepted either.
+//! Bearer tokens are unaffected. Signatures use keys derived from `jwt_refresh_key` with
+//! HKDF, one per purpose, so they never share a key with the refresh tokens.
+//!
+//! The `oauth_state` cookie binds an OAuth attempt to the browser that started it, in both
+//! modes: the callback is only accepted with the cookie signed for its `state`, so a
+//! callback URL sent to somebody else cannot sign them in (login CSRF).
+
+use app_config::AppConfig;
+use app_cryptography::{
+    base64::base64_encode_from_u8,
+    jwt::{Algorithm, Claims, JwtKeySet, validate_token},
+};
+use app_dto::auth::login::PostLoginOutput;
+use app_error::AppError;
+use axum::http::{
+    HeaderMap, HeaderValue, Method, StatusCode,
+    header::{COOKIE, SET_COOKIE},
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use tracing::*;
+
+pub const ACCESS_COOKIE: &str = "access_token";
+pub const REFRESH_COOKIE: &str = "refresh_token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+const REFRESH_PATH: &str = "/auth/refresh";
+pub const OAUTH_STATE_COOKIE: &str = "oauth_state";
+const OAUTH_STATE_PATH: &str = "/oauth";
+/// HKDF label of the CSRF signing key
+pub const CSRF_KEY_LABEL: &str = "csrf";
+/// HKDF label of the OAuth state cookie key
+pub const OAUTH_STATE_KEY_LABEL: &str = "oauth_state";
+
+/// 32-byte key for `label`, derived from `jwt_refresh_key` (HKDF-SHA256)
+pub fn derived_key(config: &AppConfig, label: &str) -> Vec<u8> {
+    let hkdf = Hkdf::<Sha256>::new(None, config.jwt_refresh_key.as_bytes());
+    let mut key = vec![0u8; 32];
+    hkdf.expand(label.as_bytes(), &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+    key
+}
+
+/// Key signing the CSRF tokens
+pub fn csrf_key(config: &AppConfig) -> Vec<u8> {
+    derived_key(config, CSRF_KEY_LABEL)
+}
+
+/// Value of the cookie `name` sent by the browser
+pub fn get_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+fn csrf_mac(key: &[u8], session: u64, nonce: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(format!("csrf:{}:{}", session, nonce).as_bytes());
+    mac
+}
+
+/// New CSRF token of a session: `<nonce>.<signature>`
+pub fn csrf_token(key: &[u8], session: u64) -> String {
+    let mut nonce = vec![0u8; 16];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let nonce = base64_encode_from_u8(&nonce);
+    let signature = csrf_mac(key, session, &nonce).finalize().into_bytes();
+    format!("{}.{}", nonce, base64_encode_from_u8(&signature.to_vec()))
+}
+
+/// Whether `token` was issued for `session`
+pub fn csrf_token_valid(key: &[u8], session: u64, token: &str) -> bool {
+    let (nonce, signature) = match token.split_once('.') {
+        None => return false,
+        Some(parts) => parts,
+    };
+    match base64_url::decode(signature.as_bytes()) {
+        Err(_) => false,
+        Ok(signature) => csrf_mac(key, session, nonce)
+            .verify_slice(&signature)
+            .is_ok(),
+    }
+}
+
+/// GET, HEAD and OPTIONS do not change state and need no CSRF token
+pub fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Requires the `X-CSRF-Token` header to match the `csrf_token` cookie and the session
+pub fn check_csrf(config: &AppConfig, headers: &HeaderMap, session: u64) -> Result<(), AppError> {
+    let header = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+    let cookie = get_cookie(headers, CSRF_COOKIE);
+    match (header, cookie) {
+        (Some(header), Some(cookie))
+            if header == cookie && csrf_token_valid(&csrf_key(config), session, header) =>
+        {
+            Ok(())
+        }
+        _ => {
+            debug!("CSRF token is missing or invalid for session {}", session);
+            Err(AppError::new(
+                "Missing or invalid CSRF token!",
+                StatusCode::FORBIDDEN,
+                4,
+            ))
+        }
+    }
+}
+
+/// Refresh token of a cookie-mode refresh request, which must carry the CSRF token of its
+/// session
+pub fn cookie_refresh_token(config: &AppConfig, headers: &HeaderMap) -> Result<String, AppError> {
+    let unauthorized = || AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0);
+    let token = match get_cookie(headers, REFRESH_COOKIE) {
+        None => return Err(unauthorized()),
+        Some(token) => token,
+    };
+    let session = match validate_token::<Claims>(Algorithm::HS256, &config.jwt_refresh_key, &token)
+    {
+        Err(e) => {
+            debug!("Refresh cookie is not valid: {:?}", &e);
+            return Err(unauthorized());
+        }
+        Ok(data) => data.claims.session,
+    };
+    check_csrf(config, headers, session)?;
+    Ok(token)
+}
+
+fn set_cookie(
+    config: &AppConfig,
+    name: &str,
+    value: &str,
+    path: &str,
+    max_age: i64,
+    http_only: bool,
+) -> HeaderValue {
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; SameSite={}",
+        name, value, path, max_age, config.session_cookie.same_site
+    );
+    if http_only {
+        cookie.push_str("; HttpOnly");
+    }
+    if config.session_cookie.secure {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+/// `Set-Cookie` headers carrying a freshly issued token pair, and the CSRF token of its
+/// session, which is also returned to the page
+pub fn session_cookies(
+    config: &AppConfig,
+    keys: &JwtKeySet,
+    tokens: &PostLoginOutput,
+) -> Result<(HeaderMap, String), AppError> {
+    let session = keys.verify::<Claims>(&tokens.access_token)?.claims.session;
+    let csrf = csrf_token(&csrf_key(config), session);
+    let access_age = config.jwt_access_session_minutes.saturating_mul(60);
+    let refresh_age = config.jwt_refresh_session_days.saturating_mul(86400);
+    let mut headers = HeaderMap::new();
+    headers.append(
+        SET_COOKIE,
+        set_cookie(
+            config,
+            ACCESS_COOKIE,
+            &tokens.access_token,
+            "/",
+            access_age,
+            true,
+        ),
+    );
+    headers.append(
+        SET_COOKIE,
+        set_cookie(
+            config,
+            REFRESH_COOKIE,
+            &tokens.refresh_token,
+            REFRESH_PATH,
+            refresh_age,
+            true,
+        ),
+    );
+    headers.append(
+        SET_COOKIE,
+        set_cookie(config, CSRF_COOKIE, &csrf, "/", refresh_age, false),
+    );
+    Ok((headers, csrf))
+}
+
+fn oauth_state_mac(key: &[u8], state: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(format!("oauth_state:{}", state).as_bytes());
+    mac
+}
+
+fn oauth_state_header(config: &AppConfig, value: &str, max_age: u64) -> HeaderValue {
+    // The callback is a cross-site redirect from the provider, which `Strict` would not
+    // carry the cookie on
+    let mut cookie = format!(
+        "{}={}; Path={}; Max-Age={}; SameSite=Lax; HttpOnly",
+        OAUTH_STATE_COOKIE, value, OAUTH_STATE_PATH, max_age
+    );
+    if config.session_cookie.secure {
+        cookie.push_str("; Secure");
+    }
+    HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+}
+
+/// `Set-Cookie` value binding the OAuth `state` to this browser for `max_age` seconds
+pub fn oauth_state_cookie(config: &AppConfig, state: &str, max_age: u64) -> HeaderValue {
+    let key = derived_key(config, OAUTH_STATE_KEY_LABEL);
+    let signature = oauth_state_mac(&key, state).finalize().into_bytes();
+    oauth_state_header(config, &base64_encode_from_u8(&signature.to_vec()), max_age)
+}
+
+/// Requires the `oauth_state` cookie of the browser to be signed for `state`
+pub fn check_oauth_state(
+    config: &AppConfig,
+    headers: &HeaderMap,
+    state: &str,
+) -> Result<(), AppError> {
+    let key = derived_key(config, OAUTH_STATE_KEY_LABEL);
+    let valid = get_cookie(headers, OAUTH_STATE_COOKIE)
+        .and_then(|cookie| base64_url::decode(cookie.as_bytes()).ok())
+        .is_some_and(|signature| {
+            oauth_state_mac(&key, state)
+                .verify_slice(&signature)
+                .is_ok()
+        });
+    if !valid {
+        debug!("OAuth state cookie is missing or does not match the callback");
+        return Err(AppError::new(
+            "Invalid or expired OAuth state!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    Ok(())
+}
+
+/// `Set-Cookie` value removing the OAuth state cookie
+pub fn clear_oauth_state_cookie(config: &AppConfig) -> HeaderValue {
+    oauth_state_header(config, "", 0)
+}
+
+/// `Set-Cookie` headers removing the session cookies
+pub fn clear_session_cookies(config: &AppConfig) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.append(
+        SET_COOKIE,
+        set_cookie(config, ACCESS_COOKIE, "", "/", 0, true),
+    );
+    headers.append(
+        SET_COOKIE,
+        set_cookie(config, REFRESH_COOKIE, "", REFRESH_PATH, 0, true),
+    );
+    headers.append(
+        SET_COOKIE,
+        set_cookie(config, CSRF_COOKIE, "", "/", 0, false),
+    );
+    headers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_csrf_token -- --nocapture
+    #[test]
+    fn check_csrf_token() {
+        let token = csrf_token(b"secret", 42);
+        println!("{}", &token);
+        assert!(csrf_token_valid(b"secret", 42, &token));
+        assert!(!csrf_token_valid(b"secret", 43, &token));
+        assert!(!csrf_token_valid(b"other", 42, &token));
+        assert!(!csrf_token_valid(b"secret", 42, "forged"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {}={}", CSRF_COOKIE, &token)).unwrap(),
+        );
+        assert_eq!(get_cookie(&headers, CSRF_COOKIE), Some(token));
+        assert_eq!(get_cookie(&headers, ACCESS_COOKIE), None);
+    }
+
+    // cargo test --package auth check_oauth_state_cookie -- --nocapture
+    #[test]
+    fn check_oauth_state_cookie() {
+        let config = AppConfig::new();
+        let set = oauth_state_cookie(&config, "state-1", 600);
+        let set = set.to_str().unwrap();
+        println!("{}", set);
+        assert!(set.contains("HttpOnly") && set.contains("Path=/oauth"));
+        let pair = set.split(';').next().unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert!(check_oauth_state(&config, &headers, "state-1").is_err());
+        headers.insert(COOKIE, HeaderValue::from_str(pair).unwrap());
+        assert!(check_oauth_state(&config, &headers, "state-1").is_ok());
+        // A callback carrying another attempt's state is refused
+        assert!(check_oauth_state(&config, &headers, "state-2").is_err());
+    }
+}
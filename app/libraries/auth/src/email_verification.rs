@@ -0,0 +1,197 @@
+//! Email verification of new accounts (`email_verification` in the config).
+//!
+//! Verification mails carry a random token that is stored as a SHA-256 hash in
+//! `auth.email_verifications`, valid for `token_minutes` and redeemable once. The token is
+//! bound to the address it was mailed to, so it stops working when the email changes.
+//! Resends are throttled per email in Redis: `verify:wait:<email>` spaces them out and
+//! `verify:count:<email>` caps them per window.
+
+use crate::password_reset::{generate_reset_token, hash_reset_token};
+use app_config::AppConfig;
+use app_error::AppError;
+use app_mailer::{Mail, MailSender};
+use app_redis::{RdPool, Redis};
+use app_schema::auth::{email_verifications::EmailVerification, users::User};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+
+const INVALID_TOKEN_MESSAGE: &str = "Invalid or expired verification token!";
+const TOO_MANY_MAILS_MESSAGE: &str = "Too many verification mails, try again later!";
+
+fn wait_key(email: &str) -> String {
+    format!("verify:wait:{}", email.to_lowercase())
+}
+
+fn count_key(email: &str) -> String {
+    format!("verify:count:{}", email.to_lowercase())
+}
+
+fn verification_link(base_url: &str, token: &str) -> String {
+    format!(
+        "{}/verify_email?token={}",
+        base_url.trim_end_matches('/'),
+        token
+    )
+}
+
+/// Refuses a resend to `email` within the interval or beyond the cap of the window, and
+/// otherwise counts it
+async fn throttle_resend(redis: &RdPool, config: &AppConfig, email: &str) -> Result<(), AppError> {
+    let settings = &config.email_verification;
+    let ttl = Redis::ttl(redis, &wait_key(email)).await?;
+    if ttl > 0 {
+        return Err(AppError::too_many_requests(
+            TOO_MANY_MAILS_MESSAGE,
+            ttl as u64,
+        ));
+    }
+    let count_key = count_key(email);
+    let sent = Redis::incr(redis, &count_key).await?;
+    if sent == 1 {
+        // The window starts with its first mail
+        Redis::expire(redis, &count_key, settings.resend_window_seconds as i64).await?;
+    }
+    if sent > settings.resend_max {
+        let ttl = Redis::ttl(redis, &count_key).await?;
+        return Err(AppError::too_many_requests(
+            TOO_MANY_MAILS_MESSAGE,
+            ttl.max(1) as u64,
+        ));
+    }
+    Redis::set_ex(
+        redis,
+        &wait_key(email),
+        "1",
+        settings.resend_interval_seconds,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Mails a new verification link to `user`, replacing the unused ones
+pub async fn send_verification(
+    pg: &Pool<Postgres>,
+    mailer: &dyn MailSender,
+    config: &AppConfig,
+    user: &User,
+) -> Result<(), AppError> {
+    let token = generate_reset_token();
+    let minutes = config.email_verification.token_minutes;
+    let mut tx = pg.begin().await?;
+    sqlx::query(EmailVerification::delete_by_user_query())
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(EmailVerification::insert_query())
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(hash_reset_token(&token))
+        .bind(Utc::now() + Duration::minutes(minutes))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    mailer
+        .send(&Mail {
+            to: user.email.clone(),
+            subject: "Verify your email address".into(),
+            body: format!(
+                "Hello {},\n\nOpen the link below to verify your email address. It expires in {} minutes.\n\n{}\n\nIf you did not create an account, you can ignore this mail.\n",
+                &user.name,
+                minutes,
+                verification_link(&config.mail.base_url, &token)
+            ),
+        })
+        .await?;
+    info!("Verification mail sent to {}", &user.email);
+    Ok(())
+}
+
+/// Resends the verification mail of `email` under the resend throttle. Unknown, inactive and
+/// already verified addresses are only logged (and throttled the same way), so callers can
+/// answer the same whether or not an unverified account exists.
+pub async fn request_verification(
+    pg: &Pool<Postgres>,
+    redis: &RdPool,
+    mailer: &dyn MailSender,
+    config: &AppConfig,
+    email: &str,
+) -> Result<(), AppError> {
+    throttle_resend(redis, config, email).await?;
+    match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+        .bind(email)
+        .fetch_optional(pg)
+        .await?
+    {
+        None => {
+            info!("Verification requested for unknown email {}", email);
+            Ok(())
+        }
+        Some(user) if !user.is_active() || user.email_verified_at.is_some() => {
+            info!(
+                "Verification requested for inactive or verified user {}",
+                email
+            );
+            Ok(())
+        }
+        Some(user) => send_verification(pg, mailer, config, &user).await,
+    }
+}
+
+/// Redeems `token` and marks the address of its user verified
+pub async fn verify_email(pg: &Pool<Postgres>, token: &str) -> Result<User, AppError> {
+    let invalid = || AppError::new(INVALID_TOKEN_MESSAGE, StatusCode::BAD_REQUEST, 6);
+    let mut tx = pg.begin().await?;
+    let verification =
+        match sqlx::query_as::<_, EmailVerification>(EmailVerification::consume_query())
+            .bind(hash_reset_token(token))
+            .fetch_optional(&mut *tx)
+            .await?
+        {
+            None => return Err(invalid()),
+            Some(verification) => verification,
+        };
+    let user = match sqlx::query_as::<_, User>(User::mark_verified_query())
+        .bind(verification.user_id)
+        .bind(&verification.email)
+        .fetch_optional(&mut *tx)
+        .await?
+    {
+        // The user changed their address or was deleted since the mail was sent
+        None => return Err(invalid()),
+        Some(user) => user,
+    };
+    tx.commit().await?;
+    info!("Email {} verified", &user.email);
+    Ok(user)
+}
+
+/// Refuses unverified users when `email_verification.required` is set
+pub fn check_verified(config: &AppConfig, user: &User) -> Result<(), AppError> {
+    if config.email_verification.required && user.email_verified_at.is_none() {
+        return Err(AppError::new(
+            "Email address is not verified!",
+            StatusCode::FORBIDDEN,
+            4,
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_verification_link -- --nocapture
+    #[test]
+    fn check_verification_link() {
+        let link = verification_link("http://localhost:9000/", "abc");
+        println!("{}", &link);
+        assert_eq!(link, "http://localhost:9000/verify_email?token=abc");
+        assert_eq!(wait_key("User@Example.com"), "verify:wait:user@example.com");
+        assert_eq!(
+            count_key("User@Example.com"),
+            "verify:count:user@example.com"
+        );
+    }
+}
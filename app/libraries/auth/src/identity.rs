@@ -0,0 +1,229 @@
+//! External sign-in identities (`auth.identities`) and their local users.
+//!
+//! A verified external sign-in resolves to a row of `auth.users`: first by the
+//! `(provider, subject)` pair, then by the verified email of an existing user (which gets the
+//! identity linked), and otherwise by provisioning a passwordless user with [`DEFAULT_ROLE`].
+//! A user has at most one identity per provider, so the email match is refused when the user
+//! already has another identity of the provider.
+
+use crate::rbac::{DEFAULT_ROLE, replace_roles};
+use app_error::AppError;
+use app_schema::auth::{identities::Identity, users::User};
+use axum::http::StatusCode;
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+/// A verified identity returned by an external provider
+#[derive(Debug, Clone)]
+pub struct ExternalIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: String,
+    pub name: String,
+}
+
+fn conflict(message: &str) -> AppError {
+    AppError::new(message, StatusCode::CONFLICT, 7)
+}
+
+fn inactive(user: &User) -> AppError {
+    info!("External sign-in refused for inactive user {}", &user.email);
+    AppError::new("Account is disabled!", StatusCode::FORBIDDEN, 2)
+}
+
+/// Finds, links or provisions the local user of a verified external identity
+pub async fn resolve_identity(
+    pg: &Pool<Postgres>,
+    identity: &ExternalIdentity,
+) -> Result<User, AppError> {
+    let mut tx = pg.begin().await?;
+    if let Some(user) = sqlx::query_as::<_, User>(User::select_by_identity_query())
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .fetch_optional(&mut *tx)
+        .await?
+    {
+        tx.commit().await?;
+        if !user.is_active() {
+            return Err(inactive(&user));
+        }
+        return Ok(user);
+    }
+    let user =
+        match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+            .bind(&identity.email)
+            .fetch_optional(&mut *tx)
+            .await?
+        {
+            Some(user) if !user.is_active() => return Err(inactive(&user)),
+            Some(user) => {
+                let linked = sqlx::query_as::<_, Identity>(&format!(
+                    "{} WHERE provider = $1 AND user_id = $2",
+                    Identity::select_query()
+                ))
+                .bind(&identity.provider)
+                .bind(user.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+                if linked.is_some() {
+                    info!(
+                        "{} sign-in of {} does not match its linked identity",
+                        &identity.provider, &user.email
+                    );
+                    return Err(conflict(
+                        "Another identity of this provider is already linked to this account!",
+                    ));
+                }
+                info!(
+                    "Linking {} identity to existing user {}",
+                    &identity.provider, &user.email
+                );
+                // The provider has verified the address
+                sqlx::query_as::<_, User>(User::mark_verified_query())
+                    .bind(user.id)
+                    .bind(&user.email)
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .unwrap_or(user)
+            }
+            None => {
+                let user = sqlx::query_as::<_, User>(User::insert_external_query())
+                    .bind(&identity.name)
+                    .bind(&identity.email)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                replace_roles(&mut tx, &user.email, &[DEFAULT_ROLE.to_string()]).await?;
+                info!(
+                    "Provisioned user {} from {} sign-in",
+                    &user.email, &identity.provider
+                );
+                user
+            }
+        };
+    sqlx::query(Identity::insert_query())
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(user.id)
+        .bind(&identity.email)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(user)
+}
+
+/// Links an external identity to `user_id`; one identity per provider and user
+pub async fn link_identity(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+    identity: &ExternalIdentity,
+) -> Result<(), AppError> {
+    let linked = sqlx::query_as::<_, Identity>(&format!(
+        "{} WHERE provider = $1 AND (subject = $2 OR user_id = $3)",
+        Identity::select_query()
+    ))
+    .bind(&identity.provider)
+    .bind(&identity.subject)
+    .bind(user_id)
+    .fetch_all(pg)
+    .await?;
+    if linked
+        .iter()
+        .any(|r| r.subject == identity.subject && &r.user_id == user_id)
+    {
+        // Already linked to this user
+        return Ok(());
+    }
+    if linked.iter().any(|r| r.subject == identity.subject) {
+        return Err(conflict("This identity is linked to another user!"));
+    }
+    if !linked.is_empty() {
+        return Err(conflict(
+            "Another identity of this provider is already linked!",
+        ));
+    }
+    sqlx::query(Identity::insert_query())
+        .bind(&identity.provider)
+        .bind(&identity.subject)
+        .bind(user_id)
+        .bind(&identity.email)
+        .execute(pg)
+        .await?;
+    Ok(())
+}
+
+/// Lists the identities linked to a user
+pub async fn list_identities(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+) -> Result<Vec<Identity>, AppError> {
+    Ok(sqlx::query_as::<_, Identity>(&format!(
+        "{} WHERE user_id = $1 ORDER BY provider",
+        Identity::select_query()
+    ))
+    .bind(user_id)
+    .fetch_all(pg)
+    .await?)
+}
+
+/// Unlinks the `provider` identity of a user. Refuses to remove the last way to sign in
+/// of a user without a password.
+pub async fn unlink_identity(
+    pg: &Pool<Postgres>,
+    user: &User,
+    provider: &str,
+) -> Result<bool, AppError> {
+    if user.password_hash.is_none() {
+        let identities = list_identities(pg, &user.id).await?;
+        if identities.iter().all(|i| i.provider == provider) && !identities.is_empty() {
+            return Err(conflict(
+                "Cannot unlink the only sign-in method of a user without a password!",
+            ));
+        }
+    }
+    let deleted = sqlx::query(Identity::delete_by_user_query())
+        .bind(provider)
+        .bind(user.id)
+        .execute(pg)
+        .await?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+    // cargo test --package auth check_resolve_identity_conflict -- --nocapture
+    #[test]
+    fn check_resolve_identity_conflict() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let pg = PgPoolOptions::new()
+                .connect(&std::env::var("DATABASE_URL").unwrap())
+                .await
+                .unwrap();
+            let identity = ExternalIdentity {
+                provider: "unit".to_owned(),
+                subject: Uuid::new_v4().to_string(),
+                email: format!("{}@unit", Uuid::new_v4()),
+                name: "Unit".to_owned(),
+            };
+            let user = resolve_identity(&pg, &identity).await.unwrap();
+            assert_eq!(resolve_identity(&pg, &identity).await.unwrap().id, user.id);
+            // Another account of the provider with the same email is not auto-linked
+            let other = ExternalIdentity {
+                subject: Uuid::new_v4().to_string(),
+                ..identity.clone()
+            };
+            let res = resolve_identity(&pg, &other).await;
+            assert_eq!(res.unwrap_err().status, StatusCode::CONFLICT);
+
+            sqlx::query("DELETE FROM auth.users WHERE id = $1")
+                .bind(user.id)
+                .execute(&pg)
+                .await
+                .unwrap();
+        });
+    }
+}
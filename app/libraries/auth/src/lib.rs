@@ -0,0 +1,14 @@
+pub mod api_key;
+pub mod cookie;
+pub mod email_verification;
+pub mod identity;
+pub mod mcp_client;
+pub mod mcp_oauth;
+pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod password_policy;
+pub mod password_reset;
+pub mod rbac;
+pub mod session;
+pub mod throttle;
@@ -0,0 +1,197 @@
+//! Credentials of MCP clients.
+//!
+//! Clients managed at `/auth/mcp_clients` (`auth.mcp_clients`) get tokens like
+//! `mcp_<prefix>_<secret>`, found by prefix and stored as a SHA-256 hash like API keys.
+//! Clients in `mcp_clients` of the config carry the hash of any token. The legacy `mcp_token`
+//! is still accepted as client `default` with every tool, for the built-in agent. Tokens are
+//! never logged; failures only name the prefix or nothing.
+
+use crate::api_key::{constant_time_eq, hash_api_key, random_bytes, to_hex};
+use app_config::AppConfig;
+use app_cryptography::base64::base64_encode_from_u8;
+use app_error::AppError;
+use app_schema::auth::mcp_clients::McpClient;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+const TOKEN_PREFIX: &str = "mcp_";
+
+/// Name of the client authenticated by the legacy `mcp_token`
+pub const DEFAULT_CLIENT: &str = "default";
+
+/// Allows every tool in `allowed_tools`
+pub const ALL_TOOLS: &str = "*";
+
+/// The MCP client a request was authenticated as, put into the request extensions by
+/// `mcp_auth_middleware`
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpClientIdentity {
+    pub id: Option<Uuid>, // None for clients of the config
+    pub name: String,
+    pub allowed_tools: Vec<String>,
+    pub user: Option<String>, // Email of the user an OAuth client acts for
+}
+
+impl McpClientIdentity {
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.allowed_tools
+            .iter()
+            .any(|t| t == ALL_TOOLS || t == tool)
+    }
+}
+
+fn unauthorized() -> AppError {
+    AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0)
+}
+
+/// Returns `(token, prefix)` of a new random token
+pub fn generate_mcp_token() -> (String, String) {
+    let prefix = to_hex(&random_bytes(6));
+    let secret = base64_encode_from_u8(&random_bytes(32));
+    (format!("{}{}_{}", TOKEN_PREFIX, &prefix, secret), prefix)
+}
+
+fn token_prefix(token: &str) -> Option<&str> {
+    token
+        .strip_prefix(TOKEN_PREFIX)?
+        .split_once('_')
+        .map(|(prefix, _)| prefix)
+}
+
+/// Stores a new client and returns it with the clear token, which is never shown again
+pub async fn create_mcp_client(
+    pg: &Pool<Postgres>,
+    name: &str,
+    allowed_tools: &[String],
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<(String, McpClient), AppError> {
+    let (token, prefix) = generate_mcp_token();
+    let mut allowed_tools = allowed_tools.to_vec();
+    allowed_tools.sort();
+    allowed_tools.dedup();
+    let client = sqlx::query_as::<_, McpClient>(McpClient::insert_query())
+        .bind(name)
+        .bind(&prefix)
+        .bind(hash_api_key(&token))
+        .bind(&allowed_tools)
+        .bind(expires_at)
+        .fetch_one(pg)
+        .await?;
+    Ok((token, client))
+}
+
+/// Matches a bearer token against the clients of the config
+fn config_client(config: &AppConfig, token: &str) -> Option<McpClientIdentity> {
+    let hash = hash_api_key(token);
+    let now = Utc::now().timestamp();
+    for client in &config.mcp_clients {
+        if !constant_time_eq(hash.as_bytes(), client.token_hash.to_lowercase().as_bytes()) {
+            continue;
+        }
+        if client.expires_at.is_some_and(|e| e <= now) {
+            info!("MCP client {} has expired", &client.name);
+            return None;
+        }
+        return Some(McpClientIdentity {
+            id: None,
+            name: client.name.clone(),
+            allowed_tools: client.allowed_tools.clone(),
+            user: None,
+        });
+    }
+    if !config.mcp_token.is_empty()
+        && constant_time_eq(token.as_bytes(), config.mcp_token.as_bytes())
+    {
+        return Some(McpClientIdentity {
+            id: None,
+            name: DEFAULT_CLIENT.to_owned(),
+            allowed_tools: vec![ALL_TOOLS.to_owned()],
+            user: None,
+        });
+    }
+    None
+}
+
+/// Resolves an `Authorization: Bearer <token>` header to the MCP client it belongs to
+pub async fn authenticate_mcp_client(
+    pg: &Pool<Postgres>,
+    config: &AppConfig,
+    token: &str,
+) -> Result<McpClientIdentity, AppError> {
+    let prefix = match token_prefix(token) {
+        None => {
+            return match config_client(config, token) {
+                None => {
+                    info!("Unknown MCP token");
+                    Err(unauthorized())
+                }
+                Some(client) => Ok(client),
+            };
+        }
+        Some(prefix) => prefix,
+    };
+    let client = match sqlx::query_as::<_, McpClient>(&format!(
+        "{} WHERE prefix = $1",
+        McpClient::select_query()
+    ))
+    .bind(prefix)
+    .fetch_optional(pg)
+    .await?
+    {
+        None => {
+            info!("Unknown MCP token prefix {}", prefix);
+            return Err(unauthorized());
+        }
+        Some(client) => client,
+    };
+    if !constant_time_eq(hash_api_key(token).as_bytes(), client.token_hash.as_bytes()) {
+        warn!("Invalid secret for MCP client {}", &client.name);
+        return Err(unauthorized());
+    }
+    if let Some(expires_at) = client.expires_at
+        && expires_at <= Utc::now()
+    {
+        info!("MCP client {} has expired", &client.name);
+        return Err(unauthorized());
+    }
+    sqlx::query(McpClient::touch_query())
+        .bind(client.id)
+        .execute(pg)
+        .await?;
+    Ok(McpClientIdentity {
+        id: Some(client.id),
+        name: client.name,
+        allowed_tools: client.allowed_tools,
+        user: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_mcp_token -- --nocapture
+    #[test]
+    fn check_mcp_token() {
+        let (token, prefix) = generate_mcp_token();
+        println!("{} {}", &token, &prefix);
+        assert_eq!(token_prefix(&token), Some(prefix.as_str()));
+        assert_eq!(token_prefix("ak_abc_def"), None);
+
+        let client = McpClientIdentity {
+            id: None,
+            name: "reporting".into(),
+            allowed_tools: vec!["sum".into()],
+            user: None,
+        };
+        assert!(client.allows_tool("sum"));
+        assert!(!client.allows_tool("get_users"));
+        let all = McpClientIdentity {
+            allowed_tools: vec![ALL_TOOLS.into()],
+            ..client
+        };
+        assert!(all.allows_tool("get_users"));
+    }
+}
@@ -0,0 +1,561 @@
+//! OAuth 2.1 authorization server for the MCP endpoint (`mcp_oauth` in the config).
+//!
+//! Third-party MCP clients register themselves (RFC 7591), send the user through
+//! `/oauth2/authorize` with a PKCE `S256` challenge and exchange the code at `/oauth2/token`.
+//! Codes live in Redis under `oauth2:code:<hash>` for `code_seconds` and are redeemed once.
+//! Tokens are ordinary login sessions whose `source` is `mcp:<client_id>`: they show up in and
+//! can be revoked from the user's session list, the MCP service only accepts tokens of such
+//! sessions, and `web_auth_middleware` refuses them.
+
+use crate::{
+    api_key::{constant_time_eq, hash_api_key, random_bytes, to_hex},
+    mcp_client::{ALL_TOOLS, McpClientIdentity},
+    oauth::pkce_challenge,
+    rbac::load_access,
+    session::{ClientInfo, SessionUser, create_session, rotate_session, session_exists},
+};
+use app_config::AppConfig;
+use app_cryptography::{
+    base64::base64_encode_from_u8,
+    jwt::{Algorithm, Claims, JwtKeySet, validate_token},
+};
+use app_dto::auth::oauth2::{
+    AuthorizationServerMetadata, AuthorizeQuery, ProtectedResourceMetadata, RegisterClientInput,
+    RegisterClientOutput, TokenInput, TokenOutput,
+};
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use app_schema::auth::{oauth_clients::OAuthClient, users::User};
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header::CACHE_CONTROL},
+    response::{IntoResponse, Response},
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+/// `source` prefix of sessions opened for an MCP client
+pub const MCP_SOURCE_PREFIX: &str = "mcp:";
+/// The only scope: use the MCP tools as the signed-in user
+pub const MCP_SCOPE: &str = "mcp";
+
+const AUTH_METHOD_NONE: &str = "none";
+const AUTH_METHOD_SECRET_POST: &str = "client_secret_post";
+
+/// Error answered by the registration and token endpoints (RFC 6749 section 5.2)
+#[derive(Debug)]
+pub struct OAuthError {
+    pub status: StatusCode,
+    pub error: &'static str,
+    pub description: String,
+}
+
+impl OAuthError {
+    pub fn new(error: &'static str, description: impl Into<String>) -> Self {
+        let status = match error {
+            "invalid_client" => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        Self {
+            status,
+            error,
+            description: description.into(),
+        }
+    }
+}
+
+impl From<AppError> for OAuthError {
+    fn from(value: AppError) -> Self {
+        error!("OAuth request failed: {}", value.message);
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            error: "server_error",
+            description: "Internal error".into(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for OAuthError {
+    fn from(value: sqlx::Error) -> Self {
+        AppError::from(value).into()
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": self.error,
+            "error_description": self.description,
+        });
+        let mut res = (self.status, Json(body)).into_response();
+        res.headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        res
+    }
+}
+
+/// An authorization code waiting to be exchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationCode {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub code_challenge: String,
+    pub user_id: Uuid,
+    pub scope: String,
+}
+
+fn code_key(code: &str) -> String {
+    format!("oauth2:code:{}", hash_api_key(code))
+}
+
+/// Session `source` of tokens issued to `client_id`
+pub fn mcp_source(client_id: &str) -> String {
+    format!("{}{}", MCP_SOURCE_PREFIX, client_id)
+}
+
+/// Whether a token belongs to a session opened for an MCP client
+pub fn is_mcp_source(source: &str) -> bool {
+    source.starts_with(MCP_SOURCE_PREFIX)
+}
+
+fn issuer(config: &AppConfig) -> &str {
+    config.mcp_oauth.issuer.trim_end_matches('/')
+}
+
+/// `/.well-known/oauth-authorization-server` of the web service
+pub fn authorization_server_metadata(config: &AppConfig) -> AuthorizationServerMetadata {
+    let issuer = issuer(config);
+    AuthorizationServerMetadata {
+        issuer: issuer.to_owned(),
+        authorization_endpoint: format!("{}/oauth2/authorize", issuer),
+        token_endpoint: format!("{}/oauth2/token", issuer),
+        registration_endpoint: config
+            .mcp_oauth
+            .registration
+            .then(|| format!("{}/oauth2/register", issuer)),
+        scopes_supported: vec![MCP_SCOPE.to_owned()],
+        response_types_supported: vec!["code".to_owned()],
+        grant_types_supported: vec!["authorization_code".to_owned(), "refresh_token".to_owned()],
+        token_endpoint_auth_methods_supported: vec![
+            AUTH_METHOD_NONE.to_owned(),
+            AUTH_METHOD_SECRET_POST.to_owned(),
+        ],
+        code_challenge_methods_supported: vec!["S256".to_owned()],
+    }
+}
+
+/// `/.well-known/oauth-protected-resource` of the MCP service
+pub fn protected_resource_metadata(config: &AppConfig) -> ProtectedResourceMetadata {
+    ProtectedResourceMetadata {
+        resource: config.mcp_base_url.clone(),
+        authorization_servers: vec![issuer(config).to_owned()],
+        scopes_supported: vec![MCP_SCOPE.to_owned()],
+        bearer_methods_supported: vec!["header".to_owned()],
+        resource_name: config.agent_app_name.clone(),
+    }
+}
+
+/// URL of the protected resource metadata, announced in `WWW-Authenticate` (RFC 9728 §5.1)
+pub fn protected_resource_metadata_url(config: &AppConfig) -> Option<String> {
+    let resource = Url::parse(&config.mcp_base_url).ok()?;
+    let path = resource.path().trim_end_matches('/');
+    Some(format!(
+        "{}/.well-known/oauth-protected-resource{}",
+        resource.origin().ascii_serialization(),
+        path
+    ))
+}
+
+/// A `resource` parameter (RFC 8707) may only name the MCP endpoint
+pub fn valid_resource(config: &AppConfig, resource: Option<&str>) -> bool {
+    resource.is_none_or(|r| r.trim_end_matches('/') == config.mcp_base_url.trim_end_matches('/'))
+}
+
+/// Redirect URIs must be HTTPS, or HTTP on the loopback interface (OAuth 2.1 §8.4.2)
+pub fn valid_redirect_uri(uri: &str) -> bool {
+    let url = match Url::parse(uri) {
+        Err(_) => return false,
+        Ok(url) => url,
+    };
+    if url.fragment().is_some() {
+        return false;
+    }
+    match url.scheme() {
+        "https" => url.host_str().is_some(),
+        "http" => matches!(
+            url.host_str(),
+            Some("localhost") | Some("127.0.0.1") | Some("[::1]")
+        ),
+        _ => false,
+    }
+}
+
+/// Registers a client; confidential clients get a secret that is never shown again
+pub async fn register_client(
+    pg: &Pool<Postgres>,
+    input: &RegisterClientInput,
+) -> Result<RegisterClientOutput, OAuthError> {
+    let invalid = |description: &str| OAuthError::new("invalid_client_metadata", description);
+    if input.redirect_uris.is_empty() {
+        return Err(OAuthError::new(
+            "invalid_redirect_uri",
+            "redirect_uris is required",
+        ));
+    }
+    if let Some(uri) = input.redirect_uris.iter().find(|u| !valid_redirect_uri(u)) {
+        return Err(OAuthError::new(
+            "invalid_redirect_uri",
+            format!("{} must use https or a loopback http address", uri),
+        ));
+    }
+    let auth_method = input
+        .token_endpoint_auth_method
+        .clone()
+        .unwrap_or_else(|| AUTH_METHOD_NONE.to_owned());
+    if auth_method != AUTH_METHOD_NONE && auth_method != AUTH_METHOD_SECRET_POST {
+        return Err(invalid("Unsupported token_endpoint_auth_method"));
+    }
+    let grant_types = input
+        .grant_types
+        .clone()
+        .unwrap_or_else(|| vec!["authorization_code".to_owned()]);
+    if grant_types
+        .iter()
+        .any(|g| g != "authorization_code" && g != "refresh_token")
+    {
+        return Err(invalid("Unsupported grant_types"));
+    }
+    let response_types = input
+        .response_types
+        .clone()
+        .unwrap_or_else(|| vec!["code".to_owned()]);
+    if response_types.iter().any(|r| r != "code") {
+        return Err(invalid("Unsupported response_types"));
+    }
+    let client_id = to_hex(&random_bytes(16));
+    let secret =
+        (auth_method == AUTH_METHOD_SECRET_POST).then(|| base64_encode_from_u8(&random_bytes(32)));
+    let client = sqlx::query_as::<_, OAuthClient>(OAuthClient::insert_query())
+        .bind(&client_id)
+        .bind(secret.as_deref().map(hash_api_key))
+        .bind(input.client_name.clone().unwrap_or_default())
+        .bind(&input.redirect_uris)
+        .bind(&auth_method)
+        .fetch_one(pg)
+        .await?;
+    info!(
+        "OAuth client {} ({}) registered",
+        &client.client_id, &client.client_name
+    );
+    Ok(RegisterClientOutput {
+        client_id: client.client_id,
+        client_secret_expires_at: secret.as_ref().map(|_| 0),
+        client_secret: secret,
+        client_id_issued_at: client.created_at.timestamp(),
+        client_name: client.client_name,
+        redirect_uris: client.redirect_uris,
+        grant_types,
+        response_types,
+        token_endpoint_auth_method: client.token_endpoint_auth_method,
+    })
+}
+
+pub async fn find_client(
+    pg: &Pool<Postgres>,
+    client_id: &str,
+) -> Result<Option<OAuthClient>, AppError> {
+    Ok(sqlx::query_as::<_, OAuthClient>(&format!(
+        "{} WHERE client_id = $1",
+        OAuthClient::select_query()
+    ))
+    .bind(client_id)
+    .fetch_optional(pg)
+    .await?)
+}
+
+/// Checks the client and redirect URI of an authorization request. Failures here must be shown
+/// to the user rather than redirected, since the redirect target cannot be trusted.
+pub async fn authorization_client(
+    pg: &Pool<Postgres>,
+    request: &AuthorizeQuery,
+) -> Result<(OAuthClient, String), AppError> {
+    let invalid = |message: &str| AppError::new(message, StatusCode::BAD_REQUEST, 6);
+    let client = match &request.client_id {
+        None => return Err(invalid("client_id is required!")),
+        Some(client_id) => match find_client(pg, client_id).await? {
+            None => return Err(invalid("Unknown client!")),
+            Some(client) => client,
+        },
+    };
+    // Registered URIs are compared exactly
+    let redirect_uri = match &request.redirect_uri {
+        Some(uri) if client.redirect_uris.contains(uri) => uri.clone(),
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        _ => return Err(invalid("redirect_uri is not registered for this client!")),
+    };
+    Ok((client, redirect_uri))
+}
+
+/// Checks the remaining parameters of an authorization request; the error code is sent back
+/// to the client's redirect URI
+pub fn check_authorization_request(
+    config: &AppConfig,
+    request: &AuthorizeQuery,
+) -> Result<(), &'static str> {
+    if request.response_type.as_deref() != Some("code") {
+        return Err("unsupported_response_type");
+    }
+    // PKCE is mandatory in OAuth 2.1, and only S256 is accepted
+    if request.code_challenge.as_deref().unwrap_or_default().len() < 43
+        || request.code_challenge_method.as_deref() != Some("S256")
+    {
+        return Err("invalid_request");
+    }
+    if request
+        .scope
+        .as_deref()
+        .is_some_and(|scope| scope.split_whitespace().any(|s| s != MCP_SCOPE))
+    {
+        return Err("invalid_scope");
+    }
+    if !valid_resource(config, request.resource.as_deref()) {
+        return Err("invalid_target");
+    }
+    Ok(())
+}
+
+/// `redirect_uri` with the query parameters of an authorization response added
+pub fn redirect_with(redirect_uri: &str, params: &[(&str, &str)]) -> String {
+    match Url::parse(redirect_uri) {
+        Err(_) => redirect_uri.to_owned(),
+        Ok(mut url) => {
+            url.query_pairs_mut().extend_pairs(params);
+            url.to_string()
+        }
+    }
+}
+
+/// Stores a new single-use code for an approved request and returns it
+pub async fn issue_code(
+    redis: &RdPool,
+    config: &AppConfig,
+    code: &AuthorizationCode,
+) -> Result<String, AppError> {
+    let value = base64_encode_from_u8(&random_bytes(32));
+    Redis::set_ex(
+        redis,
+        &code_key(&value),
+        &serde_json::to_string(code)?,
+        config.mcp_oauth.code_seconds,
+    )
+    .await?;
+    Ok(value)
+}
+
+/// Authenticates the client of a token request
+async fn token_client(pg: &Pool<Postgres>, input: &TokenInput) -> Result<OAuthClient, OAuthError> {
+    let invalid = || OAuthError::new("invalid_client", "Client authentication failed");
+    let client = match &input.client_id {
+        None => return Err(invalid()),
+        Some(client_id) => match find_client(pg, client_id).await? {
+            None => return Err(invalid()),
+            Some(client) => client,
+        },
+    };
+    if let Some(hash) = &client.client_secret_hash {
+        let secret = input.client_secret.as_deref().unwrap_or_default();
+        if !constant_time_eq(hash_api_key(secret).as_bytes(), hash.as_bytes()) {
+            warn!("Invalid secret for OAuth client {}", &client.client_id);
+            return Err(invalid());
+        }
+    }
+    Ok(client)
+}
+
+fn token_output(config: &AppConfig, access_token: String, refresh_token: String) -> TokenOutput {
+    TokenOutput {
+        access_token,
+        token_type: "Bearer".to_owned(),
+        expires_in: config.jwt_access_session_minutes.saturating_mul(60),
+        refresh_token,
+        scope: MCP_SCOPE.to_owned(),
+    }
+}
+
+/// `grant_type=authorization_code`: redeems a code with its PKCE verifier and opens a session
+/// of the user for the client
+pub async fn exchange_code(
+    config: &AppConfig,
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    pg: &Pool<Postgres>,
+    input: &TokenInput,
+    client_info: ClientInfo,
+) -> Result<TokenOutput, OAuthError> {
+    let invalid = || OAuthError::new("invalid_grant", "Invalid or expired authorization code");
+    let client = token_client(pg, input).await?;
+    let code = match &input.code {
+        None => return Err(OAuthError::new("invalid_request", "code is required")),
+        Some(code) => code,
+    };
+    let pending = match Redis::getdel::<Option<String>>(redis, &code_key(code))
+        .await
+        .map_err(AppError::from)?
+    {
+        None => return Err(invalid()),
+        Some(value) => serde_json::from_str::<AuthorizationCode>(&value)
+            .map_err(|e| OAuthError::from(AppError::from(e)))?,
+    };
+    if pending.client_id != client.client_id
+        || input
+            .redirect_uri
+            .as_deref()
+            .unwrap_or(&pending.redirect_uri)
+            != pending.redirect_uri
+    {
+        warn!(
+            "Authorization code of {} redeemed by {}",
+            &pending.client_id, &client.client_id
+        );
+        return Err(invalid());
+    }
+    let verifier = input.code_verifier.as_deref().unwrap_or_default();
+    if !constant_time_eq(
+        pkce_challenge(verifier).as_bytes(),
+        pending.code_challenge.as_bytes(),
+    ) {
+        warn!("PKCE verification failed for {}", &client.client_id);
+        return Err(invalid());
+    }
+    let user = match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+        .bind(pending.user_id)
+        .fetch_optional(pg)
+        .await?
+    {
+        Some(user) if user.is_active() => user,
+        _ => return Err(invalid()),
+    };
+    let id = format!("{:?}", &user.id);
+    let access = load_access(pg, &id).await?;
+    let tokens = create_session(
+        config,
+        redis,
+        keys,
+        SessionUser {
+            id,
+            name: user.name.clone(),
+            email: user.email.clone(),
+            source: mcp_source(&client.client_id),
+            access,
+        },
+        client_info,
+    )
+    .await?;
+    info!(
+        "OAuth client {} authorized for {}",
+        &client.client_id, &user.email
+    );
+    Ok(token_output(
+        config,
+        tokens.access_token,
+        tokens.refresh_token,
+    ))
+}
+
+/// `grant_type=refresh_token`: rotates a session that was opened for the same client
+pub async fn refresh_grant(
+    config: &AppConfig,
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    pg: &Pool<Postgres>,
+    input: &TokenInput,
+    client_info: ClientInfo,
+) -> Result<TokenOutput, OAuthError> {
+    let invalid = || OAuthError::new("invalid_grant", "Invalid refresh token");
+    let client = token_client(pg, input).await?;
+    let refresh_token = match &input.refresh_token {
+        None => {
+            return Err(OAuthError::new(
+                "invalid_request",
+                "refresh_token is required",
+            ));
+        }
+        Some(token) => token,
+    };
+    match validate_token::<Claims>(Algorithm::HS256, &config.jwt_refresh_key, refresh_token) {
+        Ok(data) if data.claims.source == mcp_source(&client.client_id) => (),
+        _ => return Err(invalid()),
+    }
+    let tokens = rotate_session(config, redis, keys, pg, refresh_token, client_info)
+        .await
+        .map_err(|_| invalid())?;
+    Ok(token_output(
+        config,
+        tokens.access_token,
+        tokens.refresh_token,
+    ))
+}
+
+/// Resolves an access token issued by `/oauth2/token` to the client and the user it acts for.
+/// Tools are then limited by the permissions of the user rather than an allowlist.
+pub async fn authenticate_mcp_user(
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    token: &str,
+) -> Result<McpClientIdentity, AppError> {
+    let unauthorized = || AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0);
+    let claims = match keys.verify::<Claims>(token) {
+        Err(e) => {
+            debug!("MCP access token is not valid: {:?}", &e);
+            return Err(unauthorized());
+        }
+        Ok(data) => data.claims,
+    };
+    let client_id = match claims.source.strip_prefix(MCP_SOURCE_PREFIX) {
+        None => {
+            info!("Token of {} was not issued to an MCP client", &claims.email);
+            return Err(unauthorized());
+        }
+        Some(client_id) => client_id.to_owned(),
+    };
+    if !session_exists(redis, &claims.email, claims.session).await? {
+        info!("Session {} of {} is revoked", claims.session, &claims.email);
+        return Err(unauthorized());
+    }
+    Ok(McpClientIdentity {
+        id: None,
+        name: client_id,
+        allowed_tools: vec![ALL_TOOLS.to_owned()],
+        user: Some(claims.email),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_mcp_oauth -- --nocapture
+    #[test]
+    fn check_mcp_oauth() {
+        assert!(valid_redirect_uri("https://client.example.com/callback"));
+        assert!(valid_redirect_uri("http://localhost:33418/callback"));
+        assert!(valid_redirect_uri("http://127.0.0.1/callback"));
+        assert!(!valid_redirect_uri("http://client.example.com/callback"));
+        assert!(!valid_redirect_uri(
+            "https://client.example.com/cb#fragment"
+        ));
+        assert!(!valid_redirect_uri("javascript:alert(1)"));
+
+        let url = redirect_with(
+            "http://localhost:8080/cb?x=1",
+            &[("code", "a b"), ("state", "s")],
+        );
+        println!("{}", &url);
+        assert_eq!(url, "http://localhost:8080/cb?x=1&code=a+b&state=s");
+
+        assert!(is_mcp_source(&mcp_source("abc")));
+        assert!(!is_mcp_source("local"));
+    }
+}
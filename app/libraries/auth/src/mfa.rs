@@ -0,0 +1,368 @@
+//! TOTP second factor and one-time recovery codes for local accounts.
+//!
+//! Enrollment is two-step: `begin_totp_enrollment` stores a pending secret, and the first valid
+//! code confirms it and issues recovery codes. With a confirmed factor, password login only
+//! yields an MFA challenge kept in Redis under `mfa:<token>` for [`MFA_CHALLENGE_TTL_SECONDS`];
+//! the challenge allows [`MFA_MAX_ATTEMPTS`] codes and is consumed by the first valid one.
+//! Wrong codes also count as login failures of the user in the throttle, so starting new
+//! challenges does not allow more guesses, and the failure counters are only reset once the
+//! second factor passed.
+
+use crate::{
+    session::ClientInfo,
+    throttle::{
+        FailureReason, check_login_allowed, record_login_failure, record_login_success,
+        record_throttled,
+    },
+};
+use app_config::{AppConfig, LoginThrottleConfig};
+use app_cryptography::{
+    base64::base64_encode_from_u8,
+    totp::{
+        TOTP_DIGITS, base32_decode, base32_encode, generate_secret, provisioning_uri, verify_totp,
+    },
+};
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use app_schema::auth::{
+    mfa::{RecoveryCode, Totp},
+    users::User,
+};
+use axum::http::StatusCode;
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+/// Lifetime of a pending MFA login
+pub const MFA_CHALLENGE_TTL_SECONDS: u64 = 300;
+/// Codes accepted per MFA challenge before it is dropped
+pub const MFA_MAX_ATTEMPTS: i64 = 5;
+/// Recovery codes issued on enrollment and regeneration
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+const INVALID_CODE_MESSAGE: &str = "Invalid verification code!";
+const INVALID_CHALLENGE_MESSAGE: &str = "Invalid or expired MFA challenge!";
+
+/// A password login waiting for its second factor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaChallenge {
+    pub user_id: Uuid,
+    pub email: String, // Key of the login throttle
+    pub browser: Option<String>,
+    #[serde(default)]
+    pub cookie: bool, // The login asked for cookie mode
+}
+
+/// Pending enrollment shown to the user once
+#[derive(Debug, Clone)]
+pub struct TotpEnrollment {
+    pub secret: String, // base32
+    pub uri: String,    // otpauth:// for QR codes
+}
+
+fn challenge_key(token: &str) -> String {
+    format!("mfa:{}", token)
+}
+
+fn attempts_key(token: &str) -> String {
+    format!("mfa:{}:attempts", token)
+}
+
+fn invalid_code() -> AppError {
+    AppError::new(INVALID_CODE_MESSAGE, StatusCode::UNAUTHORIZED, 0)
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+/// Recovery codes are compared without dashes and case
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    Sha256::digest(normalize_recovery_code(code).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A random `xxxxx-xxxxx` hex code
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}", &hex[..5], &hex[5..])
+}
+
+pub async fn load_totp(pg: &Pool<Postgres>, user_id: &Uuid) -> Result<Option<Totp>, AppError> {
+    Ok(
+        sqlx::query_as::<_, Totp>(&format!("{} WHERE user_id = $1", Totp::select_query()))
+            .bind(user_id)
+            .fetch_optional(pg)
+            .await?,
+    )
+}
+
+/// Whether login requires a second factor for `user_id`
+pub async fn mfa_enabled(pg: &Pool<Postgres>, user_id: &Uuid) -> Result<bool, AppError> {
+    Ok(load_totp(pg, user_id)
+        .await?
+        .is_some_and(|t| t.confirmed_at.is_some()))
+}
+
+/// Starts (or restarts) enrollment with a new secret; fails when TOTP is already enabled
+pub async fn begin_totp_enrollment(
+    pg: &Pool<Postgres>,
+    config: &AppConfig,
+    user: &User,
+) -> Result<TotpEnrollment, AppError> {
+    let secret = generate_secret();
+    let encoded = base32_encode(&secret);
+    let updated = sqlx::query(Totp::upsert_query())
+        .bind(user.id)
+        .bind(&encoded)
+        .execute(pg)
+        .await?
+        .rows_affected();
+    if updated == 0 {
+        return Err(AppError::new(
+            "Two-factor authentication is already enabled!",
+            StatusCode::CONFLICT,
+            7,
+        ));
+    }
+    let issuer = config
+        .mfa_issuer
+        .clone()
+        .unwrap_or_else(|| config.agent_app_name.clone());
+    Ok(TotpEnrollment {
+        uri: provisioning_uri(&issuer, &user.email, &secret),
+        secret: encoded,
+    })
+}
+
+/// Replaces all recovery codes of `user_id` and returns the new ones in clear
+pub async fn regenerate_recovery_codes(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+) -> Result<Vec<String>, AppError> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = codes.iter().map(|c| hash_recovery_code(c)).collect();
+    let mut tx = pg.begin().await?;
+    sqlx::query(RecoveryCode::delete_query())
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(RecoveryCode::insert_query())
+        .bind(user_id)
+        .bind(&hashes)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(codes)
+}
+
+/// Confirms a pending enrollment with the first code from the app and issues recovery codes
+pub async fn confirm_totp_enrollment(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+    code: &str,
+) -> Result<Vec<String>, AppError> {
+    let totp = match load_totp(pg, user_id).await? {
+        Some(totp) if totp.confirmed_at.is_none() => totp,
+        _ => {
+            return Err(AppError::new(
+                "No pending two-factor enrollment!",
+                StatusCode::BAD_REQUEST,
+                6,
+            ));
+        }
+    };
+    let secret = match base32_decode(&totp.secret) {
+        None => return Err(AppError::internal("Invalid TOTP secret")),
+        Some(secret) => secret,
+    };
+    let step = match verify_totp(&secret, code, now()) {
+        None => return Err(invalid_code()),
+        Some(step) => step,
+    };
+    let confirmed = sqlx::query(Totp::confirm_query())
+        .bind(user_id)
+        .bind(step as i64)
+        .execute(pg)
+        .await?
+        .rows_affected();
+    if confirmed == 0 {
+        return Err(invalid_code());
+    }
+    regenerate_recovery_codes(pg, user_id).await
+}
+
+/// Checks a TOTP code or consumes a recovery code. Each TOTP step is accepted only once.
+pub async fn verify_second_factor(
+    pg: &Pool<Postgres>,
+    user_id: &Uuid,
+    code: &str,
+) -> Result<bool, AppError> {
+    let totp = match load_totp(pg, user_id).await? {
+        Some(totp) if totp.confirmed_at.is_some() => totp,
+        _ => return Ok(false),
+    };
+    let code = code.trim();
+    if code.len() == TOTP_DIGITS as usize && code.chars().all(|c| c.is_ascii_digit()) {
+        let secret = match base32_decode(&totp.secret) {
+            None => return Err(AppError::internal("Invalid TOTP secret")),
+            Some(secret) => secret,
+        };
+        let step = match verify_totp(&secret, code, now()) {
+            None => return Ok(false),
+            Some(step) => step,
+        };
+        let accepted = sqlx::query(Totp::use_step_query())
+            .bind(user_id)
+            .bind(step as i64)
+            .execute(pg)
+            .await?
+            .rows_affected();
+        if accepted == 0 {
+            warn!("Replayed TOTP code for {:?}", user_id);
+        }
+        return Ok(accepted > 0);
+    }
+    let used = sqlx::query(RecoveryCode::use_query())
+        .bind(user_id)
+        .bind(hash_recovery_code(code))
+        .execute(pg)
+        .await?
+        .rows_affected();
+    if used > 0 {
+        info!("Recovery code used by {:?}", user_id);
+    }
+    Ok(used > 0)
+}
+
+/// Removes the TOTP factor and recovery codes of `user_id`
+pub async fn disable_totp(pg: &Pool<Postgres>, user_id: &Uuid) -> Result<bool, AppError> {
+    let mut tx = pg.begin().await?;
+    let deleted = sqlx::query(Totp::delete_query())
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query(RecoveryCode::delete_query())
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(deleted > 0)
+}
+
+/// Stores a challenge for a login that passed the password check and returns its token
+pub async fn create_challenge(
+    redis: &RdPool,
+    challenge: &MfaChallenge,
+) -> Result<String, AppError> {
+    let mut bytes = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = base64_encode_from_u8(&bytes);
+    Redis::set_ex(
+        redis,
+        &challenge_key(&token),
+        &serde_json::to_string(challenge)?,
+        MFA_CHALLENGE_TTL_SECONDS,
+    )
+    .await?;
+    Ok(token)
+}
+
+/// Completes a challenge with a TOTP or recovery code; the challenge is single-use and
+/// dropped after [`MFA_MAX_ATTEMPTS`] wrong codes. Codes go through the login throttle of the
+/// user: wrong ones count as failures, a locked user is refused and a valid one resets the
+/// counters.
+pub async fn redeem_challenge(
+    redis: &RdPool,
+    pg: &Pool<Postgres>,
+    config: &LoginThrottleConfig,
+    token: &str,
+    code: &str,
+    client: &ClientInfo,
+) -> Result<MfaChallenge, AppError> {
+    let invalid = || AppError::new(INVALID_CHALLENGE_MESSAGE, StatusCode::UNAUTHORIZED, 0);
+    let key = challenge_key(token);
+    let challenge = match Redis::get::<Option<String>>(redis, &key).await? {
+        None => return Err(invalid()),
+        Some(value) => serde_json::from_str::<MfaChallenge>(&value)?,
+    };
+    if let Err(err) = check_login_allowed(redis, config, &challenge.email, &client.ip).await {
+        record_throttled(pg, &challenge.email, client).await;
+        return Err(err);
+    }
+    let attempts = Redis::incr(redis, &attempts_key(token)).await?;
+    if attempts == 1 {
+        Redis::expire(
+            redis,
+            &attempts_key(token),
+            MFA_CHALLENGE_TTL_SECONDS as i64,
+        )
+        .await?;
+    }
+    if attempts > MFA_MAX_ATTEMPTS {
+        warn!("Too many MFA attempts for {:?}", &challenge.user_id);
+        Redis::del(redis, vec![&key, &attempts_key(token)]).await?;
+        return Err(invalid());
+    }
+    if !verify_second_factor(pg, &challenge.user_id, code).await? {
+        record_login_failure(
+            redis,
+            pg,
+            config,
+            &challenge.email,
+            Some(challenge.user_id),
+            client,
+            FailureReason::WrongMfaCode,
+        )
+        .await?;
+        return Err(invalid_code());
+    }
+    // A concurrent request may have redeemed it in the meantime
+    if Redis::getdel::<Option<String>>(redis, &key)
+        .await?
+        .is_none()
+    {
+        return Err(invalid());
+    }
+    Redis::del(redis, vec![&attempts_key(token)]).await?;
+    record_login_success(redis, &challenge.email).await?;
+    Ok(challenge)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_recovery_code -- --nocapture
+    #[test]
+    fn check_recovery_code() {
+        let code = generate_recovery_code();
+        println!("{}", &code);
+        assert_eq!(code.len(), 11);
+        assert_eq!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&code.to_uppercase().replace('-', " "))
+        );
+        assert_ne!(
+            hash_recovery_code(&code),
+            hash_recovery_code(&generate_recovery_code())
+        );
+    }
+}
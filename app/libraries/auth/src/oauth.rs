@@ -0,0 +1,232 @@
+//! OAuth 2.0 / OpenID Connect helpers for external sign-in.
+//!
+//! Every authorization attempt gets a random `state`, `nonce` and PKCE verifier which are kept
+//! in Redis under `oauth:state:<state>` for [`STATE_TTL_SECONDS`]. The callback consumes the
+//! record with `GETDEL`, so a state can only be redeemed once and only by the provider that
+//! issued it. The returned `id_token` is verified against the provider JWKS, which is cached in
+//! Redis and refetched when a token is signed with an unknown `kid` (key rotation).
+
+use app_cryptography::base64::base64_encode_from_u8;
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use axum::http::StatusCode;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::*;
+use uuid::Uuid;
+
+/// Lifetime of a pending authorization attempt
+pub const STATE_TTL_SECONDS: u64 = 600;
+/// Lifetime of a cached JWKS document
+pub const JWKS_TTL_SECONDS: u64 = 3600;
+
+const INVALID_STATE_MESSAGE: &str = "Invalid or expired OAuth state!";
+
+/// Parameters of one authorization attempt; `code_verifier` never leaves the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingAuthorization {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    /// Set when a signed-in user links the identity instead of signing in with it
+    #[serde(default)]
+    pub link_user_id: Option<Uuid>,
+}
+
+impl PendingAuthorization {
+    /// PKCE `S256` challenge of the verifier
+    pub fn code_challenge(&self) -> String {
+        pkce_challenge(&self.code_verifier)
+    }
+}
+
+/// Claims of a verified OpenID Connect `id_token`
+pub type IdTokenClaims = serde_json::Map<String, serde_json::Value>;
+
+fn random_token() -> String {
+    let mut bytes = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64_encode_from_u8(&bytes)
+}
+
+fn state_key(state: &str) -> String {
+    format!("oauth:state:{}", state)
+}
+
+fn jwks_key(url: &str) -> String {
+    format!("oauth:jwks:{}", url)
+}
+
+/// PKCE `S256` transformation (RFC 7636)
+pub fn pkce_challenge(verifier: &str) -> String {
+    let digest = Sha256::digest(verifier.as_bytes());
+    base64_encode_from_u8(&digest.to_vec())
+}
+
+/// Creates and stores a new authorization attempt for `provider`
+pub async fn begin_authorization(
+    redis: &RdPool,
+    provider: &str,
+    link_user_id: Option<Uuid>,
+) -> Result<PendingAuthorization, AppError> {
+    let pending = PendingAuthorization {
+        provider: provider.to_owned(),
+        state: random_token(),
+        nonce: random_token(),
+        code_verifier: random_token(),
+        link_user_id,
+    };
+    Redis::set_ex(
+        redis,
+        &state_key(&pending.state),
+        &serde_json::to_string(&pending)?,
+        STATE_TTL_SECONDS,
+    )
+    .await?;
+    Ok(pending)
+}
+
+/// Redeems the authorization attempt identified by `state`; fails when it is unknown,
+/// expired, already used or belongs to another provider
+pub async fn take_authorization(
+    redis: &RdPool,
+    provider: &str,
+    state: &str,
+) -> Result<PendingAuthorization, AppError> {
+    let invalid = || AppError::new(INVALID_STATE_MESSAGE, StatusCode::BAD_REQUEST, 6);
+    if state.is_empty() {
+        return Err(invalid());
+    }
+    let pending = match Redis::getdel::<Option<String>>(redis, &state_key(state)).await? {
+        None => {
+            warn!("Unknown OAuth state for {}", provider);
+            return Err(invalid());
+        }
+        Some(value) => serde_json::from_str::<PendingAuthorization>(&value)?,
+    };
+    if pending.provider != provider {
+        warn!(
+            "OAuth state of {} redeemed by {}",
+            &pending.provider, provider
+        );
+        return Err(invalid());
+    }
+    Ok(pending)
+}
+
+async fn fetch_jwks(redis: &RdPool, url: &str) -> Result<JwkSet, AppError> {
+    let resp = match reqwest::get(url).await {
+        Ok(resp) => resp,
+        Err(err) => {
+            error!("Error sending JWKS request: {}", err);
+            return Err(AppError::internal("JWKS request failed"));
+        }
+    };
+    if !resp.status().is_success() {
+        error!("JWKS endpoint returned {}", resp.status());
+        return Err(AppError::internal("JWKS request failed"));
+    }
+    let text = match resp.text().await {
+        Ok(text) => text,
+        Err(err) => {
+            error!("Error reading JWKS response: {}", err);
+            return Err(AppError::internal("JWKS request failed"));
+        }
+    };
+    let jwks = serde_json::from_str::<JwkSet>(&text)?;
+    Redis::set_ex(redis, &jwks_key(url), &text, JWKS_TTL_SECONDS).await?;
+    Ok(jwks)
+}
+
+/// Finds the signing key `kid`, using the cached JWKS when it contains it
+async fn find_jwk(redis: &RdPool, url: &str, kid: &str) -> Result<Option<Jwk>, AppError> {
+    if let Some(cached) = Redis::get::<Option<String>>(redis, &jwks_key(url)).await?
+        && let Ok(jwks) = serde_json::from_str::<JwkSet>(&cached)
+        && let Some(jwk) = jwks.find(kid)
+    {
+        return Ok(Some(jwk.clone()));
+    }
+    let jwks = fetch_jwks(redis, url).await?;
+    Ok(jwks.find(kid).cloned())
+}
+
+/// Verifies the signature, issuer, audience, expiry and nonce of an `id_token`
+pub async fn verify_id_token(
+    redis: &RdPool,
+    jwks_url: &str,
+    issuers: &[&str],
+    audience: &str,
+    nonce: &str,
+    id_token: &str,
+) -> Result<IdTokenClaims, AppError> {
+    let invalid = || AppError::new("Invalid ID token!", StatusCode::UNAUTHORIZED, 0);
+    let header = decode_header(id_token).map_err(|e| {
+        warn!("Cannot decode ID token header: {}", e);
+        invalid()
+    })?;
+    if !matches!(
+        header.alg,
+        Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::ES256
+            | Algorithm::EdDSA
+    ) {
+        warn!(
+            "ID token signed with unsupported algorithm {:?}",
+            header.alg
+        );
+        return Err(invalid());
+    }
+    let kid = match header.kid {
+        None => return Err(invalid()),
+        Some(kid) => kid,
+    };
+    let jwk = match find_jwk(redis, jwks_url, &kid).await? {
+        None => {
+            warn!("ID token signed with unknown key {}", &kid);
+            return Err(invalid());
+        }
+        Some(jwk) => jwk,
+    };
+    let key = DecodingKey::from_jwk(&jwk)?;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(issuers);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|e| {
+            warn!("ID token rejected: {}", e);
+            invalid()
+        })?
+        .claims;
+    if claims.get("nonce").and_then(|n| n.as_str()) != Some(nonce) {
+        warn!("ID token nonce mismatch");
+        return Err(invalid());
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_pkce_challenge -- --nocapture
+    #[test]
+    fn check_pkce_challenge() {
+        // RFC 7636 appendix B
+        let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+        assert_eq!(
+            pkce_challenge(verifier),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        let token = random_token();
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, random_token());
+    }
+}
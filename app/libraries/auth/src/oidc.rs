@@ -0,0 +1,543 @@
+//! Generic OpenID Connect / OAuth 2.0 client driven by `oauth_providers` in the config.
+//!
+//! OIDC providers (Google, Keycloak) are configured with an `issuer` and use its discovery
+//! document; plain OAuth 2.0 providers (GitHub) list their endpoints explicitly. Claims are
+//! collected from the verified `id_token` and the userinfo endpoint, then mapped to an
+//! [`ExternalIdentity`] with the provider's claim mapping. Unverified emails are refused.
+
+use crate::{
+    identity::ExternalIdentity,
+    oauth::{JWKS_TTL_SECONDS, PendingAuthorization, begin_authorization, verify_id_token},
+};
+use app_config::{AppConfig, OAuthProviderConfig};
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use axum::http::StatusCode;
+use reqwest::{RequestBuilder, Url, header::ACCEPT};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::*;
+use uuid::Uuid;
+
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Endpoints of a provider, from discovery and/or the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: Option<String>,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    id_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProviderEmail {
+    email: String,
+    #[serde(default)]
+    primary: bool,
+    #[serde(default)]
+    verified: bool,
+}
+
+fn unauthorized(message: &str) -> AppError {
+    AppError::new(message, StatusCode::UNAUTHORIZED, 0)
+}
+
+fn forbidden(message: &str) -> AppError {
+    AppError::new(message, StatusCode::FORBIDDEN, 2)
+}
+
+fn discovery_key(issuer: &str) -> String {
+    format!("oauth:discovery:{}", issuer)
+}
+
+/// Finds a configured provider by name
+pub fn find_provider<'a>(
+    config: &'a AppConfig,
+    name: &str,
+) -> Result<&'a OAuthProviderConfig, AppError> {
+    match config.oauth_providers.iter().find(|p| p.name == name) {
+        None => Err(AppError::new(
+            "Unknown sign-in provider!",
+            StatusCode::NOT_FOUND,
+            3,
+        )),
+        Some(provider) => Ok(provider),
+    }
+}
+
+fn client() -> Result<reqwest::Client, AppError> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .map_err(|e| {
+            error!("Cannot build HTTP client: {}", e);
+            AppError::internal("OAuth request failed")
+        })
+}
+
+async fn send_json(request: RequestBuilder, what: &str) -> Result<Value, AppError> {
+    let resp = match request.header(ACCEPT, "application/json").send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            error!("Error sending {} request: {}", what, err);
+            return Err(AppError::internal("OAuth request failed"));
+        }
+    };
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        error!("{} endpoint returned {}: {}", what, status, text);
+        return Err(AppError::internal("OAuth request failed"));
+    }
+    match resp.json::<Value>().await {
+        Ok(value) => Ok(value),
+        Err(err) => {
+            error!("Error parsing {} response: {}", what, err);
+            Err(AppError::internal("OAuth request failed"))
+        }
+    }
+}
+
+async fn discover(redis: &RdPool, issuer: &str) -> Result<ProviderMetadata, AppError> {
+    let key = discovery_key(issuer);
+    if let Some(cached) = Redis::get::<Option<String>>(redis, &key).await?
+        && let Ok(metadata) = serde_json::from_str::<ProviderMetadata>(&cached)
+    {
+        return Ok(metadata);
+    }
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let value = send_json(client()?.get(&url), "Discovery").await?;
+    let metadata = serde_json::from_value::<ProviderMetadata>(value)?;
+    Redis::set_ex(
+        redis,
+        &key,
+        &serde_json::to_string(&metadata)?,
+        JWKS_TTL_SECONDS,
+    )
+    .await?;
+    Ok(metadata)
+}
+
+/// Resolves the endpoints of a provider; explicit config values win over discovery
+pub async fn provider_metadata(
+    redis: &RdPool,
+    provider: &OAuthProviderConfig,
+) -> Result<ProviderMetadata, AppError> {
+    let discovered = match &provider.issuer {
+        None => ProviderMetadata::default(),
+        Some(issuer) => discover(redis, issuer).await?,
+    };
+    Ok(ProviderMetadata {
+        issuer: provider.issuer.clone().or(discovered.issuer),
+        authorization_endpoint: provider
+            .authorization_endpoint
+            .clone()
+            .or(discovered.authorization_endpoint),
+        token_endpoint: provider
+            .token_endpoint
+            .clone()
+            .or(discovered.token_endpoint),
+        userinfo_endpoint: provider
+            .userinfo_endpoint
+            .clone()
+            .or(discovered.userinfo_endpoint),
+        jwks_uri: provider.jwks_uri.clone().or(discovered.jwks_uri),
+    })
+}
+
+fn required<'a>(
+    endpoint: &'a Option<String>,
+    provider: &str,
+    name: &str,
+) -> Result<&'a str, AppError> {
+    match endpoint {
+        None => {
+            error!("OAuth provider {} has no {}", provider, name);
+            Err(AppError::internal("Invalid OAuth provider configuration"))
+        }
+        Some(endpoint) => Ok(endpoint),
+    }
+}
+
+/// Starts an authorization attempt and returns the URL to send the browser to, with the
+/// `state` of the attempt. `link_user_id` links the identity to that user instead of
+/// signing in with it.
+pub async fn authorization_url(
+    redis: &RdPool,
+    provider: &OAuthProviderConfig,
+    link_user_id: Option<Uuid>,
+) -> Result<(Url, String), AppError> {
+    let metadata = provider_metadata(redis, provider).await?;
+    let endpoint = required(
+        &metadata.authorization_endpoint,
+        &provider.name,
+        "authorization endpoint",
+    )?;
+    let pending = begin_authorization(redis, &provider.name, link_user_id).await?;
+    let scope = provider.scopes.join(" ");
+    let code_challenge = pending.code_challenge();
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", provider.redirect_url.as_str()),
+        ("scope", scope.as_str()),
+        ("state", pending.state.as_str()),
+        ("code_challenge", code_challenge.as_str()),
+        ("code_challenge_method", "S256"),
+    ];
+    if provider.scopes.iter().any(|s| s == "openid") {
+        params.push(("nonce", pending.nonce.as_str()));
+    }
+    let url = Url::parse_with_params(endpoint, &params).map_err(|e| {
+        error!(
+            "Invalid authorization endpoint of {}: {}",
+            &provider.name, e
+        );
+        AppError::internal("Invalid OAuth provider configuration")
+    })?;
+    Ok((url, pending.state))
+}
+
+fn claim_string(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    match claims.get(name) {
+        Some(Value::String(s)) if !s.is_empty() => Some(s.clone()),
+        // GitHub user ids are numbers
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn claim_bool(claims: &Map<String, Value>, name: &str) -> bool {
+    match claims.get(name) {
+        Some(Value::Bool(b)) => *b,
+        // Some providers send "true" as a string
+        Some(Value::String(s)) => s == "true",
+        _ => false,
+    }
+}
+
+/// Exchanges the authorization code of a redeemed attempt and returns the verified identity
+pub async fn complete_authorization(
+    redis: &RdPool,
+    provider: &OAuthProviderConfig,
+    pending: &PendingAuthorization,
+    code: &str,
+) -> Result<ExternalIdentity, AppError> {
+    let metadata = provider_metadata(redis, provider).await?;
+    let token_endpoint = required(&metadata.token_endpoint, &provider.name, "token endpoint")?;
+    let client = client()?;
+
+    // --- Step 1: Exchange the code; the PKCE verifier proves this server started the attempt ---
+    let params = [
+        ("code", code),
+        ("client_id", provider.client_id.as_str()),
+        ("client_secret", provider.client_secret.as_str()),
+        ("redirect_uri", provider.redirect_url.as_str()),
+        ("grant_type", "authorization_code"),
+        ("code_verifier", pending.code_verifier.as_str()),
+    ];
+    let token = send_json(client.post(token_endpoint).form(&params), "Token").await?;
+    let token = serde_json::from_value::<TokenResponse>(token)?;
+
+    // --- Step 2: Verify the ID token against the provider's published keys ---
+    let mut claims = Map::new();
+    if let Some(id_token) = &token.id_token {
+        let jwks_uri = required(&metadata.jwks_uri, &provider.name, "jwks_uri")?;
+        let issuer = required(&metadata.issuer, &provider.name, "issuer")?;
+        // Google also issues tokens with the scheme-less issuer
+        let bare_issuer = issuer.trim_start_matches("https://");
+        claims = verify_id_token(
+            redis,
+            jwks_uri,
+            &[issuer, bare_issuer],
+            &provider.client_id,
+            &pending.nonce,
+            id_token,
+        )
+        .await?;
+    } else if provider.scopes.iter().any(|s| s == "openid") {
+        warn!("{} did not return an ID token", &provider.name);
+        return Err(unauthorized("Invalid ID token!"));
+    }
+
+    // --- Step 3: Complete the claims from the userinfo endpoint ---
+    if let (Some(userinfo), Some(access_token)) = (&metadata.userinfo_endpoint, &token.access_token)
+    {
+        let info = send_json(client.get(userinfo).bearer_auth(access_token), "Userinfo").await?;
+        let info = match info {
+            Value::Object(info) => info,
+            _ => return Err(AppError::internal("Failed to parse userinfo")),
+        };
+        if let (Some(sub), Some(info_sub)) = (claims.get("sub"), info.get("sub"))
+            && sub != info_sub
+        {
+            warn!(
+                "Userinfo subject of {} does not match the ID token",
+                &provider.name
+            );
+            return Err(unauthorized("Invalid ID token!"));
+        }
+        // Verified ID token claims win over userinfo
+        for (key, value) in info {
+            claims.entry(key).or_insert(value);
+        }
+    }
+
+    // --- Step 4: Map the claims to an identity ---
+    let mapping = &provider.claims;
+    let subject = match claim_string(&claims, &mapping.subject) {
+        None => {
+            warn!("{} returned no subject", &provider.name);
+            return Err(unauthorized("Invalid ID token!"));
+        }
+        Some(subject) => subject,
+    };
+    let mut email = claim_string(&claims, &mapping.email);
+    let mut email_verified = match &mapping.email_verified {
+        None => false,
+        Some(name) => claim_bool(&claims, name),
+    };
+    if let (Some(emails), Some(access_token)) = (&provider.emails_endpoint, &token.access_token) {
+        let list = send_json(client.get(emails).bearer_auth(access_token), "Emails").await?;
+        let list = serde_json::from_value::<Vec<ProviderEmail>>(list)?;
+        if let Some(primary) = list.into_iter().find(|e| e.primary && e.verified) {
+            email = Some(primary.email);
+            email_verified = true;
+        }
+    }
+    let email = match email {
+        None => return Err(forbidden("Account has no email!")),
+        Some(email) => email,
+    };
+    if !email_verified {
+        warn!(
+            "Rejected {} sign-in with unverified email {}",
+            &provider.name, &email
+        );
+        return Err(forbidden("Email is not verified!"));
+    }
+    let name = claim_string(&claims, &mapping.name).unwrap_or_else(|| email.clone());
+    Ok(ExternalIdentity {
+        provider: provider.name.clone(),
+        subject,
+        email,
+        name,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::oauth::{pkce_challenge, take_authorization};
+    use app_config::OAuthClaimMapping;
+    use app_cryptography::base64::base64_encode_from_u8;
+    use axum::{
+        Form, Json, Router,
+        extract::{Query, State},
+        response::Redirect,
+        routing::{get, post},
+    };
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use rsa::{
+        RsaPrivateKey,
+        pkcs1::{EncodeRsaPrivateKey, LineEnding},
+        traits::PublicKeyParts,
+    };
+    use serde_json::json;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    const CLIENT_ID: &str = "mock-client";
+    const MOCK_EMAIL: &str = "mock.user@example.com";
+
+    /// A minimal OpenID provider: discovery, authorize, token, userinfo and JWKS
+    struct MockIssuer {
+        issuer: String,
+        key: EncodingKey,
+        jwks: Value,
+        email_verified: bool,
+        // code -> (nonce, code_challenge)
+        codes: Mutex<HashMap<String, (String, String)>>,
+    }
+
+    async fn mock_discovery(State(mock): State<Arc<MockIssuer>>) -> Json<Value> {
+        Json(json!({
+            "issuer": &mock.issuer,
+            "authorization_endpoint": format!("{}/authorize", &mock.issuer),
+            "token_endpoint": format!("{}/token", &mock.issuer),
+            "userinfo_endpoint": format!("{}/userinfo", &mock.issuer),
+            "jwks_uri": format!("{}/jwks", &mock.issuer),
+        }))
+    }
+
+    async fn mock_jwks(State(mock): State<Arc<MockIssuer>>) -> Json<Value> {
+        Json(mock.jwks.clone())
+    }
+
+    async fn mock_authorize(
+        State(mock): State<Arc<MockIssuer>>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Redirect {
+        let code = format!("code-{}", mock.codes.lock().unwrap().len());
+        mock.codes.lock().unwrap().insert(
+            code.clone(),
+            (query["nonce"].clone(), query["code_challenge"].clone()),
+        );
+        Redirect::to(&format!(
+            "{}?code={}&state={}",
+            query["redirect_uri"], code, query["state"]
+        ))
+    }
+
+    async fn mock_token(
+        State(mock): State<Arc<MockIssuer>>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<Json<Value>, StatusCode> {
+        let (nonce, challenge) = match mock.codes.lock().unwrap().remove(&form["code"]) {
+            None => return Err(StatusCode::BAD_REQUEST),
+            Some(v) => v,
+        };
+        if pkce_challenge(&form["code_verifier"]) != challenge {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let claims = json!({
+            "iss": &mock.issuer,
+            "sub": "mock-subject",
+            "aud": CLIENT_ID,
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "nonce": nonce,
+            "email": MOCK_EMAIL,
+            "email_verified": mock.email_verified,
+        });
+        let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some("mock-key".into());
+        let id_token = encode(&header, &claims, &mock.key).unwrap();
+        Ok(Json(json!({
+            "access_token": "mock-access-token",
+            "token_type": "Bearer",
+            "id_token": id_token,
+        })))
+    }
+
+    async fn mock_userinfo() -> Json<Value> {
+        Json(json!({ "sub": "mock-subject", "name": "Mock User" }))
+    }
+
+    async fn start_mock_issuer(email_verified: bool) -> String {
+        let private_key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let pem = private_key.to_pkcs1_pem(LineEnding::LF).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let mock = Arc::new(MockIssuer {
+            issuer: issuer.clone(),
+            key: EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap(),
+            jwks: json!({ "keys": [{
+                "kty": "RSA",
+                "use": "sig",
+                "alg": "RS256",
+                "kid": "mock-key",
+                "n": base64_encode_from_u8(&private_key.n().to_bytes_be()),
+                "e": base64_encode_from_u8(&private_key.e().to_bytes_be()),
+            }]}),
+            email_verified,
+            codes: Mutex::new(HashMap::new()),
+        });
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(mock_discovery))
+            .route("/authorize", get(mock_authorize))
+            .route("/token", post(mock_token))
+            .route("/userinfo", get(mock_userinfo))
+            .route("/jwks", get(mock_jwks))
+            .with_state(mock);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        issuer
+    }
+
+    fn mock_provider(issuer: &str) -> OAuthProviderConfig {
+        OAuthProviderConfig {
+            name: "mock".into(),
+            title: None,
+            issuer: Some(issuer.to_owned()),
+            client_id: CLIENT_ID.into(),
+            client_secret: "mock-secret".into(),
+            redirect_url: "http://localhost:9000/oauth/mock/callback".into(),
+            scopes: vec!["openid".into(), "email".into(), "profile".into()],
+            authorization_endpoint: None,
+            token_endpoint: None,
+            userinfo_endpoint: None,
+            jwks_uri: None,
+            emails_endpoint: None,
+            claims: OAuthClaimMapping::default(),
+        }
+    }
+
+    /// Follows the authorization URL like a browser and returns (code, state)
+    async fn authorize(url: &Url) -> (String, String) {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let resp = client.get(url.as_str()).send().await.unwrap();
+        let location = resp.headers()["location"].to_str().unwrap().to_owned();
+        let location = Url::parse(&location).unwrap();
+        let params: HashMap<String, String> = location.query_pairs().into_owned().collect();
+        (params["code"].clone(), params["state"].clone())
+    }
+
+    // cargo test --package auth check_oidc_mock_issuer -- --nocapture
+    #[test]
+    fn check_oidc_mock_issuer() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let config = AppConfig::new();
+            let redis = Redis::new(&config.redis_url).unwrap();
+            let issuer = start_mock_issuer(true).await;
+            let provider = mock_provider(&issuer);
+
+            let (url, _) = authorization_url(&redis, &provider, None).await.unwrap();
+            let (code, state) = authorize(&url).await;
+            let pending = take_authorization(&redis, "mock", &state).await.unwrap();
+            let identity = complete_authorization(&redis, &provider, &pending, &code)
+                .await
+                .unwrap();
+            println!("{:?}", &identity);
+            assert_eq!(identity.subject, "mock-subject");
+            assert_eq!(identity.email, MOCK_EMAIL);
+            assert_eq!(identity.name, "Mock User");
+
+            // A state can only be redeemed once
+            assert!(take_authorization(&redis, "mock", &state).await.is_err());
+        });
+    }
+
+    // cargo test --package auth check_oidc_unverified_email -- --nocapture
+    #[test]
+    fn check_oidc_unverified_email() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let config = AppConfig::new();
+            let redis = Redis::new(&config.redis_url).unwrap();
+            let issuer = start_mock_issuer(false).await;
+            let provider = mock_provider(&issuer);
+
+            let (url, _) = authorization_url(&redis, &provider, None).await.unwrap();
+            let (code, state) = authorize(&url).await;
+            let pending = take_authorization(&redis, "mock", &state).await.unwrap();
+            let res = complete_authorization(&redis, &provider, &pending, &code).await;
+            assert_eq!(res.unwrap_err().status, StatusCode::FORBIDDEN);
+        });
+    }
+}
@@ -0,0 +1,91 @@
+//! Rules for new passwords (`password` in the config): length bounds, not the account email,
+//! and not in the local list of breached passwords, which is loaded once at startup.
+
+use app_config::PasswordConfig;
+use app_error::AppError;
+use axum::http::StatusCode;
+use std::{collections::HashSet, fs, io};
+use tracing::*;
+
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    breached: HashSet<String>,
+}
+
+fn rejected(message: String) -> AppError {
+    AppError::new(message, StatusCode::BAD_REQUEST, 6)
+}
+
+impl PasswordPolicy {
+    pub fn new(min_length: usize, max_length: usize, breached: HashSet<String>) -> Self {
+        Self {
+            min_length,
+            max_length,
+            breached,
+        }
+    }
+
+    /// Reads `breached_list` (one password per line, compared case-insensitively)
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, io::Error> {
+        let breached = match &config.breached_list {
+            None => HashSet::new(),
+            Some(path) => {
+                let list: HashSet<String> = fs::read_to_string(path)?
+                    .lines()
+                    .map(|l| l.trim().to_lowercase())
+                    .filter(|l| !l.is_empty())
+                    .collect();
+                info!("Loaded {} breached passwords from {}", list.len(), path);
+                list
+            }
+        };
+        Ok(Self::new(config.min_length, config.max_length, breached))
+    }
+
+    /// Checks a new password of the account `email`; fails with `400` naming the broken rule
+    pub fn validate(&self, password: &str, email: &str) -> Result<(), AppError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(rejected(format!(
+                "Password must be at least {} characters long!",
+                self.min_length
+            )));
+        }
+        if length > self.max_length {
+            return Err(rejected(format!(
+                "Password must be at most {} characters long!",
+                self.max_length
+            )));
+        }
+        let lower = password.trim().to_lowercase();
+        if lower == email.trim().to_lowercase() {
+            return Err(rejected("Password must not be your email address!".into()));
+        }
+        if self.breached.contains(&lower) {
+            return Err(rejected(
+                "Password appears in a list of breached passwords!".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_password_policy -- --nocapture
+    #[test]
+    fn check_password_policy() {
+        let policy = PasswordPolicy::new(8, 16, HashSet::from(["password123".to_string()]));
+        assert!(policy.validate("correct horse", "test@unit").is_ok());
+        assert_eq!(policy.validate("short", "test@unit").unwrap_err().code, 6);
+        assert!(
+            policy
+                .validate("this one is far too long", "test@unit")
+                .is_err()
+        );
+        assert!(policy.validate("Test@Unit.com", "test@unit.com").is_err());
+        assert!(policy.validate("PASSWORD123", "test@unit").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Self-service password reset.
+//!
+//! A request mails a random token that is stored as a SHA-256 hash in `auth.password_resets`,
+//! valid for `mail.password_reset_minutes` and redeemable once. Requesting a new token drops
+//! the unused ones. A successful reset revokes every session of the user.
+
+use crate::{password_policy::PasswordPolicy, session::revoke_all_sessions};
+use app_config::AppConfig;
+use app_cryptography::{base64::base64_encode_from_u8, hash::Hasher};
+use app_error::AppError;
+use app_mailer::{Mail, MailSender};
+use app_redis::RdPool;
+use app_schema::auth::{password_resets::PasswordReset, users::User};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use tracing::*;
+
+const INVALID_TOKEN_MESSAGE: &str = "Invalid or expired reset token!";
+
+pub(crate) fn hash_reset_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub(crate) fn generate_reset_token() -> String {
+    let mut bytes = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64_encode_from_u8(&bytes)
+}
+
+/// Mails a reset link to `email`. Unknown addresses are only logged, so callers can answer
+/// the same way whether or not an account exists.
+pub async fn request_password_reset(
+    pg: &Pool<Postgres>,
+    mailer: &dyn MailSender,
+    config: &AppConfig,
+    email: &str,
+) -> Result<(), AppError> {
+    let user =
+        match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+            .bind(email)
+            .fetch_optional(pg)
+            .await?
+        {
+            None => {
+                info!("Password reset requested for unknown email {}", email);
+                return Ok(());
+            }
+            Some(user) if !user.is_active() => {
+                info!("Password reset requested for inactive user {}", email);
+                return Ok(());
+            }
+            Some(user) => user,
+        };
+    let token = generate_reset_token();
+    let minutes = config.mail.password_reset_minutes;
+    let mut tx = pg.begin().await?;
+    sqlx::query(PasswordReset::delete_by_user_query())
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(PasswordReset::insert_query())
+        .bind(user.id)
+        .bind(hash_reset_token(&token))
+        .bind(Utc::now() + Duration::minutes(minutes))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    let link = format!(
+        "{}/password/reset?token={}",
+        config.mail.base_url.trim_end_matches('/'),
+        &token
+    );
+    mailer
+        .send(&Mail {
+            to: user.email.clone(),
+            subject: "Reset your password".into(),
+            body: format!(
+                "Hello {},\n\nOpen the link below to choose a new password. It expires in {} minutes and can be used once.\n\n{}\n\nIf you did not request a reset, you can ignore this mail.\n",
+                &user.name, minutes, link
+            ),
+        })
+        .await?;
+    info!("Password reset mailed to {}", &user.email);
+    Ok(())
+}
+
+/// Redeems `token`, sets the new password, revokes all sessions of the user and returns them.
+/// A password rejected by the policy leaves the token unused.
+pub async fn reset_password(
+    pg: &Pool<Postgres>,
+    redis: &RdPool,
+    hasher: &Hasher,
+    policy: &PasswordPolicy,
+    token: &str,
+    new_password: &str,
+) -> Result<User, AppError> {
+    let invalid = || AppError::new(INVALID_TOKEN_MESSAGE, StatusCode::BAD_REQUEST, 6);
+    let mut tx = pg.begin().await?;
+    let reset = match sqlx::query_as::<_, PasswordReset>(PasswordReset::consume_query())
+        .bind(hash_reset_token(token))
+        .fetch_optional(&mut *tx)
+        .await?
+    {
+        None => return Err(invalid()),
+        Some(reset) => reset,
+    };
+    let user = match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+        .bind(reset.user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+    {
+        Some(user) if user.is_active() => user,
+        _ => return Err(invalid()),
+    };
+    policy.validate(new_password, &user.email)?;
+    let hash_password = hasher.hash(new_password)?;
+    sqlx::query(User::change_password_query())
+        .bind(&hash_password)
+        .bind(&user.email)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    let revoked = revoke_all_sessions(redis, &user.email).await?;
+    info!(
+        "Password of {} reset, {} sessions revoked",
+        &user.email, revoked
+    );
+    Ok(user)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_reset_token -- --nocapture
+    #[test]
+    fn check_reset_token() {
+        let token = generate_reset_token();
+        println!("{}", &token);
+        assert_eq!(hash_reset_token(&token).len(), 64);
+        assert_ne!(generate_reset_token(), token);
+    }
+}
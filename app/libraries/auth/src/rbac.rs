@@ -0,0 +1,98 @@
+//! Role-based access control: roles live in `auth.roles` / `auth.user_roles` and their
+//! permissions are embedded in the access token claims at login and refresh.
+
+use app_error::AppError;
+use app_schema::auth::roles::Role;
+use axum::http::StatusCode;
+use sqlx::{PgConnection, Pool, Postgres};
+use uuid::Uuid;
+
+pub const USERS_READ: &str = "users:read";
+pub const USERS_WRITE: &str = "users:write";
+pub const SESSIONS_ADMIN: &str = "sessions:admin";
+pub const CUSTOMERS_READ: &str = "customers:read";
+pub const KB_READ: &str = "kb:read";
+pub const KB_WRITE: &str = "kb:write";
+pub const AGENT_USE: &str = "agent:use";
+pub const AUDIT_READ: &str = "audit:read";
+pub const MCP_ADMIN: &str = "mcp:admin";
+
+/// Role given to users created without an explicit role list
+pub const DEFAULT_ROLE: &str = "viewer";
+
+/// Role names and the union of their permissions
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+}
+
+impl Access {
+    fn from_roles(roles: Vec<Role>) -> Self {
+        let mut permissions: Vec<String> = roles
+            .iter()
+            .flat_map(|r| r.permissions.iter().cloned())
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+        Self {
+            roles: roles.into_iter().map(|r| r.name).collect(),
+            permissions,
+        }
+    }
+}
+
+/// Loads the access of a user by id; ids that are not in `auth.users` get no access
+pub async fn load_access(pg: &Pool<Postgres>, user_id: &str) -> Result<Access, AppError> {
+    let user_id = match Uuid::parse_str(user_id) {
+        Ok(id) => id,
+        Err(_) => return Ok(Access::default()),
+    };
+    let roles = sqlx::query_as::<_, Role>(Role::select_by_user_query())
+        .bind(user_id)
+        .fetch_all(pg)
+        .await?;
+    Ok(Access::from_roles(roles))
+}
+
+/// Loads the access of a user by email
+pub async fn load_access_by_email(pg: &Pool<Postgres>, email: &str) -> Result<Access, AppError> {
+    let roles = sqlx::query_as::<_, Role>(Role::select_by_email_query())
+        .bind(email)
+        .fetch_all(pg)
+        .await?;
+    Ok(Access::from_roles(roles))
+}
+
+/// Replaces the roles of a user; run it inside a transaction
+pub async fn replace_roles(
+    conn: &mut PgConnection,
+    email: &str,
+    roles: &[String],
+) -> Result<(), AppError> {
+    let mut roles = roles.to_vec();
+    roles.sort();
+    roles.dedup();
+    sqlx::query(Role::clear_by_email_query())
+        .bind(email)
+        .execute(&mut *conn)
+        .await?;
+    let assigned = sqlx::query(Role::assign_by_email_query())
+        .bind(email)
+        .bind(&roles)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    if assigned != roles.len() as u64 {
+        return Err(AppError::new(
+            "Unknown user or role!",
+            StatusCode::BAD_REQUEST,
+            5,
+        ));
+    }
+    Ok(())
+}
+
+pub fn has_permission(permissions: &[String], permission: &str) -> bool {
+    permissions.iter().any(|p| p == permission)
+}
@@ -0,0 +1,613 @@
+//! Login sessions: issuing access/refresh token pairs and rotating refresh tokens.
+//!
+//! Every login creates a session family identified by a random `session` number.
+//! The latest refresh token of the family is kept in Redis under `email:session`;
+//! presenting any older refresh token of the same family is treated as token theft
+//! and revokes the whole family. Rotation is a compare-and-swap of the record, and the
+//! token it replaced is still accepted for [`REFRESH_GRACE_SECONDS`], so two tabs (or a
+//! retried request) refreshing at once get the same new refresh token instead of a revoke.
+//!
+//! Session records expire in Redis: `redis_session` is the absolute lifetime counted
+//! from login, and a session unused for `redis_session_idle` seconds ends earlier (0 turns
+//! the idle timeout off). Logins and refreshes restart the idle window; with
+//! `redis_session_sliding` every authenticated request does, through [`touch_session`].
+//! The window never reaches past the absolute lifetime.
+//!
+//! A session is alive as long as its Redis record exists; revoking a session deletes
+//! the record, which also stops its access token at `web_auth_middleware`.
+
+use crate::rbac::{Access, load_access};
+use app_config::AppConfig;
+use app_cryptography::jwt::{
+    Algorithm, Claims, JwtKeySet, RedisInfo, generate_token, validate_token,
+};
+use app_dto::auth::login::PostLoginOutput;
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use axum::http::{HeaderMap, StatusCode, header::USER_AGENT};
+use chrono::{Duration, Utc};
+use rand::Rng;
+use sqlx::{Pool, Postgres};
+use std::cmp::Reverse;
+use std::net::{IpAddr, SocketAddr};
+use tracing::*;
+
+/// How long a rotated-out refresh token is still exchanged for the current one
+pub const REFRESH_GRACE_SECONDS: i64 = 30;
+
+/// Replaces the session record only if it is still the one the rotation read
+const ROTATE_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+    return 1
+end
+return 0
+"#;
+
+/// Identity stored in the tokens and the Redis session record
+#[derive(Debug, Clone)]
+pub struct SessionUser {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub source: String,
+    pub access: Access,
+}
+
+/// Where a login or refresh request came from
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub device: String,
+    pub ip: String,
+    pub user_agent: String,
+}
+
+/// `entry` is an address (`10.0.0.1`) or a network (`10.0.0.0/8`, `fd00::/8`)
+fn ip_matches(ip: IpAddr, entry: &str) -> bool {
+    let (net, prefix) = match entry.split_once('/') {
+        None => (entry, None),
+        Some((net, prefix)) => (net, Some(prefix)),
+    };
+    let net = match net.trim().parse::<IpAddr>() {
+        Err(_) => return false,
+        Ok(net) => net,
+    };
+    let (ip, net, bits) = match (ip.to_canonical(), net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => (u32::from(ip) as u128, u32::from(net) as u128, 32),
+        (IpAddr::V6(ip), IpAddr::V6(net)) => (u128::from(ip), u128::from(net), 128),
+        _ => return false,
+    };
+    let prefix = match prefix.map(|p| p.trim().parse::<u32>()) {
+        None => bits,
+        Some(Ok(prefix)) if prefix <= bits => prefix,
+        Some(_) => return false,
+    };
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    (ip >> shift) == (net >> shift)
+}
+
+fn is_trusted_proxy(config: &AppConfig, ip: IpAddr) -> bool {
+    config.trusted_proxies.iter().any(|e| ip_matches(ip, e))
+}
+
+/// The client address: the peer itself, unless the peer is one of `trusted_proxies`. Then
+/// `X-Forwarded-For` is read from the right, skipping further trusted proxies, since only
+/// the entries they appended can be believed.
+pub fn client_ip(config: &AppConfig, headers: &HeaderMap, peer: SocketAddr) -> String {
+    let peer_ip = peer.ip().to_canonical();
+    if !is_trusted_proxy(config, peer_ip) {
+        return peer_ip.to_string();
+    }
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|v| v.trim().parse::<IpAddr>().ok())
+        .collect();
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !is_trusted_proxy(config, **ip))
+        .or(forwarded.first())
+        .copied()
+        .unwrap_or(peer_ip)
+        .to_string()
+}
+
+impl ClientInfo {
+    /// Reads the client address (see `client_ip`) and the `User-Agent` header
+    pub fn new(
+        config: &AppConfig,
+        headers: &HeaderMap,
+        peer: SocketAddr,
+        device: Option<String>,
+    ) -> Self {
+        let ip = client_ip(config, headers, peer);
+        let user_agent = headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        Self {
+            device: device.unwrap_or_default(),
+            ip,
+            user_agent,
+        }
+    }
+}
+
+/// Redis key of a session family
+pub fn session_key(email: &str, session: u64) -> String {
+    format!("{}:{}", email, session)
+}
+
+/// Checks that the session has not been revoked or expired
+pub async fn session_exists(redis: &RdPool, email: &str, session: u64) -> Result<bool, AppError> {
+    Ok(Redis::exists(redis, &session_key(email, session)).await?)
+}
+
+/// Checks that the session is alive like [`session_exists`], and with
+/// `redis_session_sliding` restarts its idle window
+pub async fn touch_session(
+    config: &AppConfig,
+    redis: &RdPool,
+    email: &str,
+    session: u64,
+) -> Result<bool, AppError> {
+    if !config.redis_session_sliding || config.redis_session_idle == 0 {
+        return session_exists(redis, email, session).await;
+    }
+    let redis_info = match get_session_info(redis, email, session).await? {
+        None => return Ok(false),
+        Some(redis_info) => redis_info,
+    };
+    // Records written before expiry tracking get their deadline at the next refresh
+    if redis_info.expires_at == 0 {
+        return Ok(true);
+    }
+    match session_ttl(config, redis_info.expires_at) {
+        None => Ok(false),
+        Some(ttl) => {
+            let ttl = i64::try_from(ttl).unwrap_or(i64::MAX);
+            Redis::expire(redis, &session_key(email, session), ttl).await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Returns the live sessions of a user, most recently seen first
+pub async fn list_sessions(redis: &RdPool, email: &str) -> Result<Vec<RedisInfo>, AppError> {
+    let prefix = format!("{}:", email);
+    let pattern = format!("{}*", Redis::escape_pattern(&prefix));
+    let keys: Vec<String> = Redis::scan(redis, &pattern)
+        .await?
+        .into_iter()
+        // Skip keys of other namespaces that happen to share the prefix
+        .filter(|k| {
+            k.strip_prefix(&prefix)
+                .is_some_and(|s| s.parse::<u64>().is_ok())
+        })
+        .collect();
+    let mut sessions = Vec::new();
+    for key in keys {
+        // A session may expire between SCAN and GET
+        if let Some(info) = Redis::get::<Option<String>>(redis, &key).await? {
+            sessions.push(serde_json::from_str::<RedisInfo>(&info)?);
+        }
+    }
+    sessions.sort_by_key(|s| Reverse(s.last_seen));
+    Ok(sessions)
+}
+
+/// Returns one session of a user
+pub async fn get_session_info(
+    redis: &RdPool,
+    email: &str,
+    session: u64,
+) -> Result<Option<RedisInfo>, AppError> {
+    match Redis::get::<Option<String>>(redis, &session_key(email, session)).await? {
+        None => Ok(None),
+        Some(info) => Ok(Some(serde_json::from_str::<RedisInfo>(&info)?)),
+    }
+}
+
+/// Revokes one session of a user; returns false when it did not exist
+pub async fn revoke_session(redis: &RdPool, email: &str, session: u64) -> Result<bool, AppError> {
+    let key = session_key(email, session);
+    if !Redis::exists(redis, &key).await? {
+        return Ok(false);
+    }
+    Redis::del(redis, vec![&key]).await?;
+    info!("Session {} of {} revoked", session, email);
+    Ok(true)
+}
+
+/// Revokes every session of a user and returns how many were removed
+pub async fn revoke_all_sessions(redis: &RdPool, email: &str) -> Result<usize, AppError> {
+    let sessions = list_sessions(redis, email).await?;
+    if sessions.is_empty() {
+        return Ok(0);
+    }
+    let keys: Vec<String> = sessions
+        .iter()
+        .map(|s| session_key(email, s.session))
+        .collect();
+    Redis::del(redis, keys.iter().map(|k| k.as_str()).collect()).await?;
+    info!("All {} sessions of {} revoked", keys.len(), email);
+    Ok(keys.len())
+}
+
+fn unauthorized() -> AppError {
+    AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0)
+}
+
+/// Starts a new session family and returns its first token pair
+pub async fn create_session(
+    config: &AppConfig,
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    user: SessionUser,
+    client: ClientInfo,
+) -> Result<PostLoginOutput, AppError> {
+    let session: u64 = {
+        let mut rng = rand::thread_rng();
+        rng.r#gen()
+    };
+    let family = Family {
+        session,
+        created_at: Utc::now().timestamp(),
+        expires_at: absolute_expiry(config),
+    };
+    let (tokens, redis_info, ttl) = issue_tokens(config, redis, keys, user, family, client).await?;
+    let redis_key = session_key(&redis_info.email, session);
+    Redis::set_ex(redis, &redis_key, &serde_json::to_string(&redis_info)?, ttl).await?;
+    Ok(tokens)
+}
+
+/// Whether `token` is the refresh token the record replaced less than
+/// [`REFRESH_GRACE_SECONDS`] ago
+fn in_grace(redis_info: &RedisInfo, token: &str) -> bool {
+    !redis_info.previous_token.is_empty()
+        && redis_info.previous_token == token
+        && Utc::now().timestamp() - redis_info.rotated_at <= REFRESH_GRACE_SECONDS
+}
+
+/// Exchanges a refresh token for a new access/refresh pair of the same session family.
+/// Reusing a refresh token that has already been rotated revokes the family, unless it was
+/// rotated within the grace period: then the current refresh token is returned with a new
+/// access token. Roles are reloaded so role changes apply from the next refresh.
+pub async fn rotate_session(
+    config: &AppConfig,
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    pg: &Pool<Postgres>,
+    refresh_token: &str,
+    mut client: ClientInfo,
+) -> Result<PostLoginOutput, AppError> {
+    let refresh_claim =
+        match validate_token::<Claims>(Algorithm::HS256, &config.jwt_refresh_key, refresh_token) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                debug!("Refresh token is not valid: {:?}", &e);
+                return Err(unauthorized());
+            }
+        };
+    let redis_key = session_key(&refresh_claim.email, refresh_claim.session);
+    let redis_info_str = match Redis::get::<Option<String>>(redis, &redis_key).await? {
+        None => {
+            info!(
+                "Refresh rejected; session {} of {} is revoked",
+                refresh_claim.session, refresh_claim.email
+            );
+            return Err(unauthorized());
+        }
+        Some(s) => s,
+    };
+    let redis_info: RedisInfo = serde_json::from_str(&redis_info_str)?;
+    let grace = in_grace(&redis_info, refresh_token);
+    if redis_info.token != refresh_token && !grace {
+        warn!(
+            "Refresh token reuse detected for {}; revoking session {}",
+            refresh_claim.email, refresh_claim.session
+        );
+        Redis::del(redis, vec![&redis_key]).await?;
+        return Err(unauthorized());
+    }
+    // Records written before expiry tracking have no deadline; start one now
+    let expires_at = match redis_info.expires_at {
+        0 => absolute_expiry(config),
+        x => x,
+    };
+    let created_at = match redis_info.created_at {
+        0 => Utc::now().timestamp(),
+        x => x,
+    };
+    // The device name is only reported at login
+    if client.device.is_empty() {
+        client.device = redis_info.device.clone();
+    }
+    let access = load_access(pg, &redis_info.id).await?;
+    let user = SessionUser {
+        id: redis_info.id.clone(),
+        name: redis_info.name.clone(),
+        email: redis_info.email.clone(),
+        source: redis_info.source.clone(),
+        access,
+    };
+    let family = Family {
+        session: refresh_claim.session,
+        created_at,
+        expires_at,
+    };
+    let (mut tokens, mut next, ttl) =
+        issue_tokens(config, redis, keys, user, family, client).await?;
+    if grace {
+        // Another request rotated this token moments ago; hand out its result
+        info!(
+            "Refresh token of {} was just rotated; returning the current one",
+            &redis_info.email
+        );
+        tokens.refresh_token = redis_info.token;
+        return Ok(tokens);
+    }
+    next.previous_token = refresh_token.to_owned();
+    next.rotated_at = Utc::now().timestamp();
+    let ttl = ttl.to_string();
+    let swapped = Redis::eval::<i64>(
+        redis,
+        ROTATE_SCRIPT,
+        vec![&redis_key],
+        vec![&redis_info_str, &serde_json::to_string(&next)?, &ttl],
+    )
+    .await?;
+    if swapped == 0 {
+        // A concurrent refresh of the same token won the swap; answer like it did
+        return match get_session_info(redis, &refresh_claim.email, refresh_claim.session).await? {
+            Some(current) if in_grace(&current, refresh_token) => {
+                tokens.refresh_token = current.token;
+                Ok(tokens)
+            }
+            _ => Err(unauthorized()),
+        };
+    }
+    info!("Rotated refresh token of {}", &next.email);
+    Ok(tokens)
+}
+
+fn absolute_expiry(config: &AppConfig) -> i64 {
+    Utc::now()
+        .timestamp()
+        .saturating_add(i64::try_from(config.redis_session).unwrap_or(i64::MAX))
+}
+
+/// Seconds the Redis record should live for; `None` when the session is over
+fn session_ttl(config: &AppConfig, expires_at: i64) -> Option<u64> {
+    let remaining = u64::try_from(expires_at - Utc::now().timestamp()).ok()?;
+    if remaining == 0 {
+        return None;
+    }
+    if config.redis_session_idle > 0 {
+        Some(remaining.min(config.redis_session_idle))
+    } else {
+        Some(remaining)
+    }
+}
+
+/// Session family the tokens belong to
+struct Family {
+    session: u64,
+    created_at: i64,
+    expires_at: i64,
+}
+
+/// Signs a token pair of the family and returns it with the session record to store and the
+/// record's TTL; storing the record is up to the caller
+async fn issue_tokens(
+    config: &AppConfig,
+    redis: &RdPool,
+    keys: &JwtKeySet,
+    user: SessionUser,
+    family: Family,
+    client: ClientInfo,
+) -> Result<(PostLoginOutput, RedisInfo, u64), AppError> {
+    let Family {
+        session,
+        created_at,
+        expires_at,
+    } = family;
+    let ttl = match session_ttl(config, expires_at) {
+        None => {
+            info!("Session {} of {} has expired", session, &user.email);
+            Redis::del(redis, vec![&session_key(&user.email, session)]).await?;
+            return Err(unauthorized());
+        }
+        Some(ttl) => ttl,
+    };
+    // Access Token
+    let iat = Utc::now().timestamp();
+    let exp = (Utc::now() + Duration::minutes(config.jwt_access_session_minutes)).timestamp();
+    let access_claim = Claims {
+        iat,
+        exp,
+        id: user.id.clone(),
+        name: user.name.clone(),
+        email: user.email.clone(),
+        session,
+        source: user.source.clone(),
+        roles: user.access.roles.clone(),
+        permissions: user.access.permissions.clone(),
+    };
+    // Refresh Token (never outlives the session record)
+    let exp = (Utc::now() + Duration::days(config.jwt_refresh_session_days))
+        .timestamp()
+        .min(expires_at);
+    let refresh_claim = Claims {
+        iat,
+        exp,
+        id: user.id.clone(),
+        name: user.name.clone(),
+        email: user.email.clone(),
+        session,
+        source: user.source.clone(),
+        roles: user.access.roles.clone(),
+        permissions: user.access.permissions.clone(),
+    };
+    // Generate Tokens; access tokens are verifiable with the published JWKS,
+    // refresh tokens are only ever read by this service
+    let access_token = keys.sign(&access_claim)?;
+    let refresh_token = generate_token(Algorithm::HS256, &config.jwt_refresh_key, &refresh_claim)?;
+    let redis_info = RedisInfo {
+        id: user.id,
+        name: user.name,
+        email: user.email,
+        session,
+        token: refresh_token.clone(),
+        source: user.source,
+        expires_at,
+        created_at,
+        last_seen: Utc::now().timestamp(),
+        device: client.device,
+        ip: client.ip,
+        user_agent: client.user_agent,
+        previous_token: String::new(),
+        rotated_at: 0,
+    };
+    let tokens = PostLoginOutput {
+        access_token,
+        refresh_token,
+    };
+    Ok((tokens, redis_info, ttl))
+}
+
+// cargo test --package auth check_session_rotation -- --nocapture
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+    // cargo test --package auth check_client_ip -- --nocapture
+    #[test]
+    fn check_client_ip() {
+        let v4 = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert!(ip_matches(v4("10.1.2.3"), "10.0.0.0/8"));
+        assert!(ip_matches(v4("10.1.2.3"), "10.1.2.3"));
+        assert!(!ip_matches(v4("11.1.2.3"), "10.0.0.0/8"));
+        assert!(ip_matches(v4("::ffff:10.1.2.3"), "10.0.0.0/8"));
+        assert!(ip_matches(v4("fd00::1"), "fd00::/8"));
+        assert!(!ip_matches(v4("10.1.2.3"), "fd00::/8"));
+        assert!(!ip_matches(v4("10.1.2.3"), "10.0.0.0/33"));
+
+        let mut config = AppConfig::new();
+        config.trusted_proxies = vec!["10.0.0.0/8".into()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            "1.1.1.1, 2.2.2.2, 10.0.0.2".parse().unwrap(),
+        );
+        // A client cannot pick its address by sending the header itself
+        let direct: SocketAddr = "3.3.3.3:5000".parse().unwrap();
+        assert_eq!(client_ip(&config, &headers, direct), "3.3.3.3");
+        // Behind the proxy, the last address not added by a trusted proxy is the client
+        let proxied: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        assert_eq!(client_ip(&config, &headers, proxied), "2.2.2.2");
+        assert_eq!(client_ip(&config, &HeaderMap::new(), proxied), "10.0.0.1");
+    }
+
+    // cargo test --package auth check_session_ttl -- --nocapture
+    #[test]
+    fn check_session_ttl() {
+        let mut config = AppConfig::new();
+        let in_an_hour = Utc::now().timestamp() + 3600;
+        // The idle timeout applies whether or not requests slide it
+        config.redis_session_idle = 60;
+        config.redis_session_sliding = false;
+        assert_eq!(session_ttl(&config, in_an_hour), Some(60));
+        config.redis_session_idle = 0;
+        assert!(session_ttl(&config, in_an_hour).is_some_and(|ttl| ttl > 60));
+        assert_eq!(session_ttl(&config, Utc::now().timestamp() - 1), None);
+    }
+
+    #[test]
+    fn check_session_rotation() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let config = AppConfig::new();
+            let redis = Redis::new(&config.redis_url).unwrap();
+            let keys = JwtKeySet::from_config(&config).unwrap();
+            let pg = PgPoolOptions::new()
+                .connect(&std::env::var("DATABASE_URL").unwrap())
+                .await
+                .unwrap();
+            let user = SessionUser {
+                id: "-1".to_owned(),
+                name: "test".to_owned(),
+                email: "test@unit".to_owned(),
+                source: "local".to_owned(),
+                access: Access::default(),
+            };
+            let first = create_session(&config, &redis, &keys, user, ClientInfo::default())
+                .await
+                .unwrap();
+            // Tokens carry second resolution; make sure the rotated pair differs
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            let second = rotate_session(
+                &config,
+                &redis,
+                &keys,
+                &pg,
+                &first.refresh_token,
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+            assert_ne!(first.refresh_token, second.refresh_token);
+            // A second refresh with the same token (another tab, a retry) gets the same pair
+            let concurrent = rotate_session(
+                &config,
+                &redis,
+                &keys,
+                &pg,
+                &first.refresh_token,
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(concurrent.refresh_token, second.refresh_token);
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            let third = rotate_session(
+                &config,
+                &redis,
+                &keys,
+                &pg,
+                &second.refresh_token,
+                ClientInfo::default(),
+            )
+            .await
+            .unwrap();
+            // Reusing a token older than the one just replaced revokes the family
+            let reused = rotate_session(
+                &config,
+                &redis,
+                &keys,
+                &pg,
+                &first.refresh_token,
+                ClientInfo::default(),
+            )
+            .await;
+            assert_eq!(reused.unwrap_err().status, StatusCode::UNAUTHORIZED);
+            let revoked = rotate_session(
+                &config,
+                &redis,
+                &keys,
+                &pg,
+                &third.refresh_token,
+                ClientInfo::default(),
+            )
+            .await;
+            assert!(revoked.is_err());
+        })
+    }
+}
@@ -0,0 +1,352 @@
+//! Brute-force protection for password checks (`login_throttle` in the config).
+//!
+//! Failures are counted in Redis per email (`login:fail:email:<email>`) and per client IP
+//! (`login:fail:ip:<ip>`). After every failure the email has to wait an exponentially growing
+//! delay (`login:wait:<email>`); after `max_failures` it is locked (`login:lock:<email>`) for
+//! `lockout_seconds` or until an admin unlocks it. An IP over `ip_max_failures` is refused
+//! until its counter expires. Every failure is also stored in `auth.login_failures`.
+//! Wrong second-factor codes count like wrong passwords, and the counters of an email are
+//! only reset when the whole login succeeded, so the password cannot be used to start new
+//! rounds of code guesses.
+
+use crate::session::ClientInfo;
+use app_config::LoginThrottleConfig;
+use app_cryptography::hash::Hasher;
+use app_error::AppError;
+use app_redis::{RdPool, Redis};
+use app_schema::auth::{login_failures::LoginFailure, users::User};
+use axum::http::StatusCode;
+use sqlx::{Pool, Postgres};
+use tracing::*;
+use uuid::Uuid;
+
+const TOO_MANY_ATTEMPTS_MESSAGE: &str = "Too many failed attempts, try again later!";
+const ACCOUNT_LOCKED_MESSAGE: &str = "Account is temporarily locked!";
+const AUTH_FAILD_MESSAGE: &str = "Provided information is wrong!";
+
+/// Why a password check failed, as stored in `auth.login_failures.reason`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureReason {
+    UnknownUser,
+    NoPassword,
+    WrongPassword,
+    Throttled,
+    Inactive,
+    WrongMfaCode,
+}
+
+impl FailureReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureReason::UnknownUser => "unknown_user",
+            FailureReason::NoPassword => "no_password",
+            FailureReason::WrongPassword => "wrong_password",
+            FailureReason::Throttled => "throttled",
+            FailureReason::Inactive => "inactive",
+            FailureReason::WrongMfaCode => "wrong_mfa_code",
+        }
+    }
+}
+
+/// Throttle state of one email, for admins
+#[derive(Debug, Clone, Default)]
+pub struct LockoutStatus {
+    pub failures: i64,
+    pub locked_for: Option<u64>, // Seconds
+    pub wait_for: Option<u64>,   // Seconds of backoff
+}
+
+fn email_failures_key(email: &str) -> String {
+    format!("login:fail:email:{}", email.to_lowercase())
+}
+
+fn ip_failures_key(ip: &str) -> String {
+    format!("login:fail:ip:{}", ip)
+}
+
+fn wait_key(email: &str) -> String {
+    format!("login:wait:{}", email.to_lowercase())
+}
+
+fn lock_key(email: &str) -> String {
+    format!("login:lock:{}", email.to_lowercase())
+}
+
+/// Remaining lifetime of a key, `None` when it does not exist
+async fn remaining(redis: &RdPool, key: &str) -> Result<Option<u64>, AppError> {
+    let ttl = Redis::ttl(redis, key).await?;
+    Ok(if ttl > 0 { Some(ttl as u64) } else { None })
+}
+
+/// Delay after the `failures`-th consecutive failure
+pub fn backoff_seconds(config: &LoginThrottleConfig, failures: i64) -> u64 {
+    if failures <= 0 {
+        return 0;
+    }
+    let exponent = (failures - 1).min(32) as u32;
+    config
+        .backoff_base_seconds
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(config.backoff_max_seconds)
+}
+
+/// Refuses the attempt with `429` while the email is locked or backing off, or the IP is over
+/// its limit. Call before checking the password.
+pub async fn check_login_allowed(
+    redis: &RdPool,
+    config: &LoginThrottleConfig,
+    email: &str,
+    ip: &str,
+) -> Result<(), AppError> {
+    if let Some(seconds) = remaining(redis, &lock_key(email)).await? {
+        return Err(AppError::too_many_requests(ACCOUNT_LOCKED_MESSAGE, seconds));
+    }
+    if let Some(seconds) = remaining(redis, &wait_key(email)).await? {
+        return Err(AppError::too_many_requests(
+            TOO_MANY_ATTEMPTS_MESSAGE,
+            seconds,
+        ));
+    }
+    let ip_key = ip_failures_key(ip);
+    if let Some(failures) = Redis::get::<Option<i64>>(redis, &ip_key).await?
+        && failures >= config.ip_max_failures
+    {
+        let seconds = remaining(redis, &ip_key).await?.unwrap_or(1);
+        return Err(AppError::too_many_requests(
+            TOO_MANY_ATTEMPTS_MESSAGE,
+            seconds,
+        ));
+    }
+    Ok(())
+}
+
+async fn count_failure(redis: &RdPool, key: &str, window: u64) -> Result<i64, AppError> {
+    let failures = Redis::incr(redis, key).await?;
+    // Every failure extends the window, so counters only reset after a quiet period
+    Redis::expire(redis, key, window as i64).await?;
+    Ok(failures)
+}
+
+/// Counts a failed password check, starts the backoff or lockout and stores the attempt
+pub async fn record_login_failure(
+    redis: &RdPool,
+    pg: &Pool<Postgres>,
+    config: &LoginThrottleConfig,
+    email: &str,
+    user_id: Option<Uuid>,
+    client: &ClientInfo,
+    reason: FailureReason,
+) -> Result<(), AppError> {
+    let failures = count_failure(redis, &email_failures_key(email), config.window_seconds).await?;
+    count_failure(redis, &ip_failures_key(&client.ip), config.window_seconds).await?;
+    if failures >= config.max_failures {
+        warn!(
+            "{} locked for {}s after {} failed logins",
+            email, config.lockout_seconds, failures
+        );
+        Redis::set_ex(redis, &lock_key(email), "1", config.lockout_seconds).await?;
+    } else {
+        let seconds = backoff_seconds(config, failures);
+        if seconds > 0 {
+            Redis::set_ex(redis, &wait_key(email), "1", seconds).await?;
+        }
+    }
+    // Losing the record must not turn a failed login into an internal error
+    if let Err(err) = sqlx::query(LoginFailure::insert_query())
+        .bind(email)
+        .bind(user_id)
+        .bind(&client.ip)
+        .bind(&client.user_agent)
+        .bind(reason.as_str())
+        .execute(pg)
+        .await
+    {
+        error!("Cannot record login failure of {}: {}", email, err);
+    }
+    Ok(())
+}
+
+/// Stores an attempt refused by the throttle; counters are not increased
+pub async fn record_throttled(pg: &Pool<Postgres>, email: &str, client: &ClientInfo) {
+    if let Err(err) = sqlx::query(LoginFailure::insert_query())
+        .bind(email)
+        .bind(None::<Uuid>)
+        .bind(&client.ip)
+        .bind(&client.user_agent)
+        .bind(FailureReason::Throttled.as_str())
+        .execute(pg)
+        .await
+    {
+        error!("Cannot record throttled login of {}: {}", email, err);
+    }
+}
+
+/// Resets the email counters once the login passed every factor (or a password check that
+/// needs no second factor). The IP counter is kept, so one valid account cannot be used to
+/// keep guessing others.
+pub async fn record_login_success(redis: &RdPool, email: &str) -> Result<(), AppError> {
+    Redis::del(redis, vec![&email_failures_key(email), &wait_key(email)]).await?;
+    Ok(())
+}
+
+pub async fn lockout_status(redis: &RdPool, email: &str) -> Result<LockoutStatus, AppError> {
+    Ok(LockoutStatus {
+        failures: Redis::get::<Option<i64>>(redis, &email_failures_key(email))
+            .await?
+            .unwrap_or(0),
+        locked_for: remaining(redis, &lock_key(email)).await?,
+        wait_for: remaining(redis, &wait_key(email)).await?,
+    })
+}
+
+/// Lifts the lockout and backoff of an email; returns false when it was not throttled
+pub async fn unlock_login(redis: &RdPool, email: &str) -> Result<bool, AppError> {
+    let status = lockout_status(redis, email).await?;
+    Redis::del(
+        redis,
+        vec![
+            &email_failures_key(email),
+            &wait_key(email),
+            &lock_key(email),
+        ],
+    )
+    .await?;
+    Ok(status.failures > 0 || status.locked_for.is_some() || status.wait_for.is_some())
+}
+
+/// Replaces the stored hash of a verified password; failures only cost the upgrade
+async fn rehash_password(pg: &Pool<Postgres>, hasher: &Hasher, user: &User, password: &str) {
+    let hashed = match hasher.hash(password) {
+        Ok(hashed) => hashed,
+        Err(err) => {
+            error!("Cannot rehash password of {}: {}", &user.email, err);
+            return;
+        }
+    };
+    match sqlx::query(User::change_password_query())
+        .bind(&hashed)
+        .bind(&user.email)
+        .execute(pg)
+        .await
+    {
+        Ok(_) => info!("Password hash of {} upgraded", &user.email),
+        Err(err) => error!("Cannot store rehashed password of {}: {}", &user.email, err),
+    }
+}
+
+/// Checks `password` of the local account `email` under the throttle and returns the user.
+/// All failures answer the same `403`, throttled attempts `429`. A valid password stored with
+/// an older algorithm or parameters is rehashed. The counters are not reset here: the caller
+/// calls `record_login_success` once no second factor is pending.
+pub async fn check_password(
+    redis: &RdPool,
+    pg: &Pool<Postgres>,
+    config: &LoginThrottleConfig,
+    hasher: &Hasher,
+    email: &str,
+    password: &str,
+    client: &ClientInfo,
+) -> Result<User, AppError> {
+    if let Err(err) = check_login_allowed(redis, config, email, &client.ip).await {
+        record_throttled(pg, email, client).await;
+        return Err(err);
+    }
+    let failed = || AppError::new(AUTH_FAILD_MESSAGE, StatusCode::FORBIDDEN, 2);
+    let user =
+        match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+            .bind(email)
+            .fetch_optional(pg)
+            .await?
+        {
+            Some(user) => user,
+            None => {
+                record_login_failure(
+                    redis,
+                    pg,
+                    config,
+                    email,
+                    None,
+                    client,
+                    FailureReason::UnknownUser,
+                )
+                .await?;
+                return Err(failed());
+            }
+        };
+    // Disabled and deleted users get the same answer as a wrong password
+    if !user.is_active() {
+        record_login_failure(
+            redis,
+            pg,
+            config,
+            email,
+            Some(user.id),
+            client,
+            FailureReason::Inactive,
+        )
+        .await?;
+        return Err(failed());
+    }
+    // Users provisioned by an external provider have no password
+    let password_hash = match &user.password_hash {
+        Some(h) => h,
+        None => {
+            record_login_failure(
+                redis,
+                pg,
+                config,
+                email,
+                Some(user.id),
+                client,
+                FailureReason::NoPassword,
+            )
+            .await?;
+            return Err(failed());
+        }
+    };
+    let verified = hasher.verify(password, password_hash)?;
+    if !verified.valid {
+        record_login_failure(
+            redis,
+            pg,
+            config,
+            email,
+            Some(user.id),
+            client,
+            FailureReason::WrongPassword,
+        )
+        .await?;
+        return Err(failed());
+    }
+    if verified.needs_rehash {
+        rehash_password(pg, hasher, &user, password).await;
+    }
+    Ok(user)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package auth check_backoff_seconds -- --nocapture
+    #[test]
+    fn check_backoff_seconds() {
+        let config = LoginThrottleConfig::default();
+        assert_eq!(backoff_seconds(&config, 0), 0);
+        assert_eq!(backoff_seconds(&config, 1), 1);
+        assert_eq!(backoff_seconds(&config, 2), 2);
+        assert_eq!(backoff_seconds(&config, 4), 8);
+        assert_eq!(backoff_seconds(&config, 10), config.backoff_max_seconds);
+        assert_eq!(
+            backoff_seconds(&config, i64::MAX),
+            config.backoff_max_seconds
+        );
+    }
+
+    // cargo test --package auth check_failure_reason -- --nocapture
+    #[test]
+    fn check_failure_reason() {
+        // Stored in `auth.login_failures.reason`
+        assert_eq!(FailureReason::WrongPassword.as_str(), "wrong_password");
+        assert_eq!(FailureReason::WrongMfaCode.as_str(), "wrong_mfa_code");
+    }
+}
@@ -7,9 +7,13 @@ use std::{env, fs};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub backend_bind: String, // 0.0.0.0:9000
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>, // IPs or CIDRs of reverse proxies allowed to set X-Forwarded-For
     pub asset_path: String,
-    pub mcp_bind: String,     // 0.0.0.0:9001
-    pub mcp_token: String,    // <TOKEN>
+    pub mcp_bind: String,  // 0.0.0.0:9001
+    pub mcp_token: String, // <TOKEN>, sent by the agent; accepted as MCP client `default`
+    #[serde(default)]
+    pub mcp_clients: Vec<McpClientConfig>, // Static MCP clients; more are managed at /auth/mcp_clients
     pub mcp_base_url: String, // For Agent Cli
     pub llm_base_url: String,
     pub llm_token: String,
@@ -20,22 +24,318 @@ pub struct AppConfig {
     pub agent_app_name: String,
     pub agent_description: String,
     pub agent_instruction: String,
+    #[serde(default)]
+    pub agents: Vec<AgentConfig>, // Named agents; without any, one agent is built from agent_*
+    #[serde(default)]
+    pub agent_approval: AgentApprovalConfig,
     pub log_level: LogLevel, // Debug, Info, Warn, Error, Trace
     pub pg_connection: usize,
     pub redis_url: String,  // redis://127.0.0.1:6379
-    pub redis_session: u64, // 10000
-    pub jwt_access_key: String,
+    pub redis_session: u64, // 2592000 (absolute session lifetime in seconds)
+    #[serde(default)]
+    pub redis_session_idle: u64, // 604800 (idle timeout in seconds; 0, the default, disables)
+    #[serde(default)]
+    pub redis_session_sliding: bool, // Also renew the idle window on every request, not only on refresh
     pub jwt_access_session_minutes: i64,
     pub jwt_refresh_key: String,
     pub jwt_refresh_session_days: i64,
+    #[serde(default = "default_jwt_access_algorithm")]
+    pub jwt_access_algorithm: String, // RS256 (default) or EdDSA; access tokens are signed with rsa_private_key
+    #[serde(default = "default_jwt_key_id")]
+    pub jwt_key_id: String, // `kid` of rsa_private_key / rsa_public_key; "default" when unset
+    #[serde(default)]
+    pub jwt_previous_keys: Vec<JwtPublicKeyConfig>, // Retired keys accepted during rotation
     pub rsa_private_key: String,
     pub rsa_public_key: String,
-    pub google_client_id: String,
-    pub google_client_secret: String,
-    pub google_redirect: String,
-    pub google_auth_url: String,
-    pub google_token_url: String,
-    pub google_userinfo_url: String,
+    #[serde(default)]
+    pub oauth_providers: Vec<OAuthProviderConfig>, // External sign-in at /oauth/{name}/auth
+    #[serde(default)]
+    pub mfa_issuer: Option<String>, // Shown in authenticator apps; defaults to agent_app_name
+    #[serde(default)]
+    pub mail: MailConfig,
+    #[serde(default)]
+    pub login_throttle: LoginThrottleConfig,
+    #[serde(default)]
+    pub password: PasswordConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub session_cookie: SessionCookieConfig,
+    #[serde(default)]
+    pub email_verification: EmailVerificationConfig,
+    #[serde(default)]
+    pub mcp_oauth: McpOAuthConfig,
+}
+
+// Configs written before access tokens were signed with rsa_private_key have neither field;
+// they load with RS256 and a fixed `kid`, and their `jwt_access_key` is ignored
+fn default_jwt_access_algorithm() -> String {
+    "RS256".into()
+}
+
+fn default_jwt_key_id() -> String {
+    "default".into()
+}
+
+/// OAuth 2.1 authorization of the MCP endpoint: the web service is the authorization server
+/// at `issuer`, and `mcp_base_url` is the protected resource. Third-party MCP clients
+/// register themselves and act for a signed-in user. Off without an `mcp_oauth` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpOAuthConfig {
+    pub enabled: bool,
+    pub issuer: String,     // Public URL of the web service, http://localhost:9000
+    pub code_seconds: u64,  // 60, lifetime of authorization codes
+    pub registration: bool, // Allow dynamic client registration
+}
+
+impl Default for McpOAuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            issuer: "http://localhost:9000".into(),
+            code_seconds: 60,
+            registration: false,
+        }
+    }
+}
+
+/// Verification mails of new accounts. With `required` unverified users cannot log in.
+/// Every email may be sent a mail once per `resend_interval_seconds` and at most
+/// `resend_max` times per `resend_window_seconds`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailVerificationConfig {
+    pub required: bool,
+    pub token_minutes: i64,           // 1440
+    pub resend_interval_seconds: u64, // 60
+    pub resend_max: i64,              // 5
+    pub resend_window_seconds: u64,   // 3600
+}
+
+impl Default for EmailVerificationConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            token_minutes: 1440,
+            resend_interval_seconds: 60,
+            resend_max: 5,
+            resend_window_seconds: 3600,
+        }
+    }
+}
+
+/// Cookies of the browser session mode. The access and refresh tokens are `HttpOnly`; the
+/// CSRF token is readable by the pages, which echo it in `X-CSRF-Token`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionCookieConfig {
+    pub secure: bool,      // Send over HTTPS only (browsers also allow http://localhost)
+    pub same_site: String, // Strict or Lax; Lax lets the OAuth callback redirect keep the session
+}
+
+impl Default for SessionCookieConfig {
+    fn default() -> Self {
+        Self {
+            secure: true,
+            same_site: "Lax".into(),
+        }
+    }
+}
+
+/// Buffered writer of `audit.events`. Events beyond `buffer_size` waiting to be written are
+/// dropped (and logged) rather than slowing requests down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditConfig {
+    pub buffer_size: usize,     // 10000
+    pub batch_size: usize,      // 100
+    pub flush_interval_ms: u64, // 1000
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: 10000,
+            batch_size: 100,
+            flush_interval_ms: 1000,
+        }
+    }
+}
+
+/// Password hashing and the policy for new passwords. Hashes of the other algorithm are still
+/// verified and replaced at the next successful login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordConfig {
+    pub algorithm: String,       // argon2id, bcrypt
+    pub argon2_memory_kib: u32,  // 19456
+    pub argon2_iterations: u32,  // 2
+    pub argon2_parallelism: u32, // 1
+    pub bcrypt_cost: u32,        // 12
+    pub min_length: usize,       // 8
+    pub max_length: usize,       // 128
+    #[serde(default)]
+    pub breached_list: Option<String>, // File with one known breached password per line
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: "argon2id".into(),
+            argon2_memory_kib: 19456,
+            argon2_iterations: 2,
+            argon2_parallelism: 1,
+            bcrypt_cost: 12,
+            min_length: 8,
+            max_length: 128,
+            breached_list: None,
+        }
+    }
+}
+
+/// Failed password checks per email back off exponentially and lock the account after
+/// `max_failures`; failures per client IP are capped separately. Counters expire after
+/// `window_seconds` without failures.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginThrottleConfig {
+    pub max_failures: i64,         // 5
+    pub lockout_seconds: u64,      // 900
+    pub backoff_base_seconds: u64, // 1, doubled on every failure
+    pub backoff_max_seconds: u64,  // 60
+    pub ip_max_failures: i64,      // 50
+    pub window_seconds: u64,       // 3600
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            lockout_seconds: 900,
+            backoff_base_seconds: 1,
+            backoff_max_seconds: 60,
+            ip_max_failures: 50,
+            window_seconds: 3600,
+        }
+    }
+}
+
+/// Outgoing mail. `outbox` stores mails in `app.outbox`, `file` writes one `.eml` per mail
+/// into `file_path`; both are meant for development and tests or an external relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailConfig {
+    pub sender: String, // outbox, file
+    pub from: String,   // no-reply@localhost
+    #[serde(default)]
+    pub file_path: Option<String>, // Directory of the `file` sender
+    pub base_url: String, // Public URL used in mailed links, http://localhost:9000
+    pub password_reset_minutes: i64,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            sender: "outbox".into(),
+            from: "no-reply@localhost".into(),
+            file_path: None,
+            base_url: "http://localhost:9000".into(),
+            password_reset_minutes: 30,
+        }
+    }
+}
+
+/// An MCP client defined in the config. Only the SHA-256 hash (hex) of its token is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpClientConfig {
+    pub name: String,
+    pub token_hash: String,
+    pub allowed_tools: Vec<String>, // Tool names, "*" for every tool
+    #[serde(default)]
+    pub expires_at: Option<i64>, // Unix seconds
+}
+
+/// An agent served next to the others, chosen by the `agent` field of chat requests. The first
+/// one answers requests that name no agent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub instruction: String,
+    #[serde(default)]
+    pub model: Option<String>, // Defaults to llm_model
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    pub tools: Vec<String>, // MCP tool names, "*" for every tool; "google_search" adds Google Search
+}
+
+/// Tools that run only after the user approves the call. A paused call is answered at
+/// `/auth/agent/sessions/{id}/approvals/{call_id}` and counts as rejected after
+/// `timeout_seconds`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentApprovalConfig {
+    pub tools: Vec<String>,   // MCP tool names
+    pub timeout_seconds: u64, // 300
+}
+
+impl Default for AgentApprovalConfig {
+    fn default() -> Self {
+        Self {
+            tools: vec!["add_content_knowledge_based".into()],
+            timeout_seconds: 300,
+        }
+    }
+}
+
+/// A public key that no longer signs but still verifies unexpired access tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtPublicKeyConfig {
+    pub kid: String,
+    pub algorithm: String,  // RS256, EdDSA
+    pub public_key: String, // Path to the PEM file
+}
+
+/// An OpenID Connect (or plain OAuth 2.0) sign-in provider.
+/// With `issuer` set, endpoints come from `{issuer}/.well-known/openid-configuration`;
+/// the optional endpoint fields override discovery and are required without an issuer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthProviderConfig {
+    pub name: String, // google, keycloak, github
+    #[serde(default)]
+    pub title: Option<String>, // Label of the login button
+    #[serde(default)]
+    pub issuer: Option<String>, // https://accounts.google.com
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String, // http://localhost:9000/oauth/google/callback
+    pub scopes: Vec<String>,  // ["openid", "email", "profile"]
+    #[serde(default)]
+    pub authorization_endpoint: Option<String>,
+    #[serde(default)]
+    pub token_endpoint: Option<String>,
+    #[serde(default)]
+    pub userinfo_endpoint: Option<String>,
+    #[serde(default)]
+    pub jwks_uri: Option<String>,
+    #[serde(default)]
+    pub emails_endpoint: Option<String>, // GitHub: https://api.github.com/user/emails
+    #[serde(default)]
+    pub claims: OAuthClaimMapping,
+}
+
+/// Names of the ID token / userinfo claims that carry the identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthClaimMapping {
+    pub subject: String,
+    pub email: String,
+    pub email_verified: Option<String>, // None: only `emails_endpoint` can verify the email
+    pub name: String,
+}
+
+impl Default for OAuthClaimMapping {
+    fn default() -> Self {
+        Self {
+            subject: "sub".into(),
+            email: "email".into(),
+            email_verified: Some("email_verified".into()),
+            name: "name".into(),
+        }
+    }
 }
 
 impl AppConfig {
@@ -74,4 +374,23 @@ mod test {
         let config = AppConfig::new();
         println!("{:#?}", config);
     }
+
+    // cargo test --package config check_legacy_config -- --nocapture
+    #[test]
+    fn check_legacy_config() {
+        let mut legacy: serde_json::Value =
+            serde_json::from_str(include_str!("../../../config.json.sample")).unwrap();
+        let fields = legacy.as_object_mut().unwrap();
+        fields.remove("jwt_access_algorithm");
+        fields.remove("jwt_key_id");
+        fields.remove("redis_session_idle");
+        fields.remove("redis_session_sliding");
+        fields.insert("jwt_access_key".into(), "<KEY>".into());
+        let config: AppConfig = serde_json::from_value(legacy).unwrap();
+        assert_eq!(config.jwt_access_algorithm, "RS256");
+        assert_eq!(config.jwt_key_id, "default");
+        // Sessions of older configs only have their absolute lifetime
+        assert_eq!(config.redis_session_idle, 0);
+        assert!(!config.redis_session_sliding);
+    }
 }
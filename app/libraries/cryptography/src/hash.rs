@@ -1,10 +1,220 @@
-extern crate bcrypt;
-use bcrypt::{hash_with_result, verify as vh, BcryptError, HashParts, DEFAULT_COST};
-pub fn hash(password: &str) -> Result<HashParts, BcryptError> {
-    hash_with_result(password, DEFAULT_COST)
+//! Password hashing. [`Hasher`] hashes new passwords with the configured algorithm and
+//! verifies hashes of every supported one, reporting when a hash should be replaced.
+
+use app_config::PasswordConfig;
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::{
+        Error as Argon2HashError, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+    },
+};
+use bcrypt::BcryptError;
+use rand::RngCore;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum HashError {
+    Bcrypt(BcryptError),
+    Argon2(Argon2HashError),
+    Params(argon2::Error),
+    Config(String),
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bcrypt(e) => write!(f, "bcrypt: {}", e),
+            Self::Argon2(e) => write!(f, "argon2: {}", e),
+            Self::Params(e) => write!(f, "argon2 parameters: {}", e),
+            Self::Config(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+impl From<BcryptError> for HashError {
+    fn from(value: BcryptError) -> Self {
+        Self::Bcrypt(value)
+    }
+}
+
+impl From<Argon2HashError> for HashError {
+    fn from(value: Argon2HashError) -> Self {
+        Self::Argon2(value)
+    }
+}
+
+impl From<argon2::Error> for HashError {
+    fn from(value: argon2::Error) -> Self {
+        Self::Params(value)
+    }
+}
+
+/// One password hashing scheme
+pub trait PasswordHasher: Send + Sync {
+    /// Whether `hashed` is in the format of this scheme
+    fn recognizes(&self, hashed: &str) -> bool;
+    fn hash(&self, password: &str) -> Result<String, HashError>;
+    fn verify(&self, password: &str, hashed: &str) -> Result<bool, HashError>;
+    /// Whether `hashed` was made with other parameters than the current ones
+    fn outdated(&self, hashed: &str) -> bool;
+}
+
+/// argon2id in PHC string format (`$argon2id$v=19$m=..,t=..,p=..$salt$hash`)
+pub struct Argon2Hasher {
+    params: Params,
+}
+
+impl Argon2Hasher {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, HashError> {
+        Ok(Self {
+            params: Params::new(memory_kib, iterations, parallelism, None)?,
+        })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
 }
-pub fn verify(password: &str, hashed: &str) -> Result<bool, BcryptError> {
-    vh(password, hashed)
+
+impl PasswordHasher for Argon2Hasher {
+    fn recognizes(&self, hashed: &str) -> bool {
+        hashed.starts_with("$argon2")
+    }
+
+    fn hash(&self, password: &str) -> Result<String, HashError> {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let salt = SaltString::encode_b64(&salt)?;
+        Ok(self
+            .argon2()
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    }
+
+    fn verify(&self, password: &str, hashed: &str) -> Result<bool, HashError> {
+        let parsed = PasswordHash::new(hashed)?;
+        // Parameters are taken from the hash, so older settings still verify
+        match self.argon2().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(Argon2HashError::Password) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn outdated(&self, hashed: &str) -> bool {
+        let parsed = match PasswordHash::new(hashed) {
+            Err(_) => return true,
+            Ok(parsed) => parsed,
+        };
+        if parsed.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+        match Params::try_from(&parsed) {
+            Err(_) => true,
+            Ok(params) => {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            }
+        }
+    }
+}
+
+/// bcrypt (`$2a$`, `$2b$`, `$2y$`)
+pub struct BcryptHasher {
+    cost: u32,
+}
+
+impl BcryptHasher {
+    pub fn new(cost: u32) -> Self {
+        Self { cost }
+    }
+}
+
+impl PasswordHasher for BcryptHasher {
+    fn recognizes(&self, hashed: &str) -> bool {
+        hashed.starts_with("$2")
+    }
+
+    fn hash(&self, password: &str) -> Result<String, HashError> {
+        Ok(bcrypt::hash(password, self.cost)?)
+    }
+
+    fn verify(&self, password: &str, hashed: &str) -> Result<bool, HashError> {
+        Ok(bcrypt::verify(password, hashed)?)
+    }
+
+    fn outdated(&self, hashed: &str) -> bool {
+        // `$2b$12$...`
+        hashed
+            .split('$')
+            .nth(2)
+            .and_then(|c| c.parse::<u32>().ok())
+            .is_none_or(|c| c != self.cost)
+    }
+}
+
+/// Result of a password check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Verified {
+    pub valid: bool,
+    /// The hash is of another scheme or outdated parameters and should be replaced
+    pub needs_rehash: bool,
+}
+
+/// Hashes with the primary scheme and verifies hashes of all known schemes
+pub struct Hasher {
+    primary: Box<dyn PasswordHasher>,
+    others: Vec<Box<dyn PasswordHasher>>,
+}
+
+impl Hasher {
+    pub fn new(primary: Box<dyn PasswordHasher>, others: Vec<Box<dyn PasswordHasher>>) -> Self {
+        Self { primary, others }
+    }
+
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, HashError> {
+        let argon2 = Box::new(Argon2Hasher::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+        )?);
+        let bcrypt = Box::new(BcryptHasher::new(config.bcrypt_cost));
+        match config.algorithm.as_str() {
+            "argon2id" => Ok(Self::new(argon2, vec![bcrypt])),
+            "bcrypt" => Ok(Self::new(bcrypt, vec![argon2])),
+            other => Err(HashError::Config(format!(
+                "Unknown password algorithm {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, HashError> {
+        self.primary.hash(password)
+    }
+
+    pub fn verify(&self, password: &str, hashed: &str) -> Result<Verified, HashError> {
+        if self.primary.recognizes(hashed) {
+            let valid = self.primary.verify(password, hashed)?;
+            return Ok(Verified {
+                valid,
+                needs_rehash: valid && self.primary.outdated(hashed),
+            });
+        }
+        match self.others.iter().find(|h| h.recognizes(hashed)) {
+            None => Err(HashError::Config("Unknown password hash format".into())),
+            Some(hasher) => {
+                let valid = hasher.verify(password, hashed)?;
+                Ok(Verified {
+                    valid,
+                    needs_rehash: valid,
+                })
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -14,18 +224,40 @@ mod tests {
     fn check_hash_functions() {
         let password = "password";
         let not_password = "not_password";
-        let h = hash(password).unwrap();
-        let should_true = verify(password, &h.to_string()).unwrap();
-        let should_false = verify(not_password, &h.to_string()).unwrap();
+        let hasher = Hasher::from_config(&PasswordConfig::default()).unwrap();
+        let h = hasher.hash(password).unwrap();
+        let should_true = hasher.verify(password, &h).unwrap();
+        let should_false = hasher.verify(not_password, &h).unwrap();
         println!(
-            "Hash Functions -> Password: {} Hash: {} Salt: {} Valid pass: {} Not valid: {}",
-            password,
-            h.to_string(),
-            h.get_salt(),
-            should_true,
-            should_false
+            "Hash Functions -> Password: {} Hash: {} Valid pass: {:?} Not valid: {:?}",
+            password, h, should_true, should_false
         );
-        assert_eq!(should_true, true);
-        assert_eq!(should_false, false);
+        assert!(h.starts_with("$argon2id$"));
+        assert!(should_true.valid);
+        assert!(!should_true.needs_rehash);
+        assert!(!should_false.valid);
+    }
+
+    // cargo test --package cryptography check_bcrypt_migration -- --nocapture
+    #[test]
+    fn check_bcrypt_migration() {
+        let hasher = Hasher::from_config(&PasswordConfig::default()).unwrap();
+        let legacy = BcryptHasher::new(4).hash("password").unwrap();
+        let verified = hasher.verify("password", &legacy).unwrap();
+        assert!(verified.valid);
+        assert!(verified.needs_rehash);
+        let verified = hasher.verify("not_password", &legacy).unwrap();
+        assert!(!verified.valid);
+        assert!(!verified.needs_rehash);
+
+        // Stronger argon2 parameters make older argon2 hashes outdated
+        let old = Argon2Hasher::new(8192, 1, 1)
+            .unwrap()
+            .hash("password")
+            .unwrap();
+        let verified = hasher.verify("password", &old).unwrap();
+        assert!(verified.valid);
+        assert!(verified.needs_rehash);
+        assert!(hasher.verify("password", "plain").is_err());
     }
 }
@@ -1,10 +1,25 @@
+use crate::base64::base64_encode_from_u8;
+use app_config::AppConfig;
 pub use jsonwebtoken::Algorithm;
 use jsonwebtoken::errors::Error;
 pub use jsonwebtoken::errors::ErrorKind as JWTErrorKind;
+pub use jsonwebtoken::jwk::{Jwk, JwkSet};
 use jsonwebtoken::{
-    DecodingKey, EncodingKey, Header, TokenData, Validation, decode as jdec, encode as jenc,
+    DecodingKey, EncodingKey, Header, TokenData, Validation, decode as jdec, decode_header,
+    encode as jenc,
 };
-use serde::{Deserialize, Serialize};
+use rsa::{
+    RsaPublicKey,
+    pkcs1::DecodeRsaPublicKey,
+    pkcs8::{
+        DecodePublicKey, SubjectPublicKeyInfoRef,
+        der::{Decode, Document},
+    },
+    traits::PublicKeyParts,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use std::{fmt, fs, str::FromStr};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -15,6 +30,10 @@ pub struct Claims {
     pub email: String,
     pub session: u64,
     pub source: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +44,22 @@ pub struct RedisInfo {
     pub session: u64,
     pub token: String,
     pub source: String,
+    #[serde(default)]
+    pub expires_at: i64, // Absolute end of the session (unix timestamp)
+    #[serde(default)]
+    pub created_at: i64, // Login time (unix timestamp)
+    #[serde(default)]
+    pub last_seen: i64, // Last login or refresh (unix timestamp)
+    #[serde(default)]
+    pub device: String,
+    #[serde(default)]
+    pub ip: String,
+    #[serde(default)]
+    pub user_agent: String,
+    #[serde(default)]
+    pub previous_token: String, // Refresh token replaced by `token`
+    #[serde(default)]
+    pub rotated_at: i64, // When `token` replaced `previous_token` (unix timestamp)
 }
 
 pub fn generate_token<T>(algorithm: Algorithm, key: &str, claims: T) -> Result<String, Error>
@@ -54,10 +89,198 @@ where
     )
 }
 
+/// A public key that access tokens may be signed with, published in the JWKS
+#[derive(Clone)]
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub jwk: Jwk,
+    decoding: DecodingKey,
+}
+
+impl JwtKey {
+    /// Loads a PEM public key; RSA keys may be SPKI or PKCS#1, Ed25519 keys SPKI
+    pub fn from_pem(kid: &str, algorithm: Algorithm, pem: &str) -> Result<Self, Error> {
+        let invalid = || Error::from(JWTErrorKind::InvalidKeyFormat);
+        let mut jwk = match algorithm {
+            Algorithm::RS256
+            | Algorithm::RS384
+            | Algorithm::RS512
+            | Algorithm::PS256
+            | Algorithm::PS384
+            | Algorithm::PS512 => {
+                let key = RsaPublicKey::from_public_key_pem(pem)
+                    .or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+                    .map_err(|_| invalid())?;
+                json!({
+                    "kty": "RSA",
+                    "n": base64_encode_from_u8(&key.n().to_bytes_be()),
+                    "e": base64_encode_from_u8(&key.e().to_bytes_be()),
+                })
+            }
+            Algorithm::EdDSA => {
+                let (_, der) = Document::from_pem(pem).map_err(|_| invalid())?;
+                let spki =
+                    SubjectPublicKeyInfoRef::from_der(der.as_bytes()).map_err(|_| invalid())?;
+                json!({
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": base64_encode_from_u8(&spki.subject_public_key.raw_bytes().to_vec()),
+                })
+            }
+            _ => return Err(Error::from(JWTErrorKind::InvalidAlgorithm)),
+        };
+        jwk["kid"] = json!(kid);
+        jwk["alg"] = serde_json::to_value(algorithm)?;
+        jwk["use"] = json!("sig");
+        let jwk = serde_json::from_value::<Jwk>(jwk)?;
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            decoding: DecodingKey::from_jwk(&jwk)?,
+            jwk,
+        })
+    }
+}
+
+/// Signing key of this service plus every public key still accepted for verification.
+/// Tokens carry the `kid` of their key, so retired keys keep working until their tokens expire.
+#[derive(Clone)]
+pub struct JwtKeySet {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    keys: Vec<JwtKey>,
+}
+
+#[derive(Debug)]
+pub enum JwtKeyError {
+    Io(String, std::io::Error),
+    Key(String, Error),
+}
+
+impl fmt::Display for JwtKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "Cannot read key {}: {}", path, e),
+            Self::Key(kid, e) => write!(f, "Invalid key {}: {}", kid, e),
+        }
+    }
+}
+
+impl std::error::Error for JwtKeyError {}
+
+fn read_key(path: &str) -> Result<String, JwtKeyError> {
+    fs::read_to_string(path).map_err(|e| JwtKeyError::Io(path.to_owned(), e))
+}
+
+fn parse_algorithm(kid: &str, algorithm: &str) -> Result<Algorithm, JwtKeyError> {
+    Algorithm::from_str(algorithm).map_err(|e| JwtKeyError::Key(kid.to_owned(), e))
+}
+
+impl JwtKeySet {
+    pub fn new(
+        kid: &str,
+        algorithm: Algorithm,
+        private_pem: &str,
+        public_pem: &str,
+        previous: Vec<JwtKey>,
+    ) -> Result<Self, Error> {
+        let encoding = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem.as_bytes())?,
+            _ => EncodingKey::from_rsa_pem(private_pem.as_bytes())?,
+        };
+        let mut keys = vec![JwtKey::from_pem(kid, algorithm, public_pem)?];
+        keys.extend(previous.into_iter().filter(|k| k.kid != kid));
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            encoding,
+            keys,
+        })
+    }
+
+    /// Loads `rsa_private_key` / `rsa_public_key` and the `jwt_previous_keys` of the config
+    pub fn from_config(config: &AppConfig) -> Result<Self, JwtKeyError> {
+        let kid = config.jwt_key_id.as_str();
+        let algorithm = parse_algorithm(kid, &config.jwt_access_algorithm)?;
+        let mut previous = Vec::new();
+        for key in &config.jwt_previous_keys {
+            let key_algorithm = parse_algorithm(&key.kid, &key.algorithm)?;
+            let pem = read_key(&key.public_key)?;
+            previous.push(
+                JwtKey::from_pem(&key.kid, key_algorithm, &pem)
+                    .map_err(|e| JwtKeyError::Key(key.kid.clone(), e))?,
+            );
+        }
+        Self::new(
+            kid,
+            algorithm,
+            &read_key(&config.rsa_private_key)?,
+            &read_key(&config.rsa_public_key)?,
+            previous,
+        )
+        .map_err(|e| JwtKeyError::Key(kid.to_owned(), e))
+    }
+
+    /// Signs with the current key and puts its `kid` in the header
+    pub fn sign<T>(&self, claims: &T) -> Result<String, Error>
+    where
+        T: Serialize,
+    {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.kid.clone());
+        jenc(&header, claims, &self.encoding)
+    }
+
+    /// Verifies a token signed by any accepted key; the algorithm is taken from the key,
+    /// never from the token header
+    pub fn verify<T>(&self, token: &str) -> Result<TokenData<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let header = decode_header(token)?;
+        let key = match header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.keys.iter().find(|k| k.kid == kid))
+        {
+            None => return Err(Error::from(JWTErrorKind::InvalidToken)),
+            Some(key) => key,
+        };
+        if header.alg != key.algorithm {
+            return Err(Error::from(JWTErrorKind::InvalidAlgorithm));
+        }
+        jdec::<T>(token, &key.decoding, &Validation::new(key.algorithm))
+    }
+
+    /// Public keys for `/.well-known/jwks.json`
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().map(|k| k.jwk.clone()).collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use app_config::AppConfig;
+    use rsa::{
+        RsaPrivateKey,
+        pkcs1::{EncodeRsaPrivateKey, LineEnding},
+        pkcs8::EncodePublicKey,
+    };
+
+    fn rsa_pem() -> (String, String) {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 2048).unwrap();
+        let private_pem = key.to_pkcs1_pem(LineEnding::LF).unwrap().to_string();
+        let public_pem = key
+            .to_public_key()
+            .to_public_key_pem(LineEnding::LF)
+            .unwrap();
+        (private_pem, public_pem)
+    }
+
     #[test]
     fn check_jwt_functions() {
         let mut my_claim = Claims {
@@ -67,9 +290,12 @@ mod test {
             name: "test".to_owned(),
             email: "test@unit".to_owned(),
             session: 1,
+            source: "local".to_owned(),
+            roles: vec![],
+            permissions: vec![],
         };
         let config = AppConfig::new();
-        let secret = config.jwt_access_key;
+        let secret = config.jwt_refresh_key;
         match generate_token(Algorithm::HS256, &secret, &my_claim) {
             Ok(token) => {
                 println!("Valid Token: {}", token);
@@ -86,4 +312,64 @@ mod test {
         let error = validate_token::<Claims>(Algorithm::HS256, &secret, &token).err();
         assert_eq!(error.unwrap().kind(), &JWTErrorKind::ExpiredSignature)
     }
+
+    // cargo test --package cryptography check_jwt_key_rotation -- --nocapture
+    #[test]
+    fn check_jwt_key_rotation() {
+        let claims = Claims {
+            exp: i64::MAX,
+            iat: 0,
+            id: "".to_owned(),
+            name: "test".to_owned(),
+            email: "test@unit".to_owned(),
+            session: 1,
+            source: "local".to_owned(),
+            roles: vec![],
+            permissions: vec![],
+        };
+        let (old_private, old_public) = rsa_pem();
+        let old =
+            JwtKeySet::new("old", Algorithm::RS256, &old_private, &old_public, vec![]).unwrap();
+        let old_token = old.sign(&claims).unwrap();
+        assert_eq!(
+            decode_header(&old_token).unwrap().kid.as_deref(),
+            Some("old")
+        );
+
+        // The new key signs, the old one is still accepted during rotation
+        let (new_private, new_public) = rsa_pem();
+        let retired = JwtKey::from_pem("old", Algorithm::RS256, &old_public).unwrap();
+        let new = JwtKeySet::new(
+            "new",
+            Algorithm::RS256,
+            &new_private,
+            &new_public,
+            vec![retired],
+        )
+        .unwrap();
+        let new_token = new.sign(&claims).unwrap();
+        assert_eq!(
+            new.verify::<Claims>(&old_token).unwrap().claims.email,
+            "test@unit"
+        );
+        assert_eq!(
+            new.verify::<Claims>(&new_token).unwrap().claims.email,
+            "test@unit"
+        );
+        assert_eq!(new.jwks().keys.len(), 2);
+        println!("{}", serde_json::to_string_pretty(&new.jwks()).unwrap());
+
+        // Tokens of keys that are no longer listed are rejected
+        assert!(old.verify::<Claims>(&new_token).is_err());
+        // A shared-secret token with the same kid cannot pass as the RSA key
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("new".to_owned());
+        let forged = jenc(
+            &header,
+            &claims,
+            &EncodingKey::from_secret(new_public.as_bytes()),
+        )
+        .unwrap();
+        assert!(new.verify::<Claims>(&forged).is_err());
+    }
 }
@@ -2,3 +2,4 @@ pub mod base64;
 pub mod hash;
 pub mod jwt;
 pub mod rsa;
+pub mod totp;
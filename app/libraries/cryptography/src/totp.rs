@@ -0,0 +1,140 @@
+//! Time-based one-time passwords (RFC 6238) with the defaults authenticator apps expect:
+//! HMAC-SHA1, 30 second steps and 6 digits. Secrets are exchanged in unpadded base32.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+pub const TOTP_STEP: u64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+/// Steps accepted before and after the current one, for clock drift
+pub const TOTP_SKEW: u64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A new random 160-bit secret
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// Decodes base32, ignoring case, spaces and padding; `None` on invalid characters
+pub fn base32_decode(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in data.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let c = c.to_ascii_uppercase() as u8;
+        let value = BASE32_ALPHABET.iter().position(|a| *a == c)? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push(((buffer >> bits) & 0xff) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// HOTP value (RFC 4226) of `counter`
+pub fn hotp(secret: &[u8], counter: u64, digits: u32) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let code = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+    format!(
+        "{:0width$}",
+        code % 10u32.pow(digits),
+        width = digits as usize
+    )
+}
+
+/// Step number of a unix timestamp
+pub fn totp_step(unix_time: u64) -> u64 {
+    unix_time / TOTP_STEP
+}
+
+/// Returns the step that `code` is valid for, within [`TOTP_SKEW`] steps of `unix_time`.
+/// Callers must refuse steps they have already accepted to prevent replay.
+pub fn verify_totp(secret: &[u8], code: &str, unix_time: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize {
+        return None;
+    }
+    let current = totp_step(unix_time);
+    (current.saturating_sub(TOTP_SKEW)..=current + TOTP_SKEW)
+        .find(|step| hotp(secret, *step, TOTP_DIGITS) == code)
+}
+
+/// `otpauth://` URI for QR codes of authenticator apps
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    let encode = |s: &str| -> String {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    };
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        encode(issuer),
+        encode(account),
+        base32_encode(secret),
+        encode(issuer),
+        TOTP_DIGITS,
+        TOTP_STEP
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package cryptography check_totp -- --nocapture
+    #[test]
+    fn check_totp() {
+        // RFC 6238 appendix B, SHA1
+        let secret = b"12345678901234567890";
+        assert_eq!(hotp(secret, totp_step(59), 8), "94287082");
+        assert_eq!(hotp(secret, totp_step(1111111109), 8), "07081804");
+        assert_eq!(hotp(secret, totp_step(1234567890), 8), "89005924");
+        let code = hotp(secret, totp_step(59), TOTP_DIGITS);
+        assert_eq!(code, "287082");
+        assert_eq!(verify_totp(secret, &code, 59), Some(1));
+        assert_eq!(verify_totp(secret, &code, 59 + TOTP_STEP), Some(1));
+        assert_eq!(verify_totp(secret, &code, 59 + 3 * TOTP_STEP), None);
+
+        let encoded = base32_encode(secret);
+        assert_eq!(encoded, "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(
+            base32_decode(&encoded.to_lowercase()).unwrap(),
+            secret.to_vec()
+        );
+        println!("{}", provisioning_uri("Agent Demo", "test@unit", secret));
+    }
+}
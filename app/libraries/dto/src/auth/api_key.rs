@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostApiKeyInput {
+    pub name: String,
+    pub scopes: Vec<String>,          // Permissions such as "customers:read"
+    pub expires_in_days: Option<i64>, // None: never expires
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PatchApiKeyInput {
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiKeyOutput {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,   // unix timestamp
+    pub last_used_at: Option<i64>, // unix timestamp
+    pub created_at: i64,           // unix timestamp
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostApiKeyOutput {
+    pub key: String, // Shown once; only its hash is stored
+    pub api_key: ApiKeyOutput,
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub outcome: Option<String>, // success, failure or denied
+    pub from: Option<i64>,       // Unix seconds, inclusive
+    pub to: Option<i64>,         // Unix seconds, exclusive
+    pub page: Option<i64>,       // Default 1
+    pub per_page: Option<i64>,   // Default 50, at most 500
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditEventOutput {
+    pub id: i64,
+    pub occurred_at: i64,
+    pub actor: Option<String>,
+    pub actor_id: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub details: serde_json::Value,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuditPageOutput {
+    pub items: Vec<AuditEventOutput>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
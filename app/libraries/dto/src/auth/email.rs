@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VerifyEmailQuery {
+    pub token: Option<String>, // From the mailed link; none shows the resend form
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostResendVerificationInput {
+    pub email: String,
+}
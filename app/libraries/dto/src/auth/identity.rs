@@ -0,0 +1,6 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostLinkIdentityOutput {
+    pub url: String, // Provider authorization URL to open in the browser
+}
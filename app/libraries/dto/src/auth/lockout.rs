@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LockoutOutput {
+    pub email: String,
+    pub failures: i64,
+    pub locked_for: Option<u64>, // Seconds left of the lockout
+    pub wait_for: Option<u64>,   // Seconds left of the backoff
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoginFailuresQuery {
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub limit: Option<i64>, // Default 100, at most 1000
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LoginFailureOutput {
+    pub email: String,
+    pub user_id: Option<String>,
+    pub ip: String,
+    pub user_agent: String,
+    pub reason: String,
+    pub created_at: i64,
+}
@@ -4,6 +4,9 @@ use serde::{Deserialize, Serialize};
 pub struct PostLoginInput {
     pub email: String,
     pub password: String,
+    pub browser: Option<String>,
+    #[serde(default)]
+    pub cookie: bool, // Receive the session as HttpOnly cookies instead of tokens
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -11,3 +14,37 @@ pub struct PostLoginOutput {
     pub access_token: String,
     pub refresh_token: String,
 }
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostRefreshInput {
+    #[serde(default)]
+    pub refresh_token: Option<String>, // Taken from the `refresh_token` cookie when missing
+}
+
+/// Returned instead of tokens in cookie mode; sent back in the `X-CSRF-Token` header
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostCookieSessionOutput {
+    pub csrf_token: String,
+}
+
+/// Returned instead of tokens when the account has a second factor
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostLoginMfaOutput {
+    pub mfa_required: bool,
+    pub mfa_token: String, // Exchanged with a code at POST /login/mfa
+    pub expires_in: u64,   // Seconds
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum PostLoginResult {
+    Tokens(PostLoginOutput),
+    Mfa(PostLoginMfaOutput),
+    Cookie(PostCookieSessionOutput),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostLoginMfaInput {
+    pub mfa_token: String,
+    pub code: String, // TOTP code or recovery code
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostMcpClientInput {
+    pub name: String,
+    pub allowed_tools: Vec<String>,   // Tool names, "*" for every tool
+    pub expires_in_days: Option<i64>, // None: never expires
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PatchMcpClientInput {
+    pub name: Option<String>,
+    pub allowed_tools: Option<Vec<String>>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct McpClientOutput {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub allowed_tools: Vec<String>,
+    pub expires_at: Option<i64>,   // unix timestamp
+    pub last_used_at: Option<i64>, // unix timestamp
+    pub created_at: i64,           // unix timestamp
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostMcpClientOutput {
+    pub token: String, // Shown once; only its hash is stored
+    pub client: McpClientOutput,
+}
@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostTotpEnrollOutput {
+    pub secret: String, // base32, for manual entry
+    pub uri: String,    // otpauth:// URI to render as a QR code
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostTotpConfirmInput {
+    pub code: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecoveryCodesOutput {
+    pub recovery_codes: Vec<String>, // Shown once
+}
+
+/// Disabling and regenerating require the password and a current code
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PostMfaReauthInput {
+    pub password: String,
+    pub code: String, // TOTP code or recovery code
+}
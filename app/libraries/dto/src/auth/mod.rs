@@ -1,2 +1,12 @@
+pub mod api_key;
+pub mod audit;
+pub mod email;
+pub mod identity;
+pub mod lockout;
 pub mod login;
+pub mod mcp_client;
+pub mod mfa;
+pub mod oauth2;
+pub mod password;
+pub mod session;
 pub mod user;
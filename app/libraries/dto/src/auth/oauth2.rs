@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+/// Authorization server metadata (RFC 8414)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuthorizationServerMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration_endpoint: Option<String>,
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+/// Protected resource metadata (RFC 9728)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProtectedResourceMetadata {
+    pub resource: String,
+    pub authorization_servers: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub bearer_methods_supported: Vec<String>,
+    pub resource_name: String,
+}
+
+/// Dynamic client registration request (RFC 7591)
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegisterClientInput {
+    pub redirect_uris: Vec<String>,
+    pub client_name: Option<String>,
+    pub token_endpoint_auth_method: Option<String>, // none (default), client_secret_post
+    pub grant_types: Option<Vec<String>>,
+    pub response_types: Option<Vec<String>>,
+    pub scope: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegisterClientOutput {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>, // Shown once; only its hash is stored
+    pub client_id_issued_at: i64, // unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>, // 0: never
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub response_types: Vec<String>,
+    pub token_endpoint_auth_method: String,
+}
+
+/// Parameters of `GET /oauth2/authorize`, echoed by the consent form
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AuthorizeQuery {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub scope: Option<String>,
+    pub resource: Option<String>,
+}
+
+/// The consent form posted to `/oauth2/authorize`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AuthorizeDecisionInput {
+    #[serde(flatten)]
+    pub request: AuthorizeQuery,
+    pub csrf_token: String,
+    pub approve: Option<String>, // Present when the user approved
+}
+
+/// Form of `POST /oauth2/token`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenInput {
+    pub grant_type: String, // authorization_code, refresh_token
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub refresh_token: Option<String>,
+    pub resource: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenOutput {
+    pub access_token: String,
+    pub token_type: String, // Bearer
+    pub expires_in: i64,    // Seconds
+    pub refresh_token: String,
+    pub scope: String,
+}
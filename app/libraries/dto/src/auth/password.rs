@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostForgotPasswordInput {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostResetPasswordInput {
+    pub token: String, // From the mailed link
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResetPasswordQuery {
+    pub token: Option<String>,
+}
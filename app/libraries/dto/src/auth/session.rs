@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SessionOutput {
+    pub session: String, // u64 does not fit in a JavaScript number
+    pub source: String,
+    pub device: String,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: i64,
+    pub last_seen: i64,
+    pub expires_at: i64,
+    pub current: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeleteSessionsOutput {
+    pub revoked: usize,
+}
@@ -1,12 +1,80 @@
 use app_schema::auth::users::User;
+use chrono::{DateTime, Utc};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user as shown outside the auth internals, without the password hash
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct PublicUser {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub has_password: bool, // false for users provisioned by an external provider
+    pub email_verified: bool,
+    pub disabled: bool,
+    pub deleted: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email,
+            name: user.name,
+            has_password: user.password_hash.is_some(),
+            email_verified: user.email_verified_at.is_some(),
+            disabled: user.disabled_at.is_some(),
+            deleted: user.deleted_at.is_some(),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UsersQuery {
+    pub email: Option<String>,  // Substring, case insensitive
+    pub name: Option<String>,   // Substring, case insensitive
+    pub disabled: Option<bool>, // Any when missing
+    pub deleted: Option<bool>,  // Include deleted users, default false
+    pub page: Option<i64>,      // Default 1
+    pub per_page: Option<i64>,  // Default 50, at most 500
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UsersPageOutput {
+    pub items: Vec<PublicUser>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PostUserInput {
     pub name: String,
     pub email: String,
     pub password: String,
+    pub roles: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PutUserRolesInput {
+    pub email: String,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PatchUserInput {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PostUserPasswordInput {
+    pub password: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -23,10 +91,35 @@ pub struct McpGetUserByEmailInput {
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct McpGetUserByEmailOutput {
-    pub result: User,
+    pub result: PublicUser,
 }
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct McpGetUsersOutput {
-    pub results: Vec<User>,
+    pub results: Vec<PublicUser>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package dto check_public_user -- --nocapture
+    #[test]
+    fn check_public_user() {
+        let user = User {
+            id: Uuid::new_v4(),
+            email: "user@example.com".into(),
+            name: "User".into(),
+            password_hash: Some("$argon2id$secret".into()),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            email_verified_at: None,
+            disabled_at: Some(Utc::now()),
+            deleted_at: None,
+        };
+        let public = PublicUser::from(user);
+        let json = serde_json::to_string(&public).unwrap();
+        println!("{}", &json);
+        assert!(!json.contains("argon2id"));
+        assert!(public.has_password && public.disabled && !public.deleted);
+    }
 }
@@ -1,9 +1,10 @@
 use adk_core::AdkError;
 use adk_rust::anyhow::Error as AdkRustError;
+use app_cryptography::hash::HashError;
 use askama::Error as AskamaError;
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use bcrypt::BcryptError;
@@ -36,6 +37,9 @@ pub struct AppError {
     #[serde(serialize_with = "serialize_status")]
     pub status: StatusCode,
     pub code: i64,
+    /// Seconds until the request may be retried, sent as `Retry-After`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
 }
 
 fn serialize_status<S>(status: &StatusCode, s: S) -> Result<S::Ok, S::Error>
@@ -51,6 +55,15 @@ impl AppError {
             message: message.into(),
             status,
             code,
+            retry_after: None,
+        }
+    }
+
+    /// `429 Too Many Requests` with a `Retry-After` header
+    pub fn too_many_requests(message: impl Into<String>, retry_after: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after.max(1)),
+            ..Self::new(message, StatusCode::TOO_MANY_REQUESTS, 8)
         }
     }
 
@@ -85,7 +98,15 @@ impl IntoResponse for AppError {
             self.message,
             self.status.as_u16()
         );
-        (self.status, Json(self)).into_response()
+        match self.retry_after {
+            None => (self.status, Json(self)).into_response(),
+            Some(seconds) => (
+                self.status,
+                [(RETRY_AFTER, seconds.to_string())],
+                Json(self),
+            )
+                .into_response(),
+        }
     }
 }
 
@@ -222,6 +243,16 @@ impl From<BcryptError> for AppError {
     }
 }
 
+impl From<HashError> for AppError {
+    fn from(value: HashError) -> Self {
+        Self::new(
+            format!("{value}"),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            SYSTEM_ERROR_CODE_CRYPTO,
+        )
+    }
+}
+
 impl From<AskamaError> for AppError {
     fn from(value: AskamaError) -> Self {
         Self::new(
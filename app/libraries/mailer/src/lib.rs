@@ -0,0 +1,150 @@
+//! Outgoing mail behind the [`MailSender`] trait, selected by `mail.sender` in the config.
+//!
+//! No SMTP client is bundled: `outbox` keeps mails in `app.outbox` for a relay (or for tests to
+//! read), `file` writes RFC 5322 `.eml` files that any mail client can open.
+
+use app_config::{AppConfig, MailConfig};
+use app_error::AppError;
+use app_schema::outbox::OutboxMail;
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::{path::PathBuf, sync::Arc};
+use tracing::*;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String, // text/plain
+}
+
+#[async_trait]
+pub trait MailSender: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<(), AppError>;
+}
+
+/// Stores mails in `app.outbox`
+pub struct OutboxSender {
+    pg: Pool<Postgres>,
+    from: String,
+}
+
+impl OutboxSender {
+    pub fn new(pg: Pool<Postgres>, from: &str) -> Self {
+        Self {
+            pg,
+            from: from.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl MailSender for OutboxSender {
+    async fn send(&self, mail: &Mail) -> Result<(), AppError> {
+        sqlx::query(OutboxMail::insert_query())
+            .bind(&self.from)
+            .bind(&mail.to)
+            .bind(&mail.subject)
+            .bind(&mail.body)
+            .execute(&self.pg)
+            .await?;
+        debug!(
+            "Mail \"{}\" to {} stored in outbox",
+            &mail.subject, &mail.to
+        );
+        Ok(())
+    }
+}
+
+/// Writes one `.eml` file per mail into a directory
+pub struct FileSender {
+    dir: PathBuf,
+    from: String,
+}
+
+impl FileSender {
+    pub fn new(dir: impl Into<PathBuf>, from: &str) -> Self {
+        Self {
+            dir: dir.into(),
+            from: from.to_owned(),
+        }
+    }
+
+    fn render(&self, mail: &Mail) -> String {
+        // Header values must not start new headers
+        let header = |s: &str| s.replace(['\r', '\n'], " ");
+        format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            header(&self.from),
+            header(&mail.to),
+            header(&mail.subject),
+            Utc::now().to_rfc2822(),
+            mail.body.replace('\n', "\r\n")
+        )
+    }
+}
+
+#[async_trait]
+impl MailSender for FileSender {
+    async fn send(&self, mail: &Mail) -> Result<(), AppError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(format!(
+            "{}_{}.eml",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            Uuid::new_v4()
+        ));
+        tokio::fs::write(&path, self.render(mail)).await?;
+        debug!(
+            "Mail \"{}\" to {} written to {:?}",
+            &mail.subject, &mail.to, &path
+        );
+        Ok(())
+    }
+}
+
+/// Builds the sender configured in `mail`
+pub fn mail_sender(
+    config: &AppConfig,
+    pg: Pool<Postgres>,
+) -> Result<Arc<dyn MailSender>, AppError> {
+    let mail: &MailConfig = &config.mail;
+    match mail.sender.as_str() {
+        "outbox" => Ok(Arc::new(OutboxSender::new(pg, &mail.from))),
+        "file" => match &mail.file_path {
+            None => Err(AppError::internal(
+                "mail.file_path is required by the file sender",
+            )),
+            Some(dir) => Ok(Arc::new(FileSender::new(dir, &mail.from))),
+        },
+        other => Err(AppError::internal(format!("Unknown mail sender {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // cargo test --package mailer check_file_sender -- --nocapture
+    #[test]
+    fn check_file_sender() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let dir = std::env::temp_dir().join(format!("mailer_{}", Uuid::new_v4()));
+            let sender = FileSender::new(&dir, "no-reply@unit");
+            let mail = Mail {
+                to: "test@unit".into(),
+                subject: "Hello".into(),
+                body: "line 1\nline 2".into(),
+            };
+            sender.send(&mail).await.unwrap();
+            let mut entries = std::fs::read_dir(&dir).unwrap();
+            let path = entries.next().unwrap().unwrap().path();
+            let content = std::fs::read_to_string(&path).unwrap();
+            println!("{}", &content);
+            assert!(content.starts_with("From: no-reply@unit\r\nTo: test@unit\r\n"));
+            assert!(content.contains("\r\n\r\nline 1\r\nline 2\r\n"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use app_cryptography::jwt::Claims;
+use app_error::AppError;
+use axum::{
+    extract::FromRequestParts,
+    http::{StatusCode, request::Parts},
+};
+use uuid::Uuid;
+
+/// Identity of an authenticated caller, put into the request extensions by
+/// `web_auth_middleware`. Use it as an extractor in handlers behind the middleware:
+/// `async fn handler(user: AuthUser, ...)`; requests without it are answered `401`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub session: u64, // 0 for API keys, which are not bound to a login session
+    pub source: String,
+    pub roles: Vec<String>,
+    pub permissions: Vec<String>,
+    pub expires_at: i64, // Unix time the access token or API key expires
+}
+
+impl AuthUser {
+    /// `None` when the claims do not name a user id
+    pub fn from_claims(claims: &Claims) -> Option<Self> {
+        Some(Self {
+            id: Uuid::parse_str(&claims.id).ok()?,
+            name: claims.name.clone(),
+            email: claims.email.clone(),
+            session: claims.session,
+            source: claims.source.clone(),
+            roles: claims.roles.clone(),
+            permissions: claims.permissions.clone(),
+            expires_at: claims.exp,
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or_else(|| AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0))
+    }
+}
@@ -1,15 +1,36 @@
-use app_cryptography::jwt::{Algorithm, Claims, RedisInfo, generate_token, validate_token};
+mod auth_user;
+
+pub use auth_user::AuthUser;
+
+use app_audit::{AuditEntry, AuditLog, Outcome, action};
+use app_auth::{
+    api_key::{API_KEY_SOURCE, authenticate_api_key},
+    cookie::{ACCESS_COOKIE, check_csrf, get_cookie, is_safe_method},
+    mcp_client::authenticate_mcp_client,
+    mcp_oauth::{authenticate_mcp_user, is_mcp_source, protected_resource_metadata_url},
+    rbac::has_permission,
+    session::{ClientInfo, touch_session},
+};
+use app_config::AppConfig;
+use app_cryptography::jwt::Claims;
 use app_error::AppError;
-use app_redis::Redis;
 use app_state::AppState;
 use axum::{
-    extract::{Request, State},
-    http::{HeaderMap, HeaderValue, StatusCode, header::AUTHORIZATION},
+    extract::{ConnectInfo, Request, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    },
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use chrono::{Duration, Utc};
-use std::sync::Arc;
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
 use tracing::*;
 
 pub async fn web_auth_middleware(
@@ -23,149 +44,284 @@ pub async fn web_auth_middleware(
         req.uri()
     );
     let redis = state.redis.clone();
-    let config = state.config.clone();
-    let jwt_access_key = config.jwt_access_key.clone();
-    let jwt_access_session_minutes = config.jwt_access_session_minutes;
-    let jwt_refresh_key = config.jwt_refresh_key.clone();
+    let jwt_keys = state.jwt_keys.clone();
     let unauthorized =
         || AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0).into_response();
+    // Identity only ever comes from verified credentials, never from the client
+    strip_auth_headers(req.headers_mut());
+    // Route guards record their denials through the request
+    req.extensions_mut().insert(state.audit.clone());
+    attach_client(&state.config, &mut req);
 
     // IMPORTANT: make the header value owned so we don't keep borrowing `req`
-    let auth_header: String = match req
+    // Browsers in cookie mode send the access token as a cookie instead of the header
+    let (auth_header, from_cookie): (String, bool) = match req
         .headers()
         .get(AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string())
     {
-        Some(v) => v,
-        None => return unauthorized(),
+        Some(v) => (v, false),
+        None => match get_cookie(req.headers(), ACCESS_COOKIE) {
+            Some(token) => (format!("Bearer {}", token), true),
+            None => return unauthorized(),
+        },
     };
 
-    // Expect: "Authorization: Bearer <access_token> <refresh_token>"
+    // Expect: "Authorization: Bearer <access_token>" or "Authorization: ApiKey <key>"
+    // Expired access tokens are renewed by the client through `POST /auth/refresh`
     let parts: Vec<&str> = auth_header.split_whitespace().collect();
-    if parts.len() != 3 || !parts[0].eq_ignore_ascii_case("bearer") {
+    if parts.len() != 2 {
+        return unauthorized();
+    }
+    if parts[0].eq_ignore_ascii_case("apikey") {
+        // Machine clients get the identity of the key owner, limited to the key scopes
+        return match authenticate_api_key(&state.pg, parts[1]).await {
+            Ok(claim) => {
+                if !attach_user(&mut req, &claim) {
+                    return unauthorized();
+                }
+                info!("User {} approved using API key", claim.email);
+                next.run(req).await
+            }
+            Err(e) => {
+                record_denied(&state.audit, &req, None, "invalid API key");
+                e.into_response()
+            }
+        };
+    }
+    if !parts[0].eq_ignore_ascii_case("bearer") {
         return unauthorized();
     }
     let access_token = parts[1];
-    let refresh_token = parts[2];
 
-    // 1) Access token valid
-    if let Ok(access_token_data) = validate_token(Algorithm::HS256, &jwt_access_key, access_token) {
-        let access_claim = access_token_data.claims;
-        add_req_headers(&mut req, &access_claim, access_token);
+    match jwt_keys.verify::<Claims>(access_token) {
+        Ok(access_token_data) => {
+            let access_claim = access_token_data.claims;
+            // Tokens issued to MCP clients are only good for the MCP service
+            if is_mcp_source(&access_claim.source) {
+                record_denied(
+                    &state.audit,
+                    &req,
+                    Some((&access_claim.email, &access_claim.id)),
+                    "MCP access token",
+                );
+                return unauthorized();
+            }
+            // Revoked or expired sessions lose their access tokens immediately; live ones
+            // are kept from idling out
+            match touch_session(
+                &state.config,
+                &redis,
+                &access_claim.email,
+                access_claim.session,
+            )
+            .await
+            {
+                Ok(true) => (),
+                Ok(false) => {
+                    info!(
+                        "Session {} of {} is revoked",
+                        access_claim.session, access_claim.email
+                    );
+                    record_denied(
+                        &state.audit,
+                        &req,
+                        Some((&access_claim.email, &access_claim.id)),
+                        "revoked session",
+                    );
+                    return unauthorized();
+                }
+                Err(e) => return e.into_response(),
+            }
+            // Cookies are attached by the browser to any request, so state changes must
+            // prove they come from our pages
+            if from_cookie
+                && !is_safe_method(req.method())
+                && let Err(e) = check_csrf(&state.config, req.headers(), access_claim.session)
+            {
+                record_denied(
+                    &state.audit,
+                    &req,
+                    Some((&access_claim.email, &access_claim.id)),
+                    "invalid CSRF token",
+                );
+                return e.into_response();
+            }
+            if !attach_user(&mut req, &access_claim) {
+                return unauthorized();
+            }
+            info!("User {} approved using access token", access_claim.email);
+            next.run(req).await
+        }
+        Err(e) => {
+            debug!("Access token is not valid: {:?}", &e);
+            record_denied(&state.audit, &req, None, "invalid access token");
+            unauthorized()
+        }
+    }
+}
+
+/// Puts the `ClientInfo` of the caller into the request extensions; the client address is
+/// known when the server was started with `into_make_service_with_connect_info`
+fn attach_client(config: &AppConfig, req: &mut Request) {
+    let peer = match req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        None => return,
+        Some(ConnectInfo(peer)) => *peer,
+    };
+    let client = ClientInfo::new(config, req.headers(), peer, None);
+    req.extensions_mut().insert(client);
+}
 
-        info!("User {} approved using access token", access_claim.email);
+/// Audits a refused request with the `ClientInfo` put on it by `attach_client`. `actor` must
+/// come from verified credentials.
+fn record_denied(
+    audit: &AuditLog,
+    req: &Request,
+    actor: Option<(&str, &str)>, // Email and id
+    reason: &str,
+) {
+    let mut entry = AuditEntry::new(action::ACCESS, Outcome::Denied)
+        .target(&format!("{} {}", req.method(), req.uri().path()))
+        .detail("reason", reason);
+    if let Some(client) = req.extensions().get::<ClientInfo>() {
+        entry = entry.client(&client.ip, &client.user_agent);
+    }
+    if let Some((email, id)) = actor {
+        entry = entry.actor(email).actor_id(id);
+    }
+    audit.record(entry);
+}
 
-        let mut res = next.run(req).await;
-        add_res_headers(&mut res, access_token);
-        return res;
+/// Removes `x-auth-*` headers sent by the client; older versions of this middleware passed the
+/// identity in such headers, and a client must not be able to pose as another user with them
+fn strip_auth_headers(headers: &mut HeaderMap) {
+    let spoofed: Vec<_> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with("x-auth-"))
+        .cloned()
+        .collect();
+    for name in spoofed {
+        warn!("Dropping client-supplied header {}", name);
+        headers.remove(name);
     }
+}
 
-    // 2) Otherwise try refresh token
-    if let Ok(refresh_token_data) =
-        validate_token(Algorithm::HS256, &jwt_refresh_key, refresh_token)
-    {
-        let refresh_claim: Claims = refresh_token_data.claims;
-
-        if let Ok(redis_info_str) = Redis::get::<String>(
-            &redis,
-            &format!("{}:{}", refresh_claim.email, refresh_claim.session),
-        )
-        .await
-        {
-            if let Ok(redis_info) = serde_json::from_str::<RedisInfo>(&redis_info_str) {
-                if refresh_token == redis_info.token {
-                    // Regenerate access token
-                    let iat = Utc::now().timestamp();
-                    let exp =
-                        (Utc::now() + Duration::minutes(jwt_access_session_minutes)).timestamp();
-
-                    let access_claim = Claims {
-                        iat,
-                        exp,
-                        id: refresh_claim.id.clone(),
-                        name: refresh_claim.name.clone(),
-                        email: refresh_claim.email.clone(),
-                        session: refresh_claim.session,
-                        source: refresh_claim.source.clone(),
-                    };
-
-                    if let Ok(new_access_token) =
-                        generate_token(Algorithm::HS256, &jwt_access_key, access_claim)
-                    {
-                        add_req_headers(&mut req, &refresh_claim, &new_access_token);
-
-                        info!("User {} approved using refresh token", refresh_claim.email);
-
-                        let mut res = next.run(req).await;
-                        add_res_headers(&mut res, &new_access_token);
-                        return res;
-                    }
-                }
-            }
+/// Puts the caller into the request extensions for the `AuthUser` extractor
+fn attach_user(req: &mut Request, claim: &Claims) -> bool {
+    match AuthUser::from_claims(claim) {
+        None => {
+            error!("Claims of {} carry an invalid user id", claim.email);
+            false
+        }
+        Some(user) => {
+            req.extensions_mut().insert(user);
+            true
         }
     }
-    unauthorized()
 }
 
-fn add_req_headers(req: &mut Request, claim: &Claims, access_token: &str) {
-    // If any value is invalid for headers, we just skip it (don’t panic middleware)
-    let _ = req.headers_mut().insert(
-        "x-auth-name",
-        HeaderValue::from_str(&claim.name).unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
-    let _ = req.headers_mut().insert(
-        "x-auth-email",
-        HeaderValue::from_str(&claim.email).unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
-    let _ = req.headers_mut().insert(
-        "x-auth-id",
-        HeaderValue::from_str(&claim.id).unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
-    let _ = req.headers_mut().insert(
-        "x-auth-session",
-        HeaderValue::from_str(&claim.session.to_string())
-            .unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
-    let _ = req.headers_mut().insert(
-        "x-auth-access-token",
-        HeaderValue::from_str(access_token).unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
+/// Route guard that only lets callers holding `permission` through.
+/// It reads the `AuthUser` that `web_auth_middleware` put on the request, so it must run
+/// after it, e.g.
+/// `get(get_user).route_layer(require_permission(USERS_READ))` inside the `/auth` router.
+pub fn require_permission(permission: &'static str) -> RequirePermissionLayer {
+    RequirePermissionLayer { permission }
 }
 
-fn add_res_headers(res: &mut Response, access_token: &str) {
-    let _ = res.headers_mut().insert(
-        "x-auth-access-token",
-        HeaderValue::from_str(access_token).unwrap_or_else(|_| HeaderValue::from_static("")),
-    );
+#[derive(Debug, Clone)]
+pub struct RequirePermissionLayer {
+    permission: &'static str,
 }
 
-pub fn get_email(headers: &HeaderMap) -> Option<String> {
-    headers
-        .get("x-auth-email")
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string())
+impl<S> Layer<S> for RequirePermissionLayer {
+    type Service = RequirePermission<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermission {
+            inner,
+            permission: self.permission,
+        }
+    }
 }
 
-pub fn get_session(headers: &HeaderMap) -> Option<u64> {
-    match headers.get("x-auth-session").and_then(|v| v.to_str().ok()) {
-        None => None,
-        Some(x) => match x.parse::<u64>() {
-            Err(e) => {
-                error!(
-                    "Middleware cannot parse session information from header. {:?}",
-                    &e
-                );
-                None
-            }
-            Ok(x) => Some(x),
-        },
+#[derive(Debug, Clone)]
+pub struct RequirePermission<S> {
+    inner: S,
+    permission: &'static str,
+}
+
+impl<S> Service<Request> for RequirePermission<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let user = req.extensions().get::<AuthUser>();
+        if user.is_some_and(|u| has_permission(&u.permissions, self.permission)) {
+            return Box::pin(self.inner.call(req));
+        }
+        let (email, id) = user
+            .map(|u| (u.email.clone(), u.id.to_string()))
+            .unwrap_or_default();
+        warn!(
+            "Permission {} denied for {} {} ({})",
+            self.permission,
+            req.method(),
+            req.uri(),
+            &email
+        );
+        if let Some(audit) = req.extensions().get::<AuditLog>() {
+            let reason = format!("missing permission {}", self.permission);
+            record_denied(audit, &req, Some((&email, &id)), &reason);
+        }
+        let res = AppError::new("Forbidden", StatusCode::FORBIDDEN, 4).into_response();
+        Box::pin(async move { Ok(res) })
+    }
+}
+
+/// Route guard for the self-service account routes (sessions, API keys, second factors,
+/// linked identities, password): they need a login session, so a caller using an API key is
+/// refused whatever the key scopes. Runs after `web_auth_middleware`, e.g.
+/// `.route_layer(middleware::from_fn(require_session))`.
+pub async fn require_session(req: Request, next: Next) -> Response {
+    let user = req.extensions().get::<AuthUser>();
+    if user.is_some_and(|u| u.source != API_KEY_SOURCE) {
+        return next.run(req).await;
+    }
+    let (email, id) = user
+        .map(|u| (u.email.clone(), u.id.to_string()))
+        .unwrap_or_default();
+    warn!(
+        "Account route {} {} refused for {} without a login session",
+        req.method(),
+        req.uri(),
+        &email
+    );
+    if let Some(audit) = req.extensions().get::<AuditLog>() {
+        record_denied(audit, &req, Some((&email, &id)), "API key on account route");
     }
+    AppError::new(
+        "This action needs a signed-in session!",
+        StatusCode::FORBIDDEN,
+        4,
+    )
+    .into_response()
 }
 
+/// Authenticates MCP clients and puts their `McpClientIdentity` into the request extensions,
+/// where tool handlers find it through the HTTP parts of their `RequestContext`
 pub async fn mcp_auth_middleware(
     State(state): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
     next: Next,
 ) -> Response {
     info!(
@@ -173,9 +329,8 @@ pub async fn mcp_auth_middleware(
         req.method(),
         req.uri()
     );
-    let config = state.config.clone();
-    let unauthorized =
-        || AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0).into_response();
+    let unauthorized = || mcp_unauthorized(&state.config);
+    attach_client(&state.config, &mut req);
 
     // IMPORTANT: make the header value owned so we don't keep borrowing `req`
     let auth_header: String = match req
@@ -191,9 +346,139 @@ pub async fn mcp_auth_middleware(
     if parts.len() != 2 || !parts[0].eq_ignore_ascii_case("bearer") {
         return unauthorized();
     }
-    if &config.mcp_token != parts[1] {
-        error!("MCP token is not valid: {}", &parts[1]);
-        return unauthorized();
-    }
+    // The token itself is never logged. Access tokens from `/oauth2/token` are JWTs.
+    let token = parts[1];
+    let authenticated = if state.config.mcp_oauth.enabled && token.matches('.').count() == 2 {
+        authenticate_mcp_user(&state.redis, &state.jwt_keys, token).await
+    } else {
+        authenticate_mcp_client(&state.pg, &state.config, token).await
+    };
+    let client = match authenticated {
+        Ok(client) => client,
+        Err(err) => {
+            record_denied(&state.audit, &req, None, "invalid MCP token");
+            if err.status == StatusCode::UNAUTHORIZED {
+                return unauthorized();
+            }
+            return err.into_response();
+        }
+    };
+    debug!("MCP client {} authenticated", &client.name);
+    req.extensions_mut().insert(client);
     next.run(req).await
 }
+
+/// `401` pointing OAuth clients to the protected resource metadata (RFC 9728 §5.1)
+fn mcp_unauthorized(config: &AppConfig) -> Response {
+    let mut res = AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0).into_response();
+    if config.mcp_oauth.enabled {
+        let challenge = protected_resource_metadata_url(config)
+            .map(|url| format!("Bearer resource_metadata=\"{}\"", url))
+            .and_then(|v| HeaderValue::from_str(&v).ok());
+        if let Some(challenge) = challenge {
+            res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+        }
+    }
+    res
+}
+
+// cargo test --package middleware check_require_permission -- --nocapture
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn test_user(source: &str, permissions: &str) -> AuthUser {
+        AuthUser {
+            id: uuid::Uuid::new_v4(),
+            name: "Test".into(),
+            email: "test@unit".into(),
+            session: 1,
+            source: source.into(),
+            roles: vec![],
+            permissions: permissions
+                .split(',')
+                .filter(|p| !p.is_empty())
+                .map(|p| p.to_string())
+                .collect(),
+            expires_at: i64::MAX,
+        }
+    }
+
+    async fn call(permissions: Option<&str>) -> StatusCode {
+        let app: Router = Router::new().route(
+            "/users",
+            get(|| async { "ok" }).route_layer(require_permission("users:read")),
+        );
+        let mut req = Request::builder()
+            .uri("/users")
+            .body(Body::empty())
+            .unwrap();
+        if let Some(p) = permissions {
+            // Set by `web_auth_middleware` from the token claims
+            req.extensions_mut().insert(test_user("local", p));
+        }
+        let res = app.oneshot(req).await.unwrap();
+        res.status()
+    }
+
+    #[test]
+    fn check_require_permission() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            assert_eq!(call(Some("kb:read,users:read")).await, StatusCode::OK);
+            assert_eq!(call(Some("users:write")).await, StatusCode::FORBIDDEN);
+            assert_eq!(call(Some("users:read:all")).await, StatusCode::FORBIDDEN);
+            assert_eq!(call(Some("")).await, StatusCode::FORBIDDEN);
+            assert_eq!(call(None).await, StatusCode::FORBIDDEN);
+        })
+    }
+
+    async fn call_account_route(user: Option<AuthUser>) -> StatusCode {
+        let app: Router = Router::new().route(
+            "/identities/{provider}",
+            axum::routing::post(|| async { "ok" })
+                .route_layer(axum::middleware::from_fn(require_session)),
+        );
+        let mut req = Request::builder()
+            .method("POST")
+            .uri("/identities/google")
+            .body(Body::empty())
+            .unwrap();
+        if let Some(user) = user {
+            req.extensions_mut().insert(user);
+        }
+        app.oneshot(req).await.unwrap().status()
+    }
+
+    // cargo test --package middleware check_require_session -- --nocapture
+    #[test]
+    fn check_require_session() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let owner = test_user("local", "users:write");
+            assert_eq!(call_account_route(Some(owner)).await, StatusCode::OK);
+            let oauth = test_user("google", "");
+            assert_eq!(call_account_route(Some(oauth)).await, StatusCode::OK);
+            // Neither an unscoped nor a fully scoped key may touch the owner's account
+            let key = test_user(API_KEY_SOURCE, "");
+            assert_eq!(call_account_route(Some(key)).await, StatusCode::FORBIDDEN);
+            let key = test_user(API_KEY_SOURCE, "users:write,sessions:admin");
+            assert_eq!(call_account_route(Some(key)).await, StatusCode::FORBIDDEN);
+            assert_eq!(call_account_route(None).await, StatusCode::FORBIDDEN);
+        })
+    }
+
+    // cargo test --package middleware check_strip_auth_headers -- --nocapture
+    #[test]
+    fn check_strip_auth_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-auth-email", "admin@unit".parse().unwrap());
+        headers.insert("x-auth-permissions", "users:write".parse().unwrap());
+        headers.insert("x-request-id", "1".parse().unwrap());
+        strip_auth_headers(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key("x-request-id"));
+    }
+}
@@ -12,6 +12,9 @@ pub use deadpool_redis::Pool as RdPool;
 pub use deadpool_redis::redis::AsyncCommands;
 pub use deadpool_redis::redis::RedisError;
 
+/// Number of keys requested per `SCAN` round trip
+const SCAN_COUNT: usize = 100;
+
 /// This struct provides redis functions
 pub struct Redis;
 
@@ -49,6 +52,45 @@ impl Redis {
         res
     }
 
+    /// This is redis `SCAN` command; walks the cursor until every key matching the pattern
+    /// is collected without blocking the server like `KEYS` does
+    pub async fn scan(pool: &RdPool, pattern: &str) -> Result<Vec<String>, RedisError> {
+        let mut client = Self::_get_connection(pool).await?;
+        let mut cursor: u64 = 0;
+        let mut keys: Vec<String> = Vec::new();
+        loop {
+            let (next, batch): (u64, Vec<String>) = cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut client)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        // SCAN may return a key more than once
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Escapes glob characters so a value can be used literally inside a `SCAN`/`KEYS` pattern
+    pub fn escape_pattern(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
     /// This is redis `GET` command
     pub async fn get<T>(pool: &RdPool, key: &str) -> Result<T, RedisError>
     where
@@ -59,6 +101,17 @@ impl Redis {
         values
     }
 
+    /// This is redis `GETDEL` command; reads a key and removes it atomically
+    pub async fn getdel<T>(pool: &RdPool, key: &str) -> Result<T, RedisError>
+    where
+        T: FromRedisValue,
+    {
+        let mut client = Self::_get_connection(pool).await?;
+        let values: Result<T, RedisError> =
+            cmd("GETDEL").arg(&[&key]).query_async(&mut client).await;
+        values
+    }
+
     /// This is redis `MGET` command
     pub async fn mget<T>(pool: &RdPool, keys: Vec<&str>) -> Result<Vec<Vec<T>>, RedisError>
     where
@@ -78,6 +131,23 @@ impl Redis {
         res
     }
 
+    /// This is redis `SET` command with `EX` (expire time in seconds)
+    pub async fn set_ex(
+        pool: &RdPool,
+        key: &str,
+        value: &str,
+        seconds: u64,
+    ) -> Result<(), RedisError> {
+        let mut client = Self::_get_connection(pool).await?;
+        let res: Result<(), RedisError> = cmd("SET")
+            .arg(&[key, value])
+            .arg("EX")
+            .arg(seconds)
+            .query_async(&mut client)
+            .await;
+        res
+    }
+
     /// This is redis `DEL` command
     pub async fn del(pool: &RdPool, key: Vec<&str>) -> Result<(), RedisError> {
         let mut client = Self::_get_connection(pool).await?;
@@ -93,6 +163,41 @@ impl Redis {
         res
     }
 
+    /// This is redis `INCR` command, returns the new value
+    pub async fn incr(pool: &RdPool, key: &str) -> Result<i64, RedisError> {
+        let mut client = Self::_get_connection(pool).await?;
+        let res: Result<i64, RedisError> = cmd("INCR").arg(&[key]).query_async(&mut client).await;
+        res
+    }
+
+    /// This is redis `TTL` command; -2 when the key does not exist, -1 without expiry
+    pub async fn ttl(pool: &RdPool, key: &str) -> Result<i64, RedisError> {
+        let mut client = Self::_get_connection(pool).await?;
+        let res: Result<i64, RedisError> = cmd("TTL").arg(&[key]).query_async(&mut client).await;
+        res
+    }
+
+    /// This is redis `EVAL` command; the Lua script runs atomically on the server
+    pub async fn eval<T>(
+        pool: &RdPool,
+        script: &str,
+        keys: Vec<&str>,
+        args: Vec<&str>,
+    ) -> Result<T, RedisError>
+    where
+        T: FromRedisValue,
+    {
+        let mut client = Self::_get_connection(pool).await?;
+        let res: Result<T, RedisError> = cmd("EVAL")
+            .arg(script)
+            .arg(keys.len())
+            .arg(&keys)
+            .arg(&args)
+            .query_async(&mut client)
+            .await;
+        res
+    }
+
     /// This is redis `EXPIRE` command
     pub async fn expire(pool: &RdPool, key: &str, time: i64) -> Result<(), RedisError> {
         let mut client = Self::_get_connection(pool).await?;
@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub details: serde_json::Value,
+}
+
+impl AuditEvent {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../SQL/audit/select_base.sql")
+    }
+    #[inline]
+    pub fn count_query() -> &'static str {
+        include_str!("../../../SQL/audit/count_base.sql")
+    }
+    /// `WHERE` clause of the filters `$1` actor .. `$6` until, NULL meaning any
+    #[inline]
+    pub fn filter_query() -> &'static str {
+        include_str!("../../../SQL/audit/filter.sql")
+    }
+    #[inline]
+    pub fn insert_batch_query() -> &'static str {
+        include_str!("../../../SQL/audit/insert_batch.sql")
+    }
+}
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/api_keys/select_base.sql")
+    }
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/api_keys/insert_query.sql")
+    }
+    #[inline]
+    pub fn update_query() -> &'static str {
+        include_str!("../../../../SQL/auth/api_keys/update_query.sql")
+    }
+    #[inline]
+    pub fn delete_query() -> &'static str {
+        include_str!("../../../../SQL/auth/api_keys/delete_query.sql")
+    }
+    #[inline]
+    pub fn touch_query() -> &'static str {
+        include_str!("../../../../SQL/auth/api_keys/touch_query.sql")
+    }
+}
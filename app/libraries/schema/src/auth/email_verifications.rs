@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct EmailVerification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl EmailVerification {
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/email_verifications/insert_query.sql")
+    }
+    #[inline]
+    pub fn consume_query() -> &'static str {
+        include_str!("../../../../SQL/auth/email_verifications/consume_query.sql")
+    }
+    #[inline]
+    pub fn delete_by_user_query() -> &'static str {
+        include_str!("../../../../SQL/auth/email_verifications/delete_by_user.sql")
+    }
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct Identity {
+    pub id: Uuid,
+    pub provider: String,
+    pub subject: String,
+    pub user_id: Uuid,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Identity {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/identities/select_base.sql")
+    }
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/identities/insert_query.sql")
+    }
+    #[inline]
+    pub fn delete_by_user_query() -> &'static str {
+        include_str!("../../../../SQL/auth/identities/delete_by_user.sql")
+    }
+}
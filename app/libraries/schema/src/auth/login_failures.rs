@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct LoginFailure {
+    pub id: i64,
+    pub email: String,
+    pub user_id: Option<Uuid>,
+    pub ip: String,
+    pub user_agent: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LoginFailure {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/login_failures/select_base.sql")
+    }
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/login_failures/insert_query.sql")
+    }
+}
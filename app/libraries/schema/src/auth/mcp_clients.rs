@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct McpClient {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub token_hash: String,
+    pub allowed_tools: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl McpClient {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mcp_clients/select_base.sql")
+    }
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mcp_clients/insert_query.sql")
+    }
+    #[inline]
+    pub fn update_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mcp_clients/update_query.sql")
+    }
+    #[inline]
+    pub fn delete_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mcp_clients/delete_query.sql")
+    }
+    #[inline]
+    pub fn touch_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mcp_clients/touch_query.sql")
+    }
+}
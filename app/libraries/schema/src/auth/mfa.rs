@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct Totp {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub last_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Totp {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/totp_select_base.sql")
+    }
+    #[inline]
+    pub fn upsert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/totp_upsert.sql")
+    }
+    #[inline]
+    pub fn confirm_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/totp_confirm.sql")
+    }
+    #[inline]
+    pub fn use_step_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/totp_use_step.sql")
+    }
+    #[inline]
+    pub fn delete_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/totp_delete.sql")
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct RecoveryCode {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub code_hash: String,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RecoveryCode {
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/recovery_insert.sql")
+    }
+    #[inline]
+    pub fn delete_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/recovery_delete.sql")
+    }
+    #[inline]
+    pub fn use_query() -> &'static str {
+        include_str!("../../../../SQL/auth/mfa/recovery_use.sql")
+    }
+}
@@ -1 +1,10 @@
+pub mod api_keys;
+pub mod email_verifications;
+pub mod identities;
+pub mod login_failures;
+pub mod mcp_clients;
+pub mod mfa;
+pub mod oauth_clients;
+pub mod password_resets;
+pub mod roles;
 pub mod users;
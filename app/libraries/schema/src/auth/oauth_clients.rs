@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub client_secret_hash: Option<String>, // None for public clients
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub token_endpoint_auth_method: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OAuthClient {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/oauth_clients/select_base.sql")
+    }
+    #[inline]
+    pub fn insert_query() -> &'static str {
+        include_str!("../../../../SQL/auth/oauth_clients/insert_query.sql")
+    }
+}
@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Deserialize, Clone, FromRow, Serialize, JsonSchema)]
+pub struct Role {
+    pub id: i32,
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Role {
+    #[inline]
+    pub fn select_query() -> &'static str {
+        include_str!("../../../../SQL/auth/roles/select_base.sql")
+    }
+    #[inline]
+    pub fn select_by_user_query() -> &'static str {
+        include_str!("../../../../SQL/auth/roles/select_by_user.sql")
+    }
+    #[inline]
+    pub fn select_by_email_query() -> &'static str {
+        include_str!("../../../../SQL/auth/roles/select_by_email.sql")
+    }
+    #[inline]
+    pub fn assign_by_email_query() -> &'static str {
+        include_str!("../../../../SQL/auth/roles/assign_by_email.sql")
+    }
+    #[inline]
+    pub fn clear_by_email_query() -> &'static str {
+        include_str!("../../../../SQL/auth/roles/clear_by_email.sql")
+    }
+}
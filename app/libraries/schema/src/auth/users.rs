@@ -9,12 +9,19 @@ pub struct User {
     pub id: Uuid,
     pub email: String,
     pub name: String,
-    pub password_hash: String,
+    pub password_hash: Option<String>, // None for users provisioned by an external provider
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub disabled_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>, // Soft deleted, kept for the audit trail
 }
 
 impl User {
+    /// Neither disabled nor deleted, so the user may sign in
+    pub fn is_active(&self) -> bool {
+        self.disabled_at.is_none() && self.deleted_at.is_none()
+    }
     #[inline]
     pub fn select_query() -> &'static str {
         include_str!("../../../../SQL/auth/users/select_base.sql")
@@ -24,7 +31,47 @@ impl User {
         include_str!("../../../../SQL/auth/users/insert_query.sql")
     }
     #[inline]
+    pub fn insert_external_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/insert_external.sql")
+    }
+    #[inline]
+    pub fn select_by_identity_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/select_by_identity.sql")
+    }
+    #[inline]
     pub fn change_password_query() -> &'static str {
         include_str!("../../../../SQL/auth/users/change_password.sql")
     }
+    #[inline]
+    pub fn count_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/count_base.sql")
+    }
+    /// `WHERE` clause of the filters `$1` email and `$2` name (substrings), `$3` disabled
+    /// (NULL meaning any) and `$4` whether to include deleted users
+    #[inline]
+    pub fn filter_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/filter.sql")
+    }
+    /// `$1` id, `$2` name and `$3` email, NULL keeping the current value
+    #[inline]
+    pub fn update_profile_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/update_profile.sql")
+    }
+    #[inline]
+    pub fn set_disabled_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/set_disabled.sql")
+    }
+    #[inline]
+    pub fn soft_delete_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/soft_delete.sql")
+    }
+    #[inline]
+    pub fn set_password_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/set_password.sql")
+    }
+    /// `$1` id, `$2` the address that was verified
+    #[inline]
+    pub fn mark_verified_query() -> &'static str {
+        include_str!("../../../../SQL/auth/users/mark_verified.sql")
+    }
 }
@@ -1,4 +1,6 @@
+pub mod audit;
 pub mod auth;
 pub mod customer;
 pub mod kb;
+pub mod outbox;
 pub mod tools;
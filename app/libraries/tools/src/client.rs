@@ -0,0 +1,13 @@
+use app_auth::mcp_client::McpClientIdentity;
+use axum::http::request::Parts;
+use rmcp::{RoleServer, service::RequestContext};
+
+/// The MCP client a tool call came from. `mcp_auth_middleware` stores it in the HTTP request,
+/// whose parts the streamable HTTP transport hands to every `RequestContext`.
+pub fn mcp_client(ctx: &RequestContext<RoleServer>) -> Option<McpClientIdentity> {
+    ctx.extensions
+        .get::<Parts>()?
+        .extensions
+        .get::<McpClientIdentity>()
+        .cloned()
+}
@@ -1,4 +1,5 @@
 pub mod calculator;
+pub mod client;
 pub mod counter;
 pub mod customer;
 pub mod rag;
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
-use app_dto::auth::user::{McpGetUserByEmailInput, McpGetUserByEmailOutput, McpGetUsersOutput};
+use app_dto::auth::user::{
+    McpGetUserByEmailInput, McpGetUserByEmailOutput, McpGetUsersOutput, PublicUser,
+};
 use app_error::AppError;
 use app_schema::auth::users::User;
 use app_state::AppState;
@@ -34,22 +36,32 @@ impl UserTools {
         Parameters(args): Parameters<McpGetUserByEmailInput>,
     ) -> Result<Json<McpGetUserByEmailOutput>, McpError> {
         let pg = self.state.pg.clone();
-        let result =
-            sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", &User::select_query()))
-                .bind(&args.email)
-                .fetch_one(&pg)
-                .await
-                .map_err(AppError::from)?;
-        Ok(Json(McpGetUserByEmailOutput { result }))
+        let result = sqlx::query_as::<_, User>(&format!(
+            "{} WHERE email = $1 AND deleted_at IS NULL",
+            &User::select_query()
+        ))
+        .bind(&args.email)
+        .fetch_one(&pg)
+        .await
+        .map_err(AppError::from)?;
+        Ok(Json(McpGetUserByEmailOutput {
+            result: result.into(),
+        }))
     }
 
     #[tool(description = "Shows list of all users")]
     async fn get_users(&self) -> Result<Json<McpGetUsersOutput>, McpError> {
         let pg = self.state.pg.clone();
-        let results = sqlx::query_as::<_, User>(User::select_query())
-            .fetch_all(&pg)
-            .await
-            .map_err(AppError::from)?;
+        let results = sqlx::query_as::<_, User>(&format!(
+            "{} WHERE deleted_at IS NULL ORDER BY email",
+            User::select_query()
+        ))
+        .fetch_all(&pg)
+        .await
+        .map_err(AppError::from)?
+        .into_iter()
+        .map(PublicUser::from)
+        .collect();
         Ok(Json(McpGetUsersOutput { results }))
     }
 }
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS auth.user_roles;
+
+DROP TRIGGER IF EXISTS trg_roles_updated_at ON auth.roles;
+
+DROP TABLE IF EXISTS auth.roles;
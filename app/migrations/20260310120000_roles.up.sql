@@ -0,0 +1,50 @@
+-- Roles carry a list of permissions such as 'users:write'
+CREATE TABLE IF NOT EXISTS auth.roles (
+    id              SERIAL PRIMARY KEY,
+    name            TEXT NOT NULL UNIQUE,
+    description     TEXT NOT NULL DEFAULT '',
+    permissions     TEXT[] NOT NULL DEFAULT '{}',
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trg_roles_updated_at
+BEFORE UPDATE ON auth.roles
+FOR EACH ROW
+EXECUTE FUNCTION auth.set_updated_at();
+
+-- Many-to-many between users and roles
+CREATE TABLE IF NOT EXISTS auth.user_roles (
+    user_id         UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    role_id         INTEGER NOT NULL REFERENCES auth.roles(id) ON DELETE CASCADE,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role_id)
+);
+
+-- Seed roles
+INSERT INTO auth.roles (name, description, permissions)
+VALUES
+    (
+        'admin',
+        'Full access including user and session administration',
+        ARRAY['users:read', 'users:write', 'sessions:admin', 'customers:read', 'kb:read', 'kb:write', 'agent:use']
+    ),
+    (
+        'analyst',
+        'Works with customers, the knowledge base and the agent',
+        ARRAY['customers:read', 'kb:read', 'kb:write', 'agent:use']
+    ),
+    (
+        'viewer',
+        'Read-only access to customers and the knowledge base',
+        ARRAY['customers:read', 'kb:read']
+    )
+ON CONFLICT (name) DO NOTHING;
+
+-- Existing users start as viewers
+INSERT INTO auth.user_roles (user_id, role_id)
+SELECT u.id, r.id
+FROM auth.users u
+CROSS JOIN auth.roles r
+WHERE r.name = 'viewer'
+ON CONFLICT DO NOTHING;
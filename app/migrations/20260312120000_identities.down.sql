@@ -0,0 +1,8 @@
+DROP TRIGGER IF EXISTS trg_identities_updated_at ON auth.identities;
+
+DROP TABLE IF EXISTS auth.identities;
+
+-- Passwordless users keep an unusable hash
+UPDATE auth.users SET password_hash = '' WHERE password_hash IS NULL;
+
+ALTER TABLE auth.users ALTER COLUMN password_hash SET NOT NULL;
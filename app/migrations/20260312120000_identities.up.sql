@@ -0,0 +1,22 @@
+-- Users provisioned from an external provider have no local password
+ALTER TABLE auth.users ALTER COLUMN password_hash DROP NOT NULL;
+
+-- External sign-in identities (e.g. Google `sub`) linked to local users
+CREATE TABLE IF NOT EXISTS auth.identities (
+    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    provider        TEXT NOT NULL,
+    subject         TEXT NOT NULL,
+    user_id         UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    email           TEXT NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, subject),
+    UNIQUE (provider, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_identities_user_id ON auth.identities(user_id);
+
+CREATE TRIGGER trg_identities_updated_at
+BEFORE UPDATE ON auth.identities
+FOR EACH ROW
+EXECUTE FUNCTION auth.set_updated_at();
@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS trg_api_keys_updated_at ON auth.api_keys;
+
+DROP TABLE IF EXISTS auth.api_keys;
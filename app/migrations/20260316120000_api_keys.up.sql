@@ -0,0 +1,20 @@
+-- API keys for machine clients; only a SHA-256 hash of the secret is stored
+CREATE TABLE IF NOT EXISTS auth.api_keys (
+    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id         UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    name            TEXT NOT NULL,
+    prefix          TEXT NOT NULL UNIQUE,
+    key_hash        TEXT NOT NULL,
+    scopes          TEXT[] NOT NULL DEFAULT '{}',
+    expires_at      TIMESTAMPTZ,
+    last_used_at    TIMESTAMPTZ,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user_id ON auth.api_keys(user_id);
+
+CREATE TRIGGER trg_api_keys_updated_at
+BEFORE UPDATE ON auth.api_keys
+FOR EACH ROW
+EXECUTE FUNCTION auth.set_updated_at();
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS auth.recovery_codes;
+
+DROP TRIGGER IF EXISTS trg_totp_updated_at ON auth.totp;
+
+DROP TABLE IF EXISTS auth.totp;
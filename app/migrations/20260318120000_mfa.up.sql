@@ -0,0 +1,25 @@
+-- TOTP (RFC 6238) second factor; enrollment is pending until the first code is confirmed
+CREATE TABLE IF NOT EXISTS auth.totp (
+    user_id         UUID PRIMARY KEY REFERENCES auth.users(id) ON DELETE CASCADE,
+    secret          TEXT NOT NULL, -- base32
+    confirmed_at    TIMESTAMPTZ,
+    last_step       BIGINT, -- Last accepted time step, against replay
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trg_totp_updated_at
+BEFORE UPDATE ON auth.totp
+FOR EACH ROW
+EXECUTE FUNCTION auth.set_updated_at();
+
+-- One-time recovery codes, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS auth.recovery_codes (
+    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id         UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    code_hash       TEXT NOT NULL,
+    used_at         TIMESTAMPTZ,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user_id ON auth.recovery_codes(user_id);
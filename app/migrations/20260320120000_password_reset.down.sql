@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS app.outbox;
+
+DROP TABLE IF EXISTS auth.password_resets;
@@ -0,0 +1,13 @@
+-- Failed password checks, kept for review by the security team
+CREATE TABLE IF NOT EXISTS auth.login_failures (
+    id              BIGSERIAL PRIMARY KEY,
+    email           TEXT NOT NULL,
+    user_id         UUID REFERENCES auth.users(id) ON DELETE SET NULL, -- NULL for unknown emails
+    ip              TEXT NOT NULL,
+    user_agent      TEXT NOT NULL DEFAULT '',
+    reason          TEXT NOT NULL, -- unknown_user, no_password, wrong_password, throttled
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_login_failures_email ON auth.login_failures(email, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_login_failures_ip ON auth.login_failures(ip, created_at DESC);
@@ -0,0 +1,7 @@
+UPDATE auth.roles
+SET
+    permissions = array_remove(permissions, 'audit:read');
+
+DROP TABLE IF EXISTS audit.events;
+
+DROP SCHEMA IF EXISTS audit;
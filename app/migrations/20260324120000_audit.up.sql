@@ -0,0 +1,27 @@
+CREATE SCHEMA IF NOT EXISTS audit;
+
+-- Security relevant events: logins, token refreshes, password and user administration
+CREATE TABLE IF NOT EXISTS audit.events (
+    id              BIGSERIAL PRIMARY KEY,
+    occurred_at     TIMESTAMPTZ NOT NULL,
+    actor           TEXT, -- Email of the caller, NULL when unknown
+    actor_id        UUID,
+    action          TEXT NOT NULL, -- login, logout, token.refresh, password.change, ...
+    target          TEXT, -- Subject of the action, e.g. the email of an administered user
+    ip              TEXT,
+    user_agent      TEXT,
+    outcome         TEXT NOT NULL, -- success, failure, denied
+    details         JSONB NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_events_occurred_at ON audit.events(occurred_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor ON audit.events(actor, occurred_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_events_action ON audit.events(action, occurred_at DESC);
+
+-- Admins can read the audit log
+UPDATE auth.roles
+SET
+    permissions = array_append(permissions, 'audit:read')
+WHERE
+    name = 'admin'
+    AND NOT ('audit:read' = ANY(permissions));
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS auth.idx_users_active;
+
+ALTER TABLE auth.users
+    DROP COLUMN IF EXISTS deleted_at,
+    DROP COLUMN IF EXISTS disabled_at;
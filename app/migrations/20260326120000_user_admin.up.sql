@@ -0,0 +1,6 @@
+-- Disabled users cannot sign in; deleted users are kept for the audit trail but hidden
+ALTER TABLE auth.users
+    ADD COLUMN IF NOT EXISTS disabled_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_users_active ON auth.users(created_at DESC) WHERE deleted_at IS NULL;
@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS auth.email_verifications;
+
+ALTER TABLE auth.users
+    DROP COLUMN IF EXISTS email_verified_at;
@@ -0,0 +1,22 @@
+-- Accounts created before verification existed are treated as verified
+ALTER TABLE auth.users
+    ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ;
+
+UPDATE auth.users
+SET
+    email_verified_at = created_at
+WHERE
+    email_verified_at IS NULL;
+
+-- Single-use email verification tokens, stored as SHA-256 hashes
+CREATE TABLE IF NOT EXISTS auth.email_verifications (
+    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id         UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    email           TEXT NOT NULL, -- Address the token was mailed to
+    token_hash      TEXT NOT NULL UNIQUE,
+    expires_at      TIMESTAMPTZ NOT NULL,
+    used_at         TIMESTAMPTZ,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_verifications_user_id ON auth.email_verifications(user_id);
@@ -0,0 +1,5 @@
+UPDATE auth.roles
+SET
+    permissions = array_remove(permissions, 'mcp:admin');
+
+DROP TABLE IF EXISTS auth.mcp_clients;
@@ -0,0 +1,25 @@
+-- Named MCP clients; only a SHA-256 hash of the token is stored
+CREATE TABLE IF NOT EXISTS auth.mcp_clients (
+    id              UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name            TEXT NOT NULL UNIQUE,
+    prefix          TEXT NOT NULL UNIQUE,
+    token_hash      TEXT NOT NULL,
+    allowed_tools   TEXT[] NOT NULL DEFAULT '{}', -- Tool names, '*' for every tool
+    expires_at      TIMESTAMPTZ,
+    last_used_at    TIMESTAMPTZ,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at      TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trg_mcp_clients_updated_at
+BEFORE UPDATE ON auth.mcp_clients
+FOR EACH ROW
+EXECUTE FUNCTION auth.set_updated_at();
+
+-- Admins manage MCP clients
+UPDATE auth.roles
+SET
+    permissions = array_append(permissions, 'mcp:admin')
+WHERE
+    name = 'admin'
+    AND NOT ('mcp:admin' = ANY(permissions));
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS auth.oauth_clients;
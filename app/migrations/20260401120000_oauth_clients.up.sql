@@ -0,0 +1,10 @@
+-- OAuth 2.1 clients of the MCP endpoint, registered dynamically (RFC 7591).
+-- Public clients have no secret and rely on PKCE; secrets are stored as SHA-256 hashes.
+CREATE TABLE IF NOT EXISTS auth.oauth_clients (
+    client_id                   TEXT PRIMARY KEY,
+    client_secret_hash          TEXT,
+    client_name                 TEXT NOT NULL DEFAULT '',
+    redirect_uris               TEXT[] NOT NULL,
+    token_endpoint_auth_method  TEXT NOT NULL, -- none, client_secret_post
+    created_at                  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
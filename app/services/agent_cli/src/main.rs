@@ -18,7 +18,9 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     let session_id = Uuid::new_v4().to_string();
     let config = AppConfig::new();
     // Build Agent
-    let (runner, session) = agent_builder(&config).await?;
+    let (agents, session) = agent_builder(&config, None).await?;
+    // The console talks to the default agent
+    let runner = agents.get(None).unwrap().runner.clone();
 
     // Create a session
     let create_req = CreateRequest {
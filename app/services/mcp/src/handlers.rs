@@ -1,7 +1,12 @@
+use app_adk_utils::session::tools::AdkInjectContext;
+use app_auth::{
+    mcp_client::DEFAULT_CLIENT,
+    rbac::{CUSTOMERS_READ, KB_READ, KB_WRITE, USERS_READ, has_permission, load_access_by_email},
+};
 use app_state::AppState;
 use app_tools::{
-    calculator::CalculatorTools, counter::CounterTools, customer::CustomerTools, rag::RagTools,
-    users::UserTools,
+    calculator::CalculatorTools, client::mcp_client, counter::CounterTools,
+    customer::CustomerTools, rag::RagTools, users::UserTools,
 };
 use rmcp::{
     ErrorData as McpError, RoleServer, ServerHandler,
@@ -11,9 +16,11 @@ use rmcp::{
     service::RequestContext,
 };
 use std::sync::Arc;
+use tracing::*;
 
 #[derive(Clone)]
 pub struct McpHandler {
+    state: Arc<AppState>,
     calculator: CalculatorTools,
     counter: CounterTools,
     customer: CustomerTools,
@@ -24,6 +31,7 @@ pub struct McpHandler {
 impl McpHandler {
     pub fn new(state: Arc<AppState>) -> Self {
         Self {
+            state: state.clone(),
             calculator: CalculatorTools::new(),
             counter: CounterTools::new(state.clone()),
             customer: CustomerTools::new(state.clone()),
@@ -33,6 +41,89 @@ impl McpHandler {
     }
 }
 
+impl McpHandler {
+    /// Permission a user needs to run a tool; `None` for tools open to everyone
+    fn tool_permission(&self, name: &str) -> Option<&'static str> {
+        if self.customer.tool_router.has_route(name) {
+            Some(CUSTOMERS_READ)
+        } else if self.user.tool_router.has_route(name) {
+            Some(USERS_READ)
+        } else if name == "add_content_knowledge_based" {
+            Some(KB_WRITE)
+        } else if self.rag.tool_router.has_route(name) {
+            Some(KB_READ)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the MCP client of the request may see and call a tool
+    fn client_allows(&self, name: &str, ctx: &RequestContext<RoleServer>) -> bool {
+        mcp_client(ctx).is_some_and(|c| c.allows_tool(name))
+    }
+
+    /// User whose permissions apply to a call: the user an OAuth token was issued for, or the
+    /// user the built-in agent (client `default`) names in the ADK context. Other clients set
+    /// the request metadata themselves, so the user they name is not trusted.
+    fn trusted_user(&self, ctx: &RequestContext<RoleServer>) -> Option<String> {
+        let client = mcp_client(ctx)?;
+        if client.user.is_some() {
+            return client.user;
+        }
+        if client.name != DEFAULT_CLIENT {
+            return None;
+        }
+        AdkInjectContext::extract_adk_context(ctx).and_then(|c| c.user_id)
+    }
+
+    /// Checks the tools allowed to the MCP client, then the permissions of the trusted user of
+    /// the call. Tools that need a permission are denied when there is no trusted user.
+    async fn authorize_tool(
+        &self,
+        name: &str,
+        ctx: &RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if !self.client_allows(name, ctx) {
+            let client = mcp_client(ctx).map(|c| c.name).unwrap_or_default();
+            warn!("Tool {} is not allowed for MCP client {}", name, &client);
+            return Err(McpError {
+                code: ErrorCode(-32001),
+                message: format!("Tool '{}' is not allowed for this client", name).into(),
+                data: None,
+            });
+        }
+        let permission = match self.tool_permission(name) {
+            None => return Ok(()),
+            Some(p) => p,
+        };
+        let user = match self.trusted_user(ctx) {
+            None => {
+                warn!("Tool {} denied; no trusted user for {}", name, permission);
+                return Err(McpError {
+                    code: ErrorCode(-32001),
+                    message: format!("Tool '{}' needs a signed-in user", name).into(),
+                    data: None,
+                });
+            }
+            Some(u) => u,
+        };
+        let access = load_access_by_email(&self.state.pg, &user).await?;
+        if has_permission(&access.permissions, permission) {
+            return Ok(());
+        }
+        warn!("Tool {} denied for {}; missing {}", name, &user, permission);
+        Err(McpError {
+            code: ErrorCode(-32001),
+            message: format!(
+                "Permission '{}' is required for tool '{}'",
+                permission, name
+            )
+            .into(),
+            data: None,
+        })
+    }
+}
+
 impl ServerHandler for McpHandler {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
@@ -58,7 +149,7 @@ impl ServerHandler for McpHandler {
     async fn list_tools(
         &self,
         _req: Option<PaginatedRequestParams>,
-        _ctx: RequestContext<RoleServer>,
+        ctx: RequestContext<RoleServer>,
     ) -> Result<ListToolsResult, McpError> {
         let mut tools = Vec::new();
         // Calculator Tools
@@ -96,6 +187,8 @@ impl ServerHandler for McpHandler {
                 ..t
             });
         }
+        // Clients only see the tools they may call
+        tools.retain(|t| self.client_allows(&t.name, &ctx));
         Ok(ListToolsResult {
             tools,
             next_cursor: None,
@@ -116,6 +209,7 @@ impl ServerHandler for McpHandler {
             "__adk_tool_args".into(),
             JsonValue::Object(req.arguments.clone().unwrap_or_default()),
         );
+        self.authorize_tool(&req.name, &ctx).await?;
         // Check if Calculator owns this tool
         if self.calculator.tool_router.has_route(&req.name) {
             let calc_ctx = ToolCallContext::new(
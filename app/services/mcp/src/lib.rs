@@ -2,8 +2,12 @@ mod handlers;
 mod routings;
 
 use crate::routings::router;
+use app_audit::AuditLog;
+use app_auth::password_policy::PasswordPolicy;
 use app_config::AppConfig;
+use app_cryptography::{hash::Hasher, jwt::JwtKeySet};
 use app_log::init_tracing;
+use app_mailer::mail_sender;
 use app_redis::Redis;
 use app_state::AppState;
 use dotenv::dotenv;
@@ -39,13 +43,40 @@ pub async fn mcp_service() {
         Ok(redis_pool) => redis_pool,
         Err(err) => panic!("Cannot connect Redis\n{}", err),
     };
+    // Access token keys
+    let jwt_keys = match JwtKeySet::from_config(&config) {
+        Ok(keys) => Arc::new(keys),
+        Err(err) => panic!("Cannot load JWT keys\n{}", err),
+    };
+    // Password hashing and policy
+    let hasher = match Hasher::from_config(&config.password) {
+        Ok(hasher) => Arc::new(hasher),
+        Err(err) => panic!("Cannot create password hasher\n{}", err),
+    };
+    let password_policy = match PasswordPolicy::from_config(&config.password) {
+        Ok(policy) => Arc::new(policy),
+        Err(err) => panic!("Cannot load breached password list\n{}", err),
+    };
+    // Outgoing mail
+    let mailer = match mail_sender(&config, pg.clone()) {
+        Ok(mailer) => mailer,
+        Err(err) => panic!("Cannot create mail sender\n{}", err.message),
+    };
+    // Security audit log
+    let audit = AuditLog::spawn(pg.clone(), &config.audit);
     // Generating AppState
     let app_state = Arc::new(AppState {
         config: config.clone(),
         pg,
         redis,
-        agent_runner: None,
+        jwt_keys,
+        mailer,
+        hasher,
+        password_policy,
+        audit,
+        agents: None,
         agent_session: None,
+        agent_approvals: None,
     });
     // Loading Routes
     let mcp_config = StreamableHttpServerConfig {
@@ -1,7 +1,10 @@
 use crate::handlers::McpHandler;
+use app_auth::mcp_oauth::protected_resource_metadata;
+use app_dto::auth::oauth2::ProtectedResourceMetadata;
+use app_error::AppError;
 use app_middleware::mcp_auth_middleware;
 use app_state::AppState;
-use axum::{Router, middleware, routing::get};
+use axum::{Json, Router, extract::State, http::StatusCode, middleware, routing::get};
 use rmcp::transport::streamable_http_server::{
     StreamableHttpServerConfig, StreamableHttpService, session::SessionManager,
 };
@@ -25,8 +28,22 @@ where
         mcp_config,
     );
 
+    // Looked up by OAuth clients after a `401`, both at the root and at the path of the
+    // resource (RFC 9728 §3.1)
+    let well_known = Router::new()
+        .route(
+            "/.well-known/oauth-protected-resource",
+            get(get_protected_resource),
+        )
+        .route(
+            "/.well-known/oauth-protected-resource/v1/mcp",
+            get(get_protected_resource),
+        )
+        .with_state(state.clone());
+
     Router::new()
         .route("/", get(index))
+        .merge(well_known)
         .nest(
             "/v1",
             axum::Router::new().nest_service("/mcp", service).layer(
@@ -39,3 +56,13 @@ where
 async fn index() -> String {
     "[ok]".to_owned()
 }
+
+// #[get("/.well-known/oauth-protected-resource")]
+async fn get_protected_resource(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ProtectedResourceMetadata>, AppError> {
+    if !state.config.mcp_oauth.enabled {
+        return Err(AppError::new("Not found!", StatusCode::NOT_FOUND, 3));
+    }
+    Ok(Json(protected_resource_metadata(&state.config)))
+}
@@ -0,0 +1,71 @@
+// Session helpers shared by the pages.
+// Pages sign in with cookie mode: the tokens are HttpOnly cookies and the readable `csrf_token`
+// cookie is echoed in `X-CSRF-Token`. Tokens stored by older pages (`jwtToken` and
+// `refreshToken` in localStorage) are still sent as a bearer token until they are cleared.
+function saveTokens(accessToken, refreshToken) {
+  localStorage.setItem('jwtToken', accessToken);
+  localStorage.setItem('refreshToken', refreshToken);
+}
+
+function clearTokens() {
+  localStorage.removeItem('jwtToken');
+  localStorage.removeItem('refreshToken');
+}
+
+function getCookie(name) {
+  const prefix = name + '=';
+  const cookie = document.cookie.split('; ').find(c => c.startsWith(prefix));
+  return cookie ? decodeURIComponent(cookie.substring(prefix.length)) : null;
+}
+
+// Whether the browser holds a session of either mode
+function hasSession() {
+  return localStorage.getItem('jwtToken') !== null || getCookie('csrf_token') !== null;
+}
+
+// Rotates the session using `POST /auth/refresh`; returns false when the session is gone
+async function refreshTokens() {
+  const refreshToken = localStorage.getItem('refreshToken');
+  const csrfToken = getCookie('csrf_token');
+  if (!refreshToken && !csrfToken) {
+    return false;
+  }
+  const headers = { 'Content-Type': 'application/json' };
+  if (!refreshToken) {
+    // Cookie mode: the refresh token cookie is sent by the browser
+    headers['X-CSRF-Token'] = csrfToken;
+  }
+  const response = await fetch('/auth/refresh', {
+    method: 'POST',
+    headers,
+    body: JSON.stringify(refreshToken ? { refresh_token: refreshToken } : {})
+  });
+  if (!response.ok) {
+    clearTokens();
+    return false;
+  }
+  const data = await response.json();
+  if (data.access_token && data.refresh_token) {
+    saveTokens(data.access_token, data.refresh_token);
+  }
+  return true;
+}
+
+// `fetch` with the session attached; retries once after refreshing an expired token
+async function authFetch(url, options = {}) {
+  const send = () => {
+    const headers = { ...(options.headers || {}) };
+    const accessToken = localStorage.getItem('jwtToken');
+    if (accessToken) {
+      headers['Authorization'] = `Bearer ${accessToken}`;
+    } else {
+      headers['X-CSRF-Token'] = getCookie('csrf_token') || '';
+    }
+    return fetch(url, { ...options, headers });
+  };
+  let response = await send();
+  if (response.status === 401 && await refreshTokens()) {
+    response = await send();
+  }
+  return response;
+}
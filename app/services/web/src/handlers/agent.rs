@@ -1,20 +1,42 @@
 use adk_core::Content;
-use adk_rust::session::{CreateRequest, GetRequest};
-use app_agent::runner::stream_response_parser;
+use adk_rust::{
+    runner::Runner,
+    session::{CreateRequest, GetRequest, SessionService},
+};
+use app_adk_utils::session::{
+    approval::ApprovalGate,
+    postgres::{PendingApproval, PgSessionService, SessionSummary, TranscriptEvent},
+};
+use app_agent::runner::{AgentFrame, spawn_run, stream_response_parser};
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::session::ClientInfo;
 use app_error::AppError;
-use app_middleware::get_email;
+use app_middleware::AuthUser;
 use app_state::AppState;
 use askama::Template;
-use axum::response::Html;
-use axum::{
-    extract::{Json, State},
-    http::HeaderMap,
+use axum::extract::{ConnectInfo, Json, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{
+    Html,
+    sse::{Event as SseEvent, KeepAlive, Sse},
 };
+use futures::{Stream, stream};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::*;
 use uuid::Uuid;
 
+/// Seconds between SSE comments that keep idle proxies from closing the stream
+const HEARTBEAT_SECONDS: u64 = 15;
+/// Frames waiting for a slow client before the run waits too
+pub(crate) const STREAM_BUFFER: usize = 32;
+
 #[derive(Template)]
 #[template(path = "agent.html")]
 struct AgentPage;
@@ -27,6 +49,7 @@ pub async fn get_agent() -> Result<Html<String>, AppError> {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatPostInput {
     pub session_id: Option<String>,
+    pub agent: Option<String>, // Name of a configured agent; the default one without
     pub content: String,
 }
 
@@ -36,76 +59,111 @@ pub struct ChatPostOutput {
     pub content: String,
 }
 
-pub async fn post_agent(
-    headers: HeaderMap,
-    State(state): State<Arc<AppState>>,
-    Json(args): Json<ChatPostInput>,
-) -> Result<Json<ChatPostOutput>, AppError> {
-    let config = state.config.clone();
-    let user_id = match get_email(&headers) {
-        None => {
-            return Err(AppError::internal("Cannot identify user"));
-        }
-        Some(u) => u,
-    };
-    let agent_runner = match &state.agent_runner {
-        None => {
-            return Err(AppError::internal("Cannot find agent runner"));
-        }
-        Some(runner) => runner.clone(),
-    };
-    let agent_session = match &state.agent_session {
-        None => {
-            return Err(AppError::internal("Cannot find agent session"));
-        }
-        Some(session) => session.clone(),
+#[derive(Debug, Serialize)]
+pub struct AgentOutput {
+    pub name: String,
+    pub description: String,
+    pub default: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentSessionsQuery {
+    pub page: Option<i64>,     // Default 1
+    pub per_page: Option<i64>, // Default 20, at most 100
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentSessionsPageOutput {
+    pub items: Vec<SessionSummary>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentSessionOutput {
+    pub session_id: String,
+    pub events: Vec<TranscriptEvent>,
+    pub pending_approvals: Vec<PendingApproval>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PatchAgentSessionInput {
+    pub title: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentApprovalInput {
+    pub approved: bool,
+}
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+const MAX_TITLE_CHARS: usize = 200;
+
+/// Runner of the agent called `name` (the default agent without a name), or an error when
+/// the service was started without agents
+pub(crate) fn agent_runner(state: &AppState, name: Option<&str>) -> Result<Arc<Runner>, AppError> {
+    let agents = match &state.agents {
+        None => return Err(AppError::internal("Cannot find agent runner")),
+        Some(agents) => agents,
     };
-    let new_session_id = Uuid::new_v4().to_string();
-    let agent_current_session = match &args.session_id {
-        // Make a new session
-        None => match agent_session
-            .create(CreateRequest {
-                app_name: config.agent_app_name.clone(),
-                user_id: user_id.clone(),
-                session_id: Some(new_session_id.clone()),
-                state: HashMap::new(),
-            })
-            .await
-        {
-            Err(e) => {
-                return Err(AppError::internal(&format!("{}", &e)));
-            }
-            Ok(_) => new_session_id,
-        },
-        // Find Exising Session
-        Some(session_id) => match agent_session
+    match agents.get(name) {
+        None => Err(AppError::new("Agent not found!", StatusCode::NOT_FOUND, 3)),
+        Some(entry) => Ok(entry.runner.clone()),
+    }
+}
+
+/// Id of the agent session to continue; a new session is made when none is given or the
+/// given one does not exist
+pub(crate) async fn agent_session_id(
+    state: &AppState,
+    user_id: &str,
+    session_id: &Option<String>,
+) -> Result<String, AppError> {
+    let config = state.config.clone();
+    let agent_session = agent_sessions(state)?;
+    // Find Exising Session
+    if let Some(session_id) = session_id {
+        let found = agent_session
             .get(GetRequest {
                 app_name: config.agent_app_name.clone(),
-                user_id: user_id.clone(),
+                user_id: user_id.to_owned(),
                 session_id: session_id.clone(),
                 num_recent_events: None,
                 after: None,
             })
-            .await
-        {
-            Ok(_) => session_id.clone(),
-            // Make a new Session if session not found
-            Err(_) => match agent_session
-                .create(CreateRequest {
-                    app_name: config.agent_app_name.clone(),
-                    user_id: user_id.clone(),
-                    session_id: Some(new_session_id.clone()),
-                    state: HashMap::new(),
-                })
-                .await
-            {
-                Err(e) => {
-                    return Err(AppError::internal(&format!("{}", &e)));
-                }
-                Ok(_) => session_id.clone(),
-            },
-        },
-    };
+            .await;
+        if found.is_ok() {
+            return Ok(session_id.clone());
+        }
+    }
+    // Make a new session
+    let new_session_id = Uuid::new_v4().to_string();
+    match agent_session
+        .create(CreateRequest {
+            app_name: config.agent_app_name.clone(),
+            user_id: user_id.to_owned(),
+            session_id: Some(new_session_id.clone()),
+            state: HashMap::new(),
+        })
+        .await
+    {
+        Err(e) => Err(AppError::internal(format!("{}", &e))),
+        Ok(_) => Ok(new_session_id),
+    }
+}
+
+// #[post("/auth/agent")]
+pub async fn post_agent(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<ChatPostInput>,
+) -> Result<Json<ChatPostOutput>, AppError> {
+    // Agent sessions are keyed by email
+    let user_id = caller.email;
+    let agent_runner = agent_runner(&state, args.agent.as_deref())?;
+    let agent_current_session = agent_session_id(&state, &user_id, &args.session_id).await?;
     // Making Agent Runner
     let user_input = Content::new("user").with_text(args.content);
     let mut stream = match agent_runner
@@ -123,3 +181,264 @@ pub async fn post_agent(
         content,
     }))
 }
+
+// #[get("/auth/agents")]
+pub async fn get_agents(State(state): State<Arc<AppState>>) -> Json<Vec<AgentOutput>> {
+    let agents = match &state.agents {
+        None => return Json(vec![]),
+        Some(agents) => agents,
+    };
+    Json(
+        agents
+            .entries()
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| AgentOutput {
+                name: entry.name.clone(),
+                description: entry.description.clone(),
+                default: i == 0,
+            })
+            .collect(),
+    )
+}
+
+/// Session store of the agent, or an error when the service was started without one
+fn agent_sessions(state: &AppState) -> Result<Arc<PgSessionService>, AppError> {
+    match &state.agent_session {
+        None => Err(AppError::internal("Cannot find agent session")),
+        Some(session) => Ok(session.clone()),
+    }
+}
+
+fn session_not_found() -> AppError {
+    AppError::new("Session not found!", StatusCode::NOT_FOUND, 3)
+}
+
+// Conversations are looked up by the caller's email, so nobody reaches another user's
+// sessions; a session of someone else is answered like a missing one
+
+// #[get("/auth/agent/sessions")]
+pub async fn get_agent_sessions(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AgentSessionsQuery>,
+) -> Result<Json<AgentSessionsPageOutput>, AppError> {
+    let sessions = agent_sessions(&state)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let (items, total) = sessions
+        .list_summaries(
+            &state.config.agent_app_name,
+            &caller.email,
+            per_page,
+            (page - 1).saturating_mul(per_page),
+        )
+        .await?;
+    Ok(Json(AgentSessionsPageOutput {
+        items,
+        page,
+        per_page,
+        total,
+    }))
+}
+
+// #[get("/auth/agent/sessions/{id}")]
+pub async fn get_agent_session(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<AgentSessionOutput>, AppError> {
+    let sessions = agent_sessions(&state)?;
+    let approvals = agent_approvals(&state)?;
+    let app_name = &state.config.agent_app_name;
+    let events = match sessions.transcript(app_name, &caller.email, &id).await? {
+        None => return Err(session_not_found()),
+        Some(events) => events,
+    };
+    let pending_approvals = sessions
+        .pending_approvals(app_name, &caller.email, &id, &approvals)
+        .await?
+        .unwrap_or_default();
+    Ok(Json(AgentSessionOutput {
+        session_id: id,
+        events,
+        pending_approvals,
+    }))
+}
+
+// #[patch("/auth/agent/sessions/{id}")]
+pub async fn patch_agent_session(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(args): Json<PatchAgentSessionInput>,
+) -> Result<StatusCode, AppError> {
+    let sessions = agent_sessions(&state)?;
+    let title = args.title.trim();
+    if title.is_empty() || title.chars().count() > MAX_TITLE_CHARS {
+        return Err(AppError::new(
+            format!("Title must have 1 to {} characters!", MAX_TITLE_CHARS),
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    if !sessions
+        .rename(&state.config.agent_app_name, &caller.email, &id, title)
+        .await?
+    {
+        return Err(session_not_found());
+    }
+    Ok(StatusCode::OK)
+}
+
+// #[delete("/auth/agent/sessions/{id}")]
+pub async fn delete_agent_session(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let sessions = agent_sessions(&state)?;
+    if !sessions
+        .delete_session(&state.config.agent_app_name, &caller.email, &id)
+        .await?
+    {
+        return Err(session_not_found());
+    }
+    info!("Agent session {} of {} deleted", &id, &caller.email);
+    Ok(StatusCode::OK)
+}
+
+fn agent_approvals(state: &AppState) -> Result<Arc<ApprovalGate>, AppError> {
+    match &state.agent_approvals {
+        None => Err(AppError::internal("Cannot find agent approvals")),
+        Some(approvals) => Ok(approvals.clone()),
+    }
+}
+
+/// Approves or rejects a tool call of the caller's session that waits in a running turn,
+/// which then goes on
+pub(crate) async fn decide_approval(
+    state: &AppState,
+    caller: &AuthUser,
+    client: &ClientInfo,
+    session_id: &str,
+    call_id: &str,
+    approved: bool,
+) -> Result<(), AppError> {
+    let sessions = agent_sessions(state)?;
+    let approvals = agent_approvals(state)?;
+    let pending = match sessions
+        .pending_approvals(
+            &state.config.agent_app_name,
+            &caller.email,
+            session_id,
+            &approvals,
+        )
+        .await?
+    {
+        None => return Err(session_not_found()),
+        Some(pending) => pending,
+    };
+    let call = match pending.into_iter().find(|p| p.call_id == call_id) {
+        None => {
+            return Err(AppError::new(
+                "Approval not found!",
+                StatusCode::NOT_FOUND,
+                3,
+            ));
+        }
+        Some(call) => call,
+    };
+    // The turn may have stopped waiting since the call was listed
+    if !approvals.decide(&caller.email, session_id, call_id, approved) {
+        return Err(AppError::new(
+            "The turn waiting for this approval is no longer running!",
+            StatusCode::CONFLICT,
+            7,
+        ));
+    }
+    state.audit.record(
+        AuditEntry::new(action::AGENT_TOOL_APPROVAL, Outcome::Success)
+            .actor(&caller.email)
+            .actor_id(&caller.id.to_string())
+            .target(&call.name)
+            .client(&client.ip, &client.user_agent)
+            .detail("session_id", session_id)
+            .detail("call_id", call_id)
+            .detail("approved", approved),
+    );
+    info!(
+        "Tool call {} ({}) of {} {}",
+        call_id,
+        &call.name,
+        &caller.email,
+        if approved { "approved" } else { "rejected" }
+    );
+    Ok(())
+}
+
+// #[post("/auth/agent/sessions/{id}/approvals/{call_id}")]
+pub async fn post_agent_approval(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path((id, call_id)): Path<(String, String)>,
+    Json(args): Json<AgentApprovalInput>,
+) -> Result<StatusCode, AppError> {
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    decide_approval(&state, &caller, &client, &id, &call_id, args.approved).await?;
+    Ok(StatusCode::OK)
+}
+
+fn sse_event(frame: &AgentFrame) -> SseEvent {
+    SseEvent::default()
+        .event(frame.kind())
+        .json_data(frame)
+        .unwrap_or_else(|_| SseEvent::default().event("error").data("{}"))
+}
+
+/// Same as `post_agent`, but answers with Server-Sent Events as the run goes: `text`,
+/// `tool_call`, `approval_required`, `tool_result`, then `final` or `error`. The run is
+/// cancelled when the client goes away.
+// #[post("/auth/agent/stream")]
+pub async fn post_agent_stream(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<ChatPostInput>,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, AppError> {
+    let user_id = caller.email;
+    let agent_runner = agent_runner(&state, args.agent.as_deref())?;
+    let session_id = agent_session_id(&state, &user_id, &args.session_id).await?;
+    let user_input = Content::new("user").with_text(args.content);
+    let stream = match agent_runner
+        .run(user_id.clone(), session_id.clone(), user_input)
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => return Err(AppError::internal(format!("{}", &e))),
+    };
+
+    let (tx, rx) = mpsc::channel::<AgentFrame>(STREAM_BUFFER);
+    // The run ends when the client goes away and the receiver below is dropped
+    spawn_run(
+        stream,
+        session_id,
+        tx,
+        |frame| frame,
+        CancellationToken::new(),
+    );
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        let frame = rx.recv().await?;
+        Some((Ok(sse_event(&frame)), rx))
+    });
+    Ok(Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(HEARTBEAT_SECONDS))
+            .text("heartbeat"),
+    ))
+}
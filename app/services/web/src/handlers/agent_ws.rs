@@ -0,0 +1,424 @@
+//! `GET /auth/agent/ws`: one WebSocket carrying the turns of several agent sessions.
+//!
+//! Every message is a JSON text frame with a `type`.
+//!
+//! Client to server:
+//! - `{"type": "message", "session_id": null, "request_id": "r1", "agent": null, "content": "..."}`
+//!   starts a turn; without `session_id` (or with an unknown one) a new session is made, and
+//!   without `agent` the default agent answers
+//! - `{"type": "cancel", "session_id": "..."}` stops the running turn of a session
+//! - `{"type": "approval", "session_id": "...", "call_id": "...", "approved": true}` answers a
+//!   tool-approval prompt like `POST /auth/agent/sessions/{id}/approvals/{call_id}`
+//! - `{"type": "ping"}`
+//!
+//! Server to client:
+//! - `{"type": "started", "session_id": "...", "request_id": "r1"}`
+//! - `{"type": "frame", "session_id": "...", "frame": {...}}` with the frames of
+//!   `POST /auth/agent/stream` (`text`, `tool_call`, `tool_result`, `final`, `error`)
+//! - `{"type": "approval_required", "session_id": "...", "call_id": "...", "name": "...",
+//!   "args": {...}}` when a tool call waits for the user; the turn goes on once it is answered
+//! - `{"type": "cancelled", "session_id": "..."}`
+//! - `{"type": "pong"}`
+//! - `{"type": "error", "session_id": null, "request_id": null, "message": "..."}`
+//!
+//! A session runs one turn at a time; turns of different sessions run side by side. Closing
+//! the socket cancels every running turn.
+//!
+//! The login session is checked again on every client message and every
+//! `SESSION_CHECK_SECONDS`; after logout, revocation or expiry of the access token the server
+//! sends an `error` and closes the socket, and the client reconnects with a fresh token.
+
+use crate::handlers::agent::{STREAM_BUFFER, agent_runner, agent_session_id, decide_approval};
+use adk_core::Content;
+use app_agent::runner::{AgentFrame, spawn_run};
+use app_auth::session::{ClientInfo, session_exists, touch_session};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_state::AppState;
+use axum::{
+    extract::{
+        ConnectInfo, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, HOST, ORIGIN},
+    },
+    response::Response,
+};
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::*;
+
+/// Largest message accepted from the client
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+/// How often an idle socket checks that its login session is still alive
+const SESSION_CHECK_SECONDS: u64 = 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsTurnRequest {
+    pub session_id: Option<String>,
+    pub request_id: Option<String>, // Echoed by `started` and `error`
+    #[serde(default)]
+    pub agent: Option<String>, // Name of a configured agent; the default one without
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsClientMessage {
+    Message(WsTurnRequest),
+    Cancel {
+        session_id: String,
+    },
+    Approval {
+        session_id: String,
+        call_id: String,
+        approved: bool,
+    },
+    Ping,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WsServerMessage {
+    Started {
+        session_id: String,
+        request_id: Option<String>,
+    },
+    Frame {
+        session_id: String,
+        frame: AgentFrame,
+    },
+    ApprovalRequired {
+        session_id: String,
+        call_id: String,
+        name: String,
+        args: Value,
+    },
+    Cancelled {
+        session_id: String,
+    },
+    Pong,
+    Error {
+        session_id: Option<String>,
+        request_id: Option<String>,
+        message: String,
+    },
+}
+
+impl WsServerMessage {
+    fn error(session_id: Option<String>, request_id: Option<String>, message: &str) -> Self {
+        WsServerMessage::Error {
+            session_id,
+            request_id,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Browsers attach cookies to WebSocket handshakes from any site and CSRF headers cannot be
+/// sent, so a handshake naming another origin is refused. Browsers cannot set `Authorization`
+/// on a handshake either, so only such clients may leave the origin out; a handshake
+/// authenticated by cookie must name its origin.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let origin = match headers.get(ORIGIN).and_then(|v| v.to_str().ok()) {
+        None => return headers.contains_key(AUTHORIZATION),
+        Some(origin) => origin,
+    };
+    let host = headers.get(HOST).and_then(|v| v.to_str().ok());
+    match (Url::parse(origin), host) {
+        (Ok(origin), Some(host)) => {
+            let origin_host = match origin.port() {
+                None => origin.host_str().unwrap_or_default().to_owned(),
+                Some(port) => format!("{}:{}", origin.host_str().unwrap_or_default(), port),
+            };
+            origin_host.eq_ignore_ascii_case(host)
+        }
+        _ => false,
+    }
+}
+
+// #[get("/auth/agent/ws")]
+pub async fn get_agent_ws(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    if !same_origin(&headers) {
+        warn!("Cross-origin agent socket refused for {}", &caller.email);
+        return Err(AppError::new("Forbidden", StatusCode::FORBIDDEN, 4));
+    }
+    // Fail before upgrading when the service has no agent
+    agent_runner(&state, None)?;
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    Ok(ws
+        .max_message_size(MAX_MESSAGE_BYTES)
+        .on_upgrade(move |socket| agent_socket(state, caller, client, socket)))
+}
+
+/// A turn running on the socket
+struct Turn {
+    cancel: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+/// Whether the credential of the socket still holds, like `web_auth_middleware` checks it for
+/// every request. Client messages restart the idle window of a sliding session, timer checks
+/// do not. API keys are not bound to a login session and only expire.
+async fn caller_alive(state: &AppState, caller: &AuthUser, touch: bool) -> Result<bool, AppError> {
+    if Utc::now().timestamp() >= caller.expires_at {
+        return Ok(false);
+    }
+    match (caller.session, touch) {
+        (0, _) => Ok(true),
+        (session, true) => touch_session(&state.config, &state.redis, &caller.email, session).await,
+        (session, false) => session_exists(&state.redis, &caller.email, session).await,
+    }
+}
+
+async fn agent_socket(
+    state: Arc<AppState>,
+    caller: AuthUser,
+    client: ClientInfo,
+    socket: WebSocket,
+) {
+    let (mut sink, mut source) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<WsServerMessage>(STREAM_BUFFER);
+    // All turns write through one task, so frames of different sessions never interleave
+    // inside a message
+    let mut writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            let text = match serde_json::to_string(&message) {
+                Err(e) => {
+                    error!("Cannot encode agent socket message: {}", e);
+                    continue;
+                }
+                Ok(text) => text,
+            };
+            if sink.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+        }
+        let _ = sink.close().await;
+    });
+    info!("Agent socket opened for {}", &caller.email);
+
+    let mut turns: HashMap<String, Turn> = HashMap::new();
+    let mut check = tokio::time::interval(Duration::from_secs(SESSION_CHECK_SECONDS));
+    loop {
+        let message = tokio::select! {
+            message = source.next() => match message {
+                Some(Ok(message)) => Some(message),
+                _ => break,
+            },
+            _ = check.tick() => None,
+        };
+        match caller_alive(&state, &caller, message.is_some()).await {
+            Ok(true) => (),
+            Ok(false) => {
+                info!(
+                    "Login session of {} ended; agent socket closed",
+                    &caller.email
+                );
+                let _ = tx
+                    .send(WsServerMessage::error(None, None, "Session expired"))
+                    .await;
+                break;
+            }
+            Err(e) => {
+                error!(
+                    "Cannot check the session of {}: {}",
+                    &caller.email, &e.message
+                );
+                break;
+            }
+        }
+        turns.retain(|_, turn| !turn.handle.is_finished());
+        let Some(message) = message else {
+            continue;
+        };
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue, // Pings are answered by axum
+        };
+        let reply = match serde_json::from_str::<WsClientMessage>(&text) {
+            Err(e) => Some(WsServerMessage::error(
+                None,
+                None,
+                &format!("Invalid message: {}", e),
+            )),
+            Ok(WsClientMessage::Ping) => Some(WsServerMessage::Pong),
+            Ok(WsClientMessage::Message(request)) => {
+                start_turn(&state, &caller, &tx, &mut turns, request)
+                    .await
+                    .err()
+            }
+            Ok(WsClientMessage::Cancel { session_id }) => match turns
+                .get(&session_id)
+                .filter(|turn| !turn.handle.is_finished())
+            {
+                Some(turn) => {
+                    turn.cancel.cancel();
+                    turns.remove(&session_id);
+                    Some(WsServerMessage::Cancelled { session_id })
+                }
+                None => Some(WsServerMessage::error(
+                    Some(session_id),
+                    None,
+                    "No turn is running in this session",
+                )),
+            },
+            Ok(WsClientMessage::Approval {
+                session_id,
+                call_id,
+                approved,
+            }) => decide_approval(&state, &caller, &client, &session_id, &call_id, approved)
+                .await
+                .err()
+                .map(|e| WsServerMessage::error(Some(session_id), None, &e.message)),
+        };
+        let Some(reply) = reply else {
+            continue;
+        };
+        if tx.send(reply).await.is_err() {
+            break;
+        }
+    }
+
+    for turn in turns.values() {
+        turn.cancel.cancel();
+    }
+    // Cancelled turns drop their senders, so the writer flushes the last messages and closes
+    drop(tx);
+    let _ = tokio::time::timeout(Duration::from_secs(1), &mut writer).await;
+    writer.abort();
+    info!("Agent socket closed for {}", &caller.email);
+}
+
+/// Starts a turn; the error message is sent back to the client
+async fn start_turn(
+    state: &AppState,
+    caller: &AuthUser,
+    tx: &mpsc::Sender<WsServerMessage>,
+    turns: &mut HashMap<String, Turn>,
+    request: WsTurnRequest,
+) -> Result<(), WsServerMessage> {
+    let WsTurnRequest {
+        session_id,
+        request_id,
+        agent,
+        content,
+    } = request;
+    let fail = |session_id: Option<String>, e: &AppError| {
+        WsServerMessage::error(session_id, request_id.clone(), &e.message)
+    };
+    // Agent sessions are keyed by email
+    let user_id = caller.email.clone();
+    let session_id = agent_session_id(state, &user_id, &session_id)
+        .await
+        .map_err(|e| fail(session_id.clone(), &e))?;
+    if turns
+        .get(&session_id)
+        .is_some_and(|turn| !turn.handle.is_finished())
+    {
+        return Err(WsServerMessage::error(
+            Some(session_id),
+            request_id.clone(),
+            "A turn is already running in this session",
+        ));
+    }
+    let runner =
+        agent_runner(state, agent.as_deref()).map_err(|e| fail(Some(session_id.clone()), &e))?;
+    let user_input = Content::new("user").with_text(content);
+    let stream = match runner.run(user_id, session_id.clone(), user_input).await {
+        Ok(s) => s,
+        Err(e) => {
+            let e = AppError::internal(format!("{}", &e));
+            return Err(fail(Some(session_id), &e));
+        }
+    };
+    let started = WsServerMessage::Started {
+        session_id: session_id.clone(),
+        request_id: request_id.clone(),
+    };
+    if tx.send(started).await.is_err() {
+        return Ok(());
+    }
+    let cancel = CancellationToken::new();
+    let frame_session = session_id.clone();
+    let handle = spawn_run(
+        stream,
+        session_id.clone(),
+        tx.clone(),
+        move |frame| match frame {
+            AgentFrame::ApprovalRequired {
+                session_id,
+                id,
+                name,
+                args,
+            } => WsServerMessage::ApprovalRequired {
+                session_id,
+                call_id: id,
+                name,
+                args,
+            },
+            frame => WsServerMessage::Frame {
+                session_id: frame_session.clone(),
+                frame,
+            },
+        },
+        cancel.clone(),
+    );
+    turns.insert(session_id, Turn { cancel, handle });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::HeaderValue;
+    // cargo test --package web check_agent_ws_protocol -- --nocapture
+    #[test]
+    fn check_agent_ws_protocol() {
+        let message: WsClientMessage = serde_json::from_str(
+            r#"{"type": "message", "session_id": null, "request_id": "r1", "content": "hi"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            message,
+            WsClientMessage::Message(WsTurnRequest { agent: None, .. })
+        ));
+        let message: WsClientMessage = serde_json::from_str(r#"{"type": "ping"}"#).unwrap();
+        assert!(matches!(message, WsClientMessage::Ping));
+
+        let frame = WsServerMessage::Frame {
+            session_id: "s".into(),
+            frame: AgentFrame::Text { text: "a".into() },
+        };
+        let json = serde_json::to_value(&frame).unwrap();
+        println!("{}", &json);
+        assert_eq!(json["type"], "frame");
+        assert_eq!(json["frame"]["type"], "text");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static("localhost:9000"));
+        // A cookie-authenticated handshake without an origin is refused
+        assert!(!same_origin(&headers));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("ApiKey ak_x_y"));
+        assert!(same_origin(&headers));
+        headers.insert(ORIGIN, HeaderValue::from_static("http://localhost:9000"));
+        assert!(same_origin(&headers));
+        headers.insert(ORIGIN, HeaderValue::from_static("https://evil.example"));
+        assert!(!same_origin(&headers));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::handlers::identity::current_user;
+use app_auth::api_key::{API_KEY_SOURCE, create_api_key};
+use app_dto::auth::api_key::{ApiKeyOutput, PatchApiKeyInput, PostApiKeyInput, PostApiKeyOutput};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_schema::auth::{api_keys::ApiKey, users::User};
+use app_state::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::*;
+use uuid::Uuid;
+
+const API_KEY_NOT_FOUND_MESSAGE: &str = "API key not found!";
+
+fn api_key_output(api_key: ApiKey) -> ApiKeyOutput {
+    ApiKeyOutput {
+        id: api_key.id.to_string(),
+        name: api_key.name,
+        prefix: api_key.prefix,
+        scopes: api_key.scopes,
+        expires_at: api_key.expires_at.map(|e| e.timestamp()),
+        last_used_at: api_key.last_used_at.map(|e| e.timestamp()),
+        created_at: api_key.created_at.timestamp(),
+    }
+}
+
+fn not_found() -> AppError {
+    AppError::new(API_KEY_NOT_FOUND_MESSAGE, StatusCode::NOT_FOUND, 3)
+}
+
+/// Keys are managed by signed-in users only, so a leaked key cannot mint new keys
+async fn key_owner(pg: &Pool<Postgres>, caller: &AuthUser) -> Result<User, AppError> {
+    if caller.source == API_KEY_SOURCE {
+        return Err(AppError::new(
+            "API keys cannot manage API keys!",
+            StatusCode::FORBIDDEN,
+            4,
+        ));
+    }
+    current_user(pg, caller).await
+}
+
+/// A key may only carry permissions its owner holds
+fn check_scopes(caller: &AuthUser, scopes: &[String]) -> Result<(), AppError> {
+    match scopes.iter().find(|s| !caller.permissions.contains(s)) {
+        None => Ok(()),
+        Some(scope) => Err(AppError::new(
+            format!("Scope {} is not granted to you!", scope),
+            StatusCode::FORBIDDEN,
+            4,
+        )),
+    }
+}
+
+fn expiry(expires_in_days: Option<i64>) -> Result<Option<DateTime<Utc>>, AppError> {
+    match expires_in_days {
+        None => Ok(None),
+        Some(days) if days > 0 => Ok(Some(Utc::now() + Duration::days(days))),
+        Some(_) => Err(AppError::new(
+            "expires_in_days must be positive!",
+            StatusCode::BAD_REQUEST,
+            6,
+        )),
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|_| not_found())
+}
+
+// #[get("/auth/api_keys")]
+pub async fn get_api_keys(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ApiKeyOutput>>, AppError> {
+    let pg = state.pg.clone();
+    let user = key_owner(&pg, &caller).await?;
+    let res = sqlx::query_as::<_, ApiKey>(&format!(
+        "{} WHERE user_id = $1 ORDER BY created_at DESC",
+        ApiKey::select_query()
+    ))
+    .bind(user.id)
+    .fetch_all(&pg)
+    .await?
+    .into_iter()
+    .map(api_key_output)
+    .collect();
+    Ok(Json(res))
+}
+
+// #[post("/auth/api_keys")]
+pub async fn post_api_key(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostApiKeyInput>,
+) -> Result<(StatusCode, Json<PostApiKeyOutput>), AppError> {
+    let pg = state.pg.clone();
+    let user = key_owner(&pg, &caller).await?;
+    check_scopes(&caller, &args.scopes)?;
+    let expires_at = expiry(args.expires_in_days)?;
+    let (key, api_key) =
+        create_api_key(&pg, &user.id, &args.name, &args.scopes, expires_at).await?;
+    info!("API key {} created for {}", &api_key.prefix, &user.email);
+    Ok((
+        StatusCode::CREATED,
+        Json(PostApiKeyOutput {
+            key,
+            api_key: api_key_output(api_key),
+        }),
+    ))
+}
+
+// #[get("/auth/api_keys/{id}")]
+pub async fn get_api_key_by_id(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKeyOutput>, AppError> {
+    let pg = state.pg.clone();
+    let user = key_owner(&pg, &caller).await?;
+    match sqlx::query_as::<_, ApiKey>(&format!(
+        "{} WHERE id = $1 AND user_id = $2",
+        ApiKey::select_query()
+    ))
+    .bind(parse_id(&id)?)
+    .bind(user.id)
+    .fetch_optional(&pg)
+    .await?
+    {
+        None => Err(not_found()),
+        Some(api_key) => Ok(Json(api_key_output(api_key))),
+    }
+}
+
+// #[patch("/auth/api_keys/{id}")]
+pub async fn patch_api_key(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(args): Json<PatchApiKeyInput>,
+) -> Result<Json<ApiKeyOutput>, AppError> {
+    let pg = state.pg.clone();
+    let user = key_owner(&pg, &caller).await?;
+    let scopes = match &args.scopes {
+        None => None,
+        Some(scopes) => {
+            check_scopes(&caller, scopes)?;
+            let mut scopes = scopes.clone();
+            scopes.sort();
+            scopes.dedup();
+            Some(scopes)
+        }
+    };
+    match sqlx::query_as::<_, ApiKey>(ApiKey::update_query())
+        .bind(parse_id(&id)?)
+        .bind(user.id)
+        .bind(&args.name)
+        .bind(&scopes)
+        .bind(expiry(args.expires_in_days)?)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => Err(not_found()),
+        Some(api_key) => Ok(Json(api_key_output(api_key))),
+    }
+}
+
+// #[delete("/auth/api_keys/{id}")]
+pub async fn delete_api_key(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let user = key_owner(&pg, &caller).await?;
+    let deleted = sqlx::query(ApiKey::delete_query())
+        .bind(parse_id(&id)?)
+        .bind(user.id)
+        .execute(&pg)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(not_found());
+    }
+    info!("API key {} of {} deleted", &id, &user.email);
+    Ok(StatusCode::OK)
+}
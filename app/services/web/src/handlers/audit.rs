@@ -0,0 +1,90 @@
+use app_dto::auth::audit::{AuditEventOutput, AuditPageOutput, AuditQuery};
+use app_error::AppError;
+use app_schema::audit::AuditEvent;
+use app_state::AppState;
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 500;
+
+fn timestamp(seconds: Option<i64>) -> Result<Option<DateTime<Utc>>, AppError> {
+    match seconds {
+        None => Ok(None),
+        Some(s) => match DateTime::from_timestamp(s, 0) {
+            None => Err(AppError::new(
+                "Invalid timestamp!",
+                StatusCode::BAD_REQUEST,
+                6,
+            )),
+            Some(t) => Ok(Some(t)),
+        },
+    }
+}
+
+// #[get("/auth/audit?actor=&action=&target=&outcome=&from=&to=&page=&per_page=")]
+pub async fn get_audit(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPageOutput>, AppError> {
+    let pg = state.pg.clone();
+    let from = timestamp(query.from)?;
+    let to = timestamp(query.to)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let total: i64 = sqlx::query_scalar(&format!(
+        "{} {}",
+        AuditEvent::count_query(),
+        AuditEvent::filter_query()
+    ))
+    .bind(&query.actor)
+    .bind(&query.action)
+    .bind(&query.target)
+    .bind(&query.outcome)
+    .bind(from)
+    .bind(to)
+    .fetch_one(&pg)
+    .await?;
+    let items = sqlx::query_as::<_, AuditEvent>(&format!(
+        "{} {} ORDER BY occurred_at DESC, id DESC LIMIT $7 OFFSET $8",
+        AuditEvent::select_query(),
+        AuditEvent::filter_query()
+    ))
+    .bind(&query.actor)
+    .bind(&query.action)
+    .bind(&query.target)
+    .bind(&query.outcome)
+    .bind(from)
+    .bind(to)
+    .bind(per_page)
+    .bind((page - 1).saturating_mul(per_page))
+    .fetch_all(&pg)
+    .await?
+    .into_iter()
+    .map(|e| AuditEventOutput {
+        id: e.id,
+        occurred_at: e.occurred_at.timestamp(),
+        actor: e.actor,
+        actor_id: e.actor_id.map(|id| id.to_string()),
+        action: e.action,
+        target: e.target,
+        ip: e.ip,
+        user_agent: e.user_agent,
+        outcome: e.outcome,
+        details: e.details,
+    })
+    .collect();
+    Ok(Json(AuditPageOutput {
+        items,
+        page,
+        per_page,
+        total,
+    }))
+}
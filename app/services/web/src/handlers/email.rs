@@ -0,0 +1,104 @@
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    email_verification::{request_verification, verify_email},
+    session::ClientInfo,
+};
+use app_dto::auth::email::{PostResendVerificationInput, VerifyEmailQuery};
+use app_error::AppError;
+use app_state::AppState;
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Html,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+
+#[derive(Template)]
+#[template(path = "verify_email.html")]
+struct VerifyEmailPage {
+    verified: bool,
+    message: String, // Why the token was refused; empty when none was given
+}
+
+// #[get("/verify_email?token=")]
+pub async fn get_verify_email(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VerifyEmailQuery>,
+) -> Result<Html<String>, AppError> {
+    let pg = state.pg.clone();
+    let token = match query.token {
+        None => {
+            let page = VerifyEmailPage {
+                verified: false,
+                message: String::new(),
+            };
+            return Ok(Html(page.render()?));
+        }
+        Some(token) => token,
+    };
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::EMAIL_VERIFY, outcome).client(&client.ip, &client.user_agent)
+    };
+    let page = match verify_email(&pg, &token).await {
+        Ok(user) => {
+            state.audit.record(
+                audit(Outcome::Success)
+                    .actor(&user.email)
+                    .actor_id(&user.id.to_string()),
+            );
+            VerifyEmailPage {
+                verified: true,
+                message: String::new(),
+            }
+        }
+        Err(err) => {
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message.clone()));
+            VerifyEmailPage {
+                verified: false,
+                message: err.message,
+            }
+        }
+    };
+    Ok(Html(page.render()?))
+}
+
+// #[post("/verify_email/resend")]
+pub async fn post_resend_verification(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostResendVerificationInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let config = state.config.clone();
+    let email = args.email.trim();
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::EMAIL_VERIFY_SEND, outcome)
+            .target(email)
+            .client(&client.ip, &client.user_agent)
+    };
+    // The answer must not reveal whether an unverified account exists
+    match request_verification(&pg, &redis, state.mailer.as_ref(), &config, email).await {
+        Ok(()) => state.audit.record(audit(Outcome::Success)),
+        Err(err) if err.status == StatusCode::TOO_MANY_REQUESTS => {
+            state.audit.record(audit(Outcome::Denied));
+            return Err(err);
+        }
+        Err(err) => {
+            error!("Cannot send verification mail: {}", err.message);
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message));
+        }
+    }
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,53 @@
+use app_auth::identity::{list_identities, unlink_identity};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_schema::auth::{identities::Identity, users::User};
+use app_state::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::*;
+
+/// Loads the `auth.users` row of the signed-in caller
+pub async fn current_user(pg: &Pool<Postgres>, caller: &AuthUser) -> Result<User, AppError> {
+    match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+        .bind(caller.id)
+        .fetch_optional(pg)
+        .await?
+    {
+        None => Err(AppError::new("User not found!", StatusCode::NOT_FOUND, 3)),
+        Some(user) => Ok(user),
+    }
+}
+
+// #[get("/auth/identities")]
+pub async fn get_identities(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Identity>>, AppError> {
+    let pg = state.pg.clone();
+    let user = current_user(&pg, &caller).await?;
+    Ok(Json(list_identities(&pg, &user.id).await?))
+}
+
+// #[delete("/auth/identities/{provider}")]
+pub async fn delete_identity(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let user = current_user(&pg, &caller).await?;
+    if !unlink_identity(&pg, &user, &provider).await? {
+        return Err(AppError::new(
+            "Identity not found!",
+            StatusCode::NOT_FOUND,
+            3,
+        ));
+    }
+    info!("{} identity unlinked from {}", &provider, &user.email);
+    Ok(StatusCode::OK)
+}
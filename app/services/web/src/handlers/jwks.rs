@@ -0,0 +1,10 @@
+use app_cryptography::jwt::JwkSet;
+use app_state::AppState;
+use axum::extract::{Json, State};
+use std::sync::Arc;
+
+// #[get("/.well-known/jwks.json")]
+/// Public keys that verify our access tokens, including retired keys during rotation
+pub async fn get_jwks(State(state): State<Arc<AppState>>) -> Json<JwkSet> {
+    Json(state.jwt_keys.jwks())
+}
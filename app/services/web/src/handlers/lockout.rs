@@ -0,0 +1,94 @@
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    session::ClientInfo,
+    throttle::{lockout_status, unlock_login},
+};
+use app_dto::auth::lockout::{LockoutOutput, LoginFailureOutput, LoginFailuresQuery};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_schema::auth::login_failures::LoginFailure;
+use app_state::AppState;
+use axum::{
+    extract::{ConnectInfo, Json, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+
+const DEFAULT_FAILURES_LIMIT: i64 = 100;
+const MAX_FAILURES_LIMIT: i64 = 1000;
+
+// #[get("/auth/lockouts/{email}")]
+pub async fn get_lockout(
+    State(state): State<Arc<AppState>>,
+    Path(email): Path<String>,
+) -> Result<Json<LockoutOutput>, AppError> {
+    let redis = state.redis.clone();
+    let status = lockout_status(&redis, &email).await?;
+    Ok(Json(LockoutOutput {
+        email,
+        failures: status.failures,
+        locked_for: status.locked_for,
+        wait_for: status.wait_for,
+    }))
+}
+
+// #[delete("/auth/lockouts/{email}")]
+pub async fn delete_lockout(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let redis = state.redis.clone();
+    if !unlock_login(&redis, &email).await? {
+        return Err(AppError::new(
+            "Account is not locked!",
+            StatusCode::NOT_FOUND,
+            3,
+        ));
+    }
+    info!("Login of {} unlocked by {}", &email, &caller.email);
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    state.audit.record(
+        AuditEntry::new(action::LOGIN_UNLOCK, Outcome::Success)
+            .actor(&caller.email)
+            .actor_id(&caller.id.to_string())
+            .target(&email)
+            .client(&client.ip, &client.user_agent),
+    );
+    Ok(StatusCode::OK)
+}
+
+// #[get("/auth/login_failures?email=&ip=&limit=")]
+pub async fn get_login_failures(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<LoginFailuresQuery>,
+) -> Result<Json<Vec<LoginFailureOutput>>, AppError> {
+    let pg = state.pg.clone();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_FAILURES_LIMIT)
+        .clamp(1, MAX_FAILURES_LIMIT);
+    let res = sqlx::query_as::<_, LoginFailure>(&format!(
+        "{} WHERE ($1::TEXT IS NULL OR email = $1) AND ($2::TEXT IS NULL OR ip = $2) ORDER BY created_at DESC LIMIT $3",
+        LoginFailure::select_query()
+    ))
+    .bind(&query.email)
+    .bind(&query.ip)
+    .bind(limit)
+    .fetch_all(&pg)
+    .await?
+    .into_iter()
+    .map(|f| LoginFailureOutput {
+        email: f.email,
+        user_id: f.user_id.map(|id| id.to_string()),
+        ip: f.ip,
+        user_agent: f.user_agent,
+        reason: f.reason,
+        created_at: f.created_at.timestamp(),
+    })
+    .collect();
+    Ok(Json(res))
+}
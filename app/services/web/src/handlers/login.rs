@@ -1,134 +1,294 @@
-use app_cryptography::{
-    hash::verify,
-    jwt::{Algorithm, Claims, RedisInfo, generate_token},
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    cookie::{clear_session_cookies, cookie_refresh_token, session_cookies},
+    email_verification::check_verified,
+    mfa::{
+        MFA_CHALLENGE_TTL_SECONDS, MfaChallenge, create_challenge, mfa_enabled, redeem_challenge,
+    },
+    rbac::load_access,
+    session::{ClientInfo, SessionUser, create_session, rotate_session, session_key},
+    throttle::{check_password, record_login_success},
+};
+use app_dto::auth::login::{
+    PostCookieSessionOutput, PostLoginInput, PostLoginMfaInput, PostLoginMfaOutput,
+    PostLoginOutput, PostLoginResult, PostRefreshInput,
 };
-use app_dto::auth::login::{PostLoginInput, PostLoginOutput};
 use app_error::AppError;
-use app_middleware::{get_email, get_session};
+use app_middleware::AuthUser;
 use app_redis::Redis;
 use app_schema::auth::users::User;
 use app_state::AppState;
 use axum::{
-    extract::{Json, State},
+    extract::{ConnectInfo, Json, State},
     http::{HeaderMap, StatusCode},
 };
-use chrono::{Duration, Utc};
-use rand::Rng;
-use std::sync::Arc;
-use tracing::error;
+use std::{net::SocketAddr, sync::Arc};
 
 const AUTH_FAILD_MESSAGE: &'static str = "Provided information is wrong!";
 
 use askama::Template;
 use axum::response::Html;
 
+struct LoginProvider {
+    name: String,
+    title: String,
+}
+
 #[derive(Template)]
 #[template(path = "login.html")]
-struct LoginPage;
+struct LoginPage {
+    providers: Vec<LoginProvider>,
+}
 
-pub async fn get_login() -> Result<Html<String>, AppError> {
-    let page = LoginPage;
+pub async fn get_login(State(state): State<Arc<AppState>>) -> Result<Html<String>, AppError> {
+    let providers = state
+        .config
+        .oauth_providers
+        .iter()
+        .map(|p| LoginProvider {
+            name: p.name.clone(),
+            title: p.title.clone().unwrap_or_else(|| p.name.clone()),
+        })
+        .collect();
+    let page = LoginPage { providers };
     Ok(Html(page.render()?))
 }
 
+/// Opens a session for a user who passed every authentication step
+async fn login_user(
+    state: &AppState,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    user_info: &User,
+    browser: Option<String>,
+) -> Result<PostLoginOutput, AppError> {
+    let id = format!("{:?}", &user_info.id);
+    let access = load_access(&state.pg, &id).await?;
+    create_session(
+        &state.config,
+        &state.redis,
+        &state.jwt_keys,
+        SessionUser {
+            id,
+            name: user_info.name.clone(),
+            email: user_info.email.clone(),
+            source: "local".to_string(),
+            access,
+        },
+        ClientInfo::new(&state.config, headers, peer, browser),
+    )
+    .await
+}
+
+/// Hands a new token pair to the client: as cookies in cookie mode, in the body otherwise
+fn deliver_tokens(
+    state: &AppState,
+    tokens: PostLoginOutput,
+    cookie: bool,
+) -> Result<(HeaderMap, Json<PostLoginResult>), AppError> {
+    if !cookie {
+        return Ok((HeaderMap::new(), Json(PostLoginResult::Tokens(tokens))));
+    }
+    let (cookies, csrf_token) = session_cookies(&state.config, &state.jwt_keys, &tokens)?;
+    Ok((
+        cookies,
+        Json(PostLoginResult::Cookie(PostCookieSessionOutput {
+            csrf_token,
+        })),
+    ))
+}
+
 pub async fn post_login(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
     Json(args): Json<PostLoginInput>,
-) -> Result<Json<PostLoginOutput>, AppError> {
+) -> Result<(HeaderMap, Json<PostLoginResult>), AppError> {
     // Init configs
-    let config = state.config.clone();
     let pg = state.pg.clone();
     let redis = state.redis.clone();
-    let jwt_access_key = config.jwt_access_key.clone();
-    let jwt_access_session_min = config.jwt_access_session_minutes;
-    let jwt_refresh_key = config.jwt_refresh_key.clone();
-    let jwt_refresh_session_day = config.jwt_refresh_session_days;
-    // Search for user
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    // Check the password under the brute-force throttle
+    let user_info = match check_password(
+        &redis,
+        &pg,
+        &state.config.login_throttle,
+        &state.hasher,
+        &args.email,
+        &args.password,
+        &client,
+    )
+    .await
+    {
+        Ok(user) => match check_verified(&state.config, &user) {
+            Ok(()) => user,
+            Err(err) => {
+                state.audit.record(
+                    AuditEntry::new(action::LOGIN, Outcome::Denied)
+                        .actor(&user.email)
+                        .actor_id(&user.id.to_string())
+                        .client(&client.ip, &client.user_agent)
+                        .detail("reason", err.message.clone()),
+                );
+                return Err(err);
+            }
+        },
+        Err(err) => {
+            // Throttled attempts are refused before the password is checked
+            let outcome = match err.status {
+                StatusCode::TOO_MANY_REQUESTS => Outcome::Denied,
+                _ => Outcome::Failure,
+            };
+            state.audit.record(
+                AuditEntry::new(action::LOGIN, outcome)
+                    .actor(&args.email)
+                    .client(&client.ip, &client.user_agent)
+                    .detail("reason", err.message.clone()),
+            );
+            return Err(err);
+        }
+    };
+    let audit = AuditEntry::new(action::LOGIN, Outcome::Success)
+        .actor(&user_info.email)
+        .actor_id(&user_info.id.to_string())
+        .client(&client.ip, &client.user_agent);
+    // With a second factor the password only yields a challenge
+    if mfa_enabled(&pg, &user_info.id).await? {
+        state.audit.record(audit.detail("mfa_required", true));
+        let mfa_token = create_challenge(
+            &redis,
+            &MfaChallenge {
+                user_id: user_info.id,
+                email: user_info.email.clone(),
+                browser: args.browser.clone(),
+                cookie: args.cookie,
+            },
+        )
+        .await?;
+        return Ok((
+            HeaderMap::new(),
+            Json(PostLoginResult::Mfa(PostLoginMfaOutput {
+                mfa_required: true,
+                mfa_token,
+                expires_in: MFA_CHALLENGE_TTL_SECONDS,
+            })),
+        ));
+    }
+    // User authentication is successed, generating login
+    record_login_success(&redis, &user_info.email).await?;
+    let tokens = login_user(&state, &headers, peer, &user_info, args.browser.clone()).await?;
+    state.audit.record(audit);
+    deliver_tokens(&state, tokens, args.cookie)
+}
+
+// #[post("/login/mfa")]
+pub async fn post_login_mfa(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostLoginMfaInput>,
+) -> Result<(HeaderMap, Json<PostLoginResult>), AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let challenge = match redeem_challenge(
+        &redis,
+        &pg,
+        &state.config.login_throttle,
+        &args.mfa_token,
+        &args.code,
+        &client,
+    )
+    .await
+    {
+        Ok(challenge) => challenge,
+        Err(err) => {
+            state.audit.record(
+                AuditEntry::new(action::LOGIN_MFA, Outcome::Failure)
+                    .client(&client.ip, &client.user_agent)
+                    .detail("reason", err.message.clone()),
+            );
+            return Err(err);
+        }
+    };
     let user_info =
-        match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
-            .bind(&args.email)
+        match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+            .bind(challenge.user_id)
             .fetch_optional(&pg)
             .await?
         {
-            Some(user) => user,
-            None => {
+            Some(user) if user.is_active() => user,
+            _ => {
                 return Err(AppError::new(AUTH_FAILD_MESSAGE, StatusCode::FORBIDDEN, 2));
             }
         };
-    if !verify(&args.password, &user_info.password_hash)? {
-        return Err(AppError::new(AUTH_FAILD_MESSAGE, StatusCode::FORBIDDEN, 2));
-    }
-    // User authentication is successed, generating login
-    // Access Token
-    let session: u64 = {
-        let mut rng = rand::thread_rng();
-        rng.r#gen()
-    };
-    let iat = Utc::now().timestamp();
-    let exp = (Utc::now() + Duration::minutes(jwt_access_session_min)).timestamp();
-    let email = user_info.email.to_string().clone();
-    let name = user_info.name.clone();
-    let id = format!("{:?}", &user_info.id);
-    let access_claim = Claims {
-        iat,
-        exp,
-        id: id.clone(),
-        name: name.clone(),
-        email: email.clone(),
-        session,
-        source: "local".to_string(),
-    };
-    // Refresh Token
-    let exp = (Utc::now() + Duration::days(jwt_refresh_session_day)).timestamp();
-    let refresh_claim = Claims {
-        iat,
-        exp,
-        id: id.clone(),
-        name: name.clone(),
-        email: email.clone(),
-        session,
-        source: "local".to_string(),
+    let tokens = login_user(&state, &headers, peer, &user_info, challenge.browser).await?;
+    state.audit.record(
+        AuditEntry::new(action::LOGIN_MFA, Outcome::Success)
+            .actor(&user_info.email)
+            .actor_id(&user_info.id.to_string())
+            .client(&client.ip, &client.user_agent),
+    );
+    deliver_tokens(&state, tokens, challenge.cookie)
+}
+
+pub async fn post_refresh(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostRefreshInput>,
+) -> Result<(HeaderMap, Json<PostLoginResult>), AppError> {
+    let config = state.config.clone();
+    let redis = state.redis.clone();
+    let pg = state.pg.clone();
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::TOKEN_REFRESH, outcome).client(&client.ip, &client.user_agent)
     };
-    // Generate Tokens
-    let access_token = generate_token(Algorithm::HS256, &jwt_access_key, &access_claim)?;
-    let refresh_token = generate_token(Algorithm::HS256, &jwt_refresh_key, &refresh_claim)?;
-    // Saving Refresh Token in Redis
-    let redis_key = format!("{}:{}", &email, &session);
-    let redis_info = RedisInfo {
-        id,
-        name,
-        email,
-        session,
-        token: refresh_token.clone(),
-        source: "local".to_string(),
+    // Without a token in the body this is a cookie-mode refresh
+    let (refresh_token, cookie) = match args.refresh_token {
+        Some(token) => (token, false),
+        None => (cookie_refresh_token(&config, &headers)?, true),
     };
-    let redis_info_str = serde_json::to_string(&redis_info)?;
-    Redis::set(&redis, &redis_key, &redis_info_str).await?;
-    Ok(Json(PostLoginOutput {
-        access_token,
-        refresh_token,
-    }))
+    match rotate_session(
+        &config,
+        &redis,
+        &state.jwt_keys,
+        &pg,
+        &refresh_token,
+        client.clone(),
+    )
+    .await
+    {
+        Ok(tokens) => {
+            state.audit.record(audit(Outcome::Success));
+            deliver_tokens(&state, tokens, cookie)
+        }
+        Err(err) => {
+            // A reused refresh token revokes the session and is the notable case here
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message.clone()));
+            Err(err)
+        }
+    }
 }
 
 pub async fn post_logout(
+    caller: AuthUser,
     headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, AppError> {
+) -> Result<(HeaderMap, StatusCode), AppError> {
     let redis = state.redis.clone();
-    let email = match get_email(&headers) {
-        None => {
-            error!("Email header not found!");
-            return Err(AppError::internal(AUTH_FAILD_MESSAGE));
-        }
-        Some(e) => e,
-    };
-    let session = match get_session(&headers) {
-        None => {
-            error!("Session header not found!");
-            return Err(AppError::internal(AUTH_FAILD_MESSAGE));
-        }
-        Some(s) => s,
-    };
-    let _ = Redis::del(&redis, vec![&format!("{}:{}", &email, &session)]).await?;
-    Ok(StatusCode::OK)
+    Redis::del(&redis, vec![&session_key(&caller.email, caller.session)]).await?;
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    state.audit.record(
+        AuditEntry::new(action::LOGOUT, Outcome::Success)
+            .actor(&caller.email)
+            .actor_id(&caller.id.to_string())
+            .client(&client.ip, &client.user_agent),
+    );
+    // Harmless for bearer clients, ends the session of cookie clients
+    Ok((clear_session_cookies(&state.config), StatusCode::OK))
 }
@@ -0,0 +1,213 @@
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{mcp_client::create_mcp_client, session::ClientInfo};
+use app_config::AppConfig;
+use app_dto::auth::mcp_client::{
+    McpClientOutput, PatchMcpClientInput, PostMcpClientInput, PostMcpClientOutput,
+};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_schema::auth::mcp_clients::McpClient;
+use app_state::AppState;
+use axum::{
+    extract::{ConnectInfo, Json, Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use chrono::{DateTime, Duration, Utc};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+use uuid::Uuid;
+
+fn mcp_client_output(client: McpClient) -> McpClientOutput {
+    McpClientOutput {
+        id: client.id.to_string(),
+        name: client.name,
+        prefix: client.prefix,
+        allowed_tools: client.allowed_tools,
+        expires_at: client.expires_at.map(|e| e.timestamp()),
+        last_used_at: client.last_used_at.map(|e| e.timestamp()),
+        created_at: client.created_at.timestamp(),
+    }
+}
+
+fn not_found() -> AppError {
+    AppError::new("MCP client not found!", StatusCode::NOT_FOUND, 3)
+}
+
+fn expiry(expires_in_days: Option<i64>) -> Result<Option<DateTime<Utc>>, AppError> {
+    match expires_in_days {
+        None => Ok(None),
+        Some(days) if days > 0 => Ok(Some(Utc::now() + Duration::days(days))),
+        Some(_) => Err(AppError::new(
+            "expires_in_days must be positive!",
+            StatusCode::BAD_REQUEST,
+            6,
+        )),
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|_| not_found())
+}
+
+fn normalize_tools(tools: &[String]) -> Vec<String> {
+    let mut tools: Vec<String> = tools.iter().map(|t| t.trim().to_string()).collect();
+    tools.retain(|t| !t.is_empty());
+    tools.sort();
+    tools.dedup();
+    tools
+}
+
+/// Audit entry of an MCP client change, attributed to the caller
+fn client_entry(
+    config: &AppConfig,
+    caller: &AuthUser,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    action: &str,
+    name: &str,
+) -> AuditEntry {
+    let client = ClientInfo::new(config, headers, peer, None);
+    AuditEntry::new(action, Outcome::Success)
+        .actor(&caller.email)
+        .actor_id(&caller.id.to_string())
+        .target(name)
+        .client(&client.ip, &client.user_agent)
+}
+
+// #[get("/auth/mcp_clients")]
+pub async fn get_mcp_clients(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<McpClientOutput>>, AppError> {
+    let pg = state.pg.clone();
+    let res =
+        sqlx::query_as::<_, McpClient>(&format!("{} ORDER BY name", McpClient::select_query()))
+            .fetch_all(&pg)
+            .await?
+            .into_iter()
+            .map(mcp_client_output)
+            .collect();
+    Ok(Json(res))
+}
+
+// #[post("/auth/mcp_clients")]
+pub async fn post_mcp_client(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostMcpClientInput>,
+) -> Result<(StatusCode, Json<PostMcpClientOutput>), AppError> {
+    let pg = state.pg.clone();
+    let name = args.name.trim();
+    if name.is_empty() {
+        return Err(AppError::new(
+            "Client name is required!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    let allowed_tools = normalize_tools(&args.allowed_tools);
+    let expires_at = expiry(args.expires_in_days)?;
+    let (token, client) = create_mcp_client(&pg, name, &allowed_tools, expires_at).await?;
+    info!("MCP client {} created by {}", &client.name, &caller.email);
+    state.audit.record(
+        client_entry(
+            &state.config,
+            &caller,
+            &headers,
+            peer,
+            action::MCP_CLIENT_CREATE,
+            name,
+        )
+        .detail("allowed_tools", allowed_tools),
+    );
+    Ok((
+        StatusCode::CREATED,
+        Json(PostMcpClientOutput {
+            token,
+            client: mcp_client_output(client),
+        }),
+    ))
+}
+
+// #[get("/auth/mcp_clients/{id}")]
+pub async fn get_mcp_client_by_id(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<McpClientOutput>, AppError> {
+    let pg = state.pg.clone();
+    match sqlx::query_as::<_, McpClient>(&format!("{} WHERE id = $1", McpClient::select_query()))
+        .bind(parse_id(&id)?)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => Err(not_found()),
+        Some(client) => Ok(Json(mcp_client_output(client))),
+    }
+}
+
+// #[patch("/auth/mcp_clients/{id}")]
+pub async fn patch_mcp_client(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(args): Json<PatchMcpClientInput>,
+) -> Result<Json<McpClientOutput>, AppError> {
+    let pg = state.pg.clone();
+    let allowed_tools = args.allowed_tools.as_deref().map(normalize_tools);
+    let client = match sqlx::query_as::<_, McpClient>(McpClient::update_query())
+        .bind(parse_id(&id)?)
+        .bind(args.name.as_deref().map(str::trim))
+        .bind(&allowed_tools)
+        .bind(expiry(args.expires_in_days)?)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => return Err(not_found()),
+        Some(client) => client,
+    };
+    info!("MCP client {} updated by {}", &client.name, &caller.email);
+    state.audit.record(
+        client_entry(
+            &state.config,
+            &caller,
+            &headers,
+            peer,
+            action::MCP_CLIENT_UPDATE,
+            &client.name,
+        )
+        .detail("allowed_tools", client.allowed_tools.clone()),
+    );
+    Ok(Json(mcp_client_output(client)))
+}
+
+// #[delete("/auth/mcp_clients/{id}")]
+pub async fn delete_mcp_client(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let deleted = sqlx::query(McpClient::delete_query())
+        .bind(parse_id(&id)?)
+        .execute(&pg)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(not_found());
+    }
+    info!("MCP client {} deleted by {}", &id, &caller.email);
+    state.audit.record(client_entry(
+        &state.config,
+        &caller,
+        &headers,
+        peer,
+        action::MCP_CLIENT_DELETE,
+        &id,
+    ));
+    Ok(StatusCode::OK)
+}
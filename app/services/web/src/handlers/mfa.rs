@@ -0,0 +1,128 @@
+use crate::handlers::identity::current_user;
+use app_auth::{
+    api_key::API_KEY_SOURCE,
+    mfa::{
+        begin_totp_enrollment, confirm_totp_enrollment, disable_totp, mfa_enabled,
+        regenerate_recovery_codes, verify_second_factor,
+    },
+};
+use app_cryptography::hash::Hasher;
+use app_dto::auth::mfa::{
+    PostMfaReauthInput, PostTotpConfirmInput, PostTotpEnrollOutput, RecoveryCodesOutput,
+};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_schema::auth::users::User;
+use app_state::AppState;
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::*;
+
+const REAUTH_FAILED_MESSAGE: &str = "Provided information is wrong!";
+
+/// Second factors belong to local accounts and are managed by signed-in users only
+async fn mfa_owner(pg: &Pool<Postgres>, caller: &AuthUser) -> Result<User, AppError> {
+    if caller.source == API_KEY_SOURCE {
+        return Err(AppError::new(
+            "API keys cannot manage two-factor authentication!",
+            StatusCode::FORBIDDEN,
+            4,
+        ));
+    }
+    let user = current_user(pg, caller).await?;
+    if user.password_hash.is_none() {
+        return Err(AppError::new(
+            "Two-factor authentication is only available for password accounts!",
+            StatusCode::FORBIDDEN,
+            2,
+        ));
+    }
+    Ok(user)
+}
+
+/// Requires both the password and a current second factor
+async fn reauthenticate(
+    pg: &Pool<Postgres>,
+    hasher: &Hasher,
+    user: &User,
+    args: &PostMfaReauthInput,
+) -> Result<(), AppError> {
+    let failed = || AppError::new(REAUTH_FAILED_MESSAGE, StatusCode::FORBIDDEN, 2);
+    if !mfa_enabled(pg, &user.id).await? {
+        return Err(AppError::new(
+            "Two-factor authentication is not enabled!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    let password_hash = match &user.password_hash {
+        None => return Err(failed()),
+        Some(h) => h,
+    };
+    if !hasher.verify(&args.password, password_hash)?.valid {
+        return Err(failed());
+    }
+    if !verify_second_factor(pg, &user.id, &args.code).await? {
+        return Err(failed());
+    }
+    Ok(())
+}
+
+// #[post("/auth/mfa/totp/enroll")]
+pub async fn post_totp_enroll(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<PostTotpEnrollOutput>, AppError> {
+    let pg = state.pg.clone();
+    let user = mfa_owner(&pg, &caller).await?;
+    let enrollment = begin_totp_enrollment(&pg, &state.config, &user).await?;
+    Ok(Json(PostTotpEnrollOutput {
+        secret: enrollment.secret,
+        uri: enrollment.uri,
+    }))
+}
+
+// #[post("/auth/mfa/totp/confirm")]
+pub async fn post_totp_confirm(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostTotpConfirmInput>,
+) -> Result<Json<RecoveryCodesOutput>, AppError> {
+    let pg = state.pg.clone();
+    let user = mfa_owner(&pg, &caller).await?;
+    let recovery_codes = confirm_totp_enrollment(&pg, &user.id, &args.code).await?;
+    info!("TOTP enabled for {}", &user.email);
+    Ok(Json(RecoveryCodesOutput { recovery_codes }))
+}
+
+// #[post("/auth/mfa/totp/disable")]
+pub async fn post_totp_disable(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostMfaReauthInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let user = mfa_owner(&pg, &caller).await?;
+    reauthenticate(&pg, &state.hasher, &user, &args).await?;
+    disable_totp(&pg, &user.id).await?;
+    info!("TOTP disabled for {}", &user.email);
+    Ok(StatusCode::OK)
+}
+
+// #[post("/auth/mfa/recovery_codes")]
+pub async fn post_recovery_codes(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostMfaReauthInput>,
+) -> Result<Json<RecoveryCodesOutput>, AppError> {
+    let pg = state.pg.clone();
+    let user = mfa_owner(&pg, &caller).await?;
+    reauthenticate(&pg, &state.hasher, &user, &args).await?;
+    let recovery_codes = regenerate_recovery_codes(&pg, &user.id).await?;
+    info!("Recovery codes regenerated for {}", &user.email);
+    Ok(Json(RecoveryCodesOutput { recovery_codes }))
+}
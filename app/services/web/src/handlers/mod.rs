@@ -1,8 +1,20 @@
 pub mod agent;
+pub mod agent_ws;
+pub mod api_key;
+pub mod audit;
 pub mod customer;
-pub mod google;
+pub mod email;
+pub mod identity;
 pub mod index;
+pub mod jwks;
 pub mod knowledge_based;
+pub mod lockout;
 pub mod login;
+pub mod mcp_client;
+pub mod mfa;
+pub mod oauth;
+pub mod oauth2;
+pub mod password;
 pub mod ping;
+pub mod session;
 pub mod user;
@@ -0,0 +1,194 @@
+use crate::handlers::identity::current_user;
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    cookie::{check_oauth_state, clear_oauth_state_cookie, oauth_state_cookie, session_cookies},
+    identity::{link_identity, resolve_identity},
+    oauth::{STATE_TTL_SECONDS, take_authorization},
+    oidc::{authorization_url, complete_authorization, find_provider},
+    rbac::load_access,
+    session::{ClientInfo, SessionUser, create_session},
+};
+use app_dto::auth::identity::PostLinkIdentityOutput;
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_state::AppState;
+use axum::{
+    extract::{ConnectInfo, Json, Path, Query, State},
+    http::{HeaderMap, StatusCode, header::SET_COOKIE},
+    response::Redirect,
+};
+use serde::Deserialize;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+
+// #[get("/oauth/{provider}/auth")]
+pub async fn get_oauth_auth(
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+) -> Result<(HeaderMap, Redirect), AppError> {
+    let config = state.config.clone();
+    let redis = state.redis.clone();
+    let provider = find_provider(&config, &provider)?;
+    // State, nonce and PKCE verifier of this attempt are kept in Redis until the callback,
+    // and the state cookie ties the attempt to this browser
+    let (auth_url, oauth_state) = authorization_url(&redis, provider, None).await?;
+    let mut cookies = HeaderMap::new();
+    cookies.insert(
+        SET_COOKIE,
+        oauth_state_cookie(&config, &oauth_state, STATE_TTL_SECONDS),
+    );
+    debug!("Redirecting to {} sign-in", &provider.name);
+    Ok((cookies, Redirect::to(auth_url.as_str())))
+}
+
+// #[post("/auth/identities/{provider}")]
+pub async fn post_link_identity(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+) -> Result<(HeaderMap, Json<PostLinkIdentityOutput>), AppError> {
+    let config = state.config.clone();
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let provider = find_provider(&config, &provider)?;
+    let user = current_user(&pg, &caller).await?;
+    // The browser follows the returned URL without the bearer token, so the
+    // pending authorization remembers who asked for the link, and the state cookie
+    // ties it to the browser that asked
+    let (auth_url, oauth_state) = authorization_url(&redis, provider, Some(user.id)).await?;
+    let mut cookies = HeaderMap::new();
+    cookies.insert(
+        SET_COOKIE,
+        oauth_state_cookie(&config, &oauth_state, STATE_TTL_SECONDS),
+    );
+    Ok((
+        cookies,
+        Json(PostLinkIdentityOutput {
+            url: auth_url.to_string(),
+        }),
+    ))
+}
+
+/// Query parameters of the provider's callback URL
+#[derive(Deserialize)]
+pub struct AuthQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+// #[get("/oauth/{provider}/callback")]
+pub async fn get_oauth_callback(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+    Query(query): Query<AuthQuery>,
+) -> Result<(HeaderMap, Redirect), AppError> {
+    let config = state.config.clone();
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let provider = find_provider(&config, &provider)?;
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::OAUTH_CALLBACK, outcome)
+            .target(&provider.name)
+            .client(&client.ip, &client.user_agent)
+    };
+    // The state is consumed before anything else so it cannot be replayed
+    let oauth_state = query.state.as_deref().unwrap_or_default();
+    let pending = take_authorization(&redis, &provider.name, oauth_state).await?;
+    // A sign-in or a link is only completed in the browser that started it
+    if let Err(err) = check_oauth_state(&config, &headers, oauth_state) {
+        warn!("{} callback without its state cookie", &provider.name);
+        state
+            .audit
+            .record(audit(Outcome::Denied).detail("reason", err.message.clone()));
+        return Err(err);
+    }
+    if let Some(err) = &query.error {
+        warn!("{} sign-in was not completed: {}", &provider.name, err);
+        state
+            .audit
+            .record(audit(Outcome::Failure).detail("reason", err.clone()));
+        return Err(AppError::new(
+            "Sign-in was not completed!",
+            StatusCode::UNAUTHORIZED,
+            0,
+        ));
+    }
+    let code = match &query.code {
+        None => {
+            return Err(AppError::new(
+                "Authorization code is missing!",
+                StatusCode::BAD_REQUEST,
+                6,
+            ));
+        }
+        Some(code) => code.clone(),
+    };
+    let identity = match complete_authorization(&redis, provider, &pending, &code).await {
+        Ok(identity) => identity,
+        Err(err) => {
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message.clone()));
+            return Err(err);
+        }
+    };
+
+    // Link the identity to a signed-in user, or sign in with it
+    if let Some(user_id) = &pending.link_user_id {
+        link_identity(&pg, user_id, &identity).await?;
+        info!("{} identity linked to user {}", &provider.name, user_id);
+        state.audit.record(
+            audit(Outcome::Success)
+                .actor_id(&user_id.to_string())
+                .detail("linked", true),
+        );
+        let mut cookies = HeaderMap::new();
+        cookies.insert(SET_COOKIE, clear_oauth_state_cookie(&config));
+        return Ok((cookies, Redirect::to("/")));
+    }
+    let user = match resolve_identity(&pg, &identity).await {
+        Ok(user) => user,
+        Err(err) => {
+            state.audit.record(
+                audit(Outcome::Denied)
+                    .actor(&identity.email)
+                    .detail("reason", err.message.clone()),
+            );
+            return Err(err);
+        }
+    };
+
+    // OUR APP TOKEN GENERATION
+    let id = format!("{:?}", &user.id);
+    let access = load_access(&pg, &id).await?;
+    let tokens = create_session(
+        &config,
+        &redis,
+        &state.jwt_keys,
+        SessionUser {
+            id,
+            name: user.name.clone(),
+            email: user.email.clone(),
+            source: provider.name.clone(),
+            access,
+        },
+        client.clone(),
+    )
+    .await?;
+    state.audit.record(
+        audit(Outcome::Success)
+            .actor(&user.email)
+            .actor_id(&user.id.to_string()),
+    );
+
+    // The session reaches the browser as cookies, never in the URL
+    let (mut cookies, _) = session_cookies(&config, &state.jwt_keys, &tokens)?;
+    cookies.append(SET_COOKIE, clear_oauth_state_cookie(&config));
+
+    // Redirect to Main Page
+    Ok((cookies, Redirect::to("/")))
+}
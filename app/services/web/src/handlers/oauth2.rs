@@ -0,0 +1,369 @@
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    cookie::{ACCESS_COOKIE, CSRF_COOKIE, csrf_key, csrf_token_valid, get_cookie},
+    mcp_oauth::{
+        AuthorizationCode, MCP_SCOPE, OAuthError, authorization_client,
+        authorization_server_metadata, check_authorization_request, exchange_code, is_mcp_source,
+        issue_code, redirect_with, refresh_grant, register_client, valid_resource,
+    },
+    session::{ClientInfo, session_exists},
+};
+use app_cryptography::jwt::Claims;
+use app_dto::auth::oauth2::{
+    AuthorizationServerMetadata, AuthorizeDecisionInput, AuthorizeQuery, RegisterClientInput,
+    RegisterClientOutput, TokenInput, TokenOutput,
+};
+use app_error::AppError;
+use app_state::AppState;
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Form, Json, Query, State},
+    http::{
+        HeaderMap, HeaderName, StatusCode, Uri,
+        header::{CACHE_CONTROL, X_FRAME_OPTIONS},
+    },
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use reqwest::Url;
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "oauth2_consent.html")]
+struct ConsentPage {
+    error: String, // Shown instead of the form when the request cannot be redirected
+    client_name: String,
+    email: String,
+    csrf_token: String,
+    fields: Vec<(&'static str, String)>, // The authorization request, echoed by the form
+}
+
+fn not_found() -> AppError {
+    AppError::new("Not found!", StatusCode::NOT_FOUND, 3)
+}
+
+fn error_page(status: StatusCode, message: String) -> Result<Response, AppError> {
+    let page = ConsentPage {
+        error: message,
+        client_name: String::new(),
+        email: String::new(),
+        csrf_token: String::new(),
+        fields: vec![],
+    };
+    Ok((status, Html(page.render()?)).into_response())
+}
+
+/// Sends an error code back to the client, with its `state`
+fn error_redirect(redirect_uri: &str, error: &str, state: &Option<String>) -> Response {
+    let mut params = vec![("error", error)];
+    if let Some(state) = state {
+        params.push(("state", state.as_str()));
+    }
+    Redirect::to(&redirect_with(redirect_uri, &params)).into_response()
+}
+
+/// Claims of the browser session of the signed-in user; tokens of MCP clients do not count
+async fn browser_session(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Claims>, AppError> {
+    let token = match get_cookie(headers, ACCESS_COOKIE) {
+        None => return Ok(None),
+        Some(token) => token,
+    };
+    let claims = match state.jwt_keys.verify::<Claims>(&token) {
+        Err(_) => return Ok(None),
+        Ok(data) => data.claims,
+    };
+    if is_mcp_source(&claims.source)
+        || !session_exists(&state.redis, &claims.email, claims.session).await?
+    {
+        return Ok(None);
+    }
+    Ok(Some(claims))
+}
+
+fn request_fields(request: &AuthorizeQuery, redirect_uri: &str) -> Vec<(&'static str, String)> {
+    let field = |value: &Option<String>| value.clone().unwrap_or_default();
+    vec![
+        ("response_type", field(&request.response_type)),
+        ("client_id", field(&request.client_id)),
+        ("redirect_uri", redirect_uri.to_owned()),
+        ("state", field(&request.state)),
+        ("code_challenge", field(&request.code_challenge)),
+        (
+            "code_challenge_method",
+            field(&request.code_challenge_method),
+        ),
+        ("scope", field(&request.scope)),
+        ("resource", field(&request.resource)),
+    ]
+}
+
+/// The form posts empty strings for parameters the client did not send
+fn non_empty(request: AuthorizeQuery) -> AuthorizeQuery {
+    let keep = |value: Option<String>| value.filter(|v| !v.is_empty());
+    AuthorizeQuery {
+        response_type: keep(request.response_type),
+        client_id: keep(request.client_id),
+        redirect_uri: keep(request.redirect_uri),
+        state: keep(request.state),
+        code_challenge: keep(request.code_challenge),
+        code_challenge_method: keep(request.code_challenge_method),
+        scope: keep(request.scope),
+        resource: keep(request.resource),
+    }
+}
+
+// #[get("/.well-known/oauth-authorization-server")]
+pub async fn get_authorization_server(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<AuthorizationServerMetadata>, AppError> {
+    if !state.config.mcp_oauth.enabled {
+        return Err(not_found());
+    }
+    Ok(Json(authorization_server_metadata(&state.config)))
+}
+
+// #[post("/oauth2/register")]
+pub async fn post_oauth2_register(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<RegisterClientInput>,
+) -> Result<(StatusCode, Json<RegisterClientOutput>), OAuthError> {
+    let pg = state.pg.clone();
+    let config = state.config.clone();
+    if !config.mcp_oauth.enabled || !config.mcp_oauth.registration {
+        return Err(OAuthError {
+            status: StatusCode::NOT_FOUND,
+            error: "invalid_request",
+            description: "Client registration is disabled".into(),
+        });
+    }
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::OAUTH2_REGISTER, outcome).client(&client.ip, &client.user_agent)
+    };
+    match register_client(&pg, &args).await {
+        Ok(output) => {
+            state.audit.record(
+                audit(Outcome::Success)
+                    .target(&output.client_id)
+                    .detail("client_name", output.client_name.clone()),
+            );
+            Ok((StatusCode::CREATED, Json(output)))
+        }
+        Err(err) => {
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.description.clone()));
+            Err(err)
+        }
+    }
+}
+
+// #[get("/oauth2/authorize")]
+pub async fn get_oauth2_authorize(
+    headers: HeaderMap,
+    uri: Uri,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuthorizeQuery>,
+) -> Result<Response, AppError> {
+    let pg = state.pg.clone();
+    let config = state.config.clone();
+    if !config.mcp_oauth.enabled {
+        return Err(not_found());
+    }
+    let (client, redirect_uri) = match authorization_client(&pg, &query).await {
+        Err(err) => return error_page(err.status, err.message),
+        Ok(found) => found,
+    };
+    if let Err(error) = check_authorization_request(&config, &query) {
+        return Ok(error_redirect(&redirect_uri, error, &query.state));
+    }
+    // The consent form is protected by the CSRF token of the browser session
+    let session = browser_session(&state, &headers).await?.and_then(|claims| {
+        get_cookie(&headers, CSRF_COOKIE)
+            .filter(|token| csrf_token_valid(&csrf_key(&config), claims.session, token))
+            .map(|token| (claims, token))
+    });
+    let (claims, csrf_token) = match session {
+        None => {
+            let next = uri
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or("/oauth2/authorize");
+            let login = Url::parse_with_params("http://localhost/login", &[("next", next)])
+                .map_err(|e| AppError::internal(e.to_string()))?;
+            let login = format!("{}?{}", login.path(), login.query().unwrap_or_default());
+            return Ok(Redirect::to(&login).into_response());
+        }
+        Some(session) => session,
+    };
+    let page = ConsentPage {
+        error: String::new(),
+        client_name: match client.client_name.is_empty() {
+            true => client.client_id.clone(),
+            false => client.client_name.clone(),
+        },
+        email: claims.email,
+        csrf_token,
+        fields: request_fields(&query, &redirect_uri),
+    };
+    // The consent must not be clickable through a frame of another site
+    Ok(([(X_FRAME_OPTIONS, "DENY")], Html(page.render()?)).into_response())
+}
+
+// #[post("/oauth2/authorize")]
+pub async fn post_oauth2_authorize(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Form(args): Form<AuthorizeDecisionInput>,
+) -> Result<Response, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let config = state.config.clone();
+    if !config.mcp_oauth.enabled {
+        return Err(not_found());
+    }
+    let request = non_empty(args.request);
+    let (client, redirect_uri) = match authorization_client(&pg, &request).await {
+        Err(err) => return error_page(err.status, err.message),
+        Ok(found) => found,
+    };
+    let claims = match browser_session(&state, &headers).await? {
+        None => return Err(AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0)),
+        Some(claims) => claims,
+    };
+    let client_info = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::OAUTH2_AUTHORIZE, outcome)
+            .actor(&claims.email)
+            .actor_id(&claims.id)
+            .target(&client.client_id)
+            .client(&client_info.ip, &client_info.user_agent)
+    };
+    // Double submit: the form must carry the CSRF cookie of this session
+    let csrf_cookie = get_cookie(&headers, CSRF_COOKIE).unwrap_or_default();
+    if args.csrf_token != csrf_cookie
+        || !csrf_token_valid(&csrf_key(&config), claims.session, &args.csrf_token)
+    {
+        state
+            .audit
+            .record(audit(Outcome::Denied).detail("reason", "invalid CSRF token"));
+        return Err(AppError::new(
+            "Missing or invalid CSRF token!",
+            StatusCode::FORBIDDEN,
+            4,
+        ));
+    }
+    if let Err(error) = check_authorization_request(&config, &request) {
+        return Ok(error_redirect(&redirect_uri, error, &request.state));
+    }
+    if args.approve.is_none() {
+        info!(
+            "{} denied access to OAuth client {}",
+            &claims.email, &client.client_id
+        );
+        state.audit.record(audit(Outcome::Denied));
+        return Ok(error_redirect(
+            &redirect_uri,
+            "access_denied",
+            &request.state,
+        ));
+    }
+    let user_id = match Uuid::parse_str(&claims.id) {
+        Err(_) => return Err(AppError::new("Unauthorized", StatusCode::UNAUTHORIZED, 0)),
+        Ok(id) => id,
+    };
+    let code = issue_code(
+        &redis,
+        &config,
+        &AuthorizationCode {
+            client_id: client.client_id.clone(),
+            redirect_uri: redirect_uri.clone(),
+            code_challenge: request.code_challenge.clone().unwrap_or_default(),
+            user_id,
+            scope: MCP_SCOPE.to_owned(),
+        },
+    )
+    .await?;
+    state.audit.record(audit(Outcome::Success));
+    let mut params = vec![("code", code.as_str())];
+    if let Some(state) = &request.state {
+        params.push(("state", state.as_str()));
+    }
+    Ok(Redirect::to(&redirect_with(&redirect_uri, &params)).into_response())
+}
+
+// #[post("/oauth2/token")]
+pub async fn post_oauth2_token(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Form(args): Form<TokenInput>,
+) -> Result<([(HeaderName, &'static str); 1], Json<TokenOutput>), OAuthError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let config = state.config.clone();
+    if !config.mcp_oauth.enabled {
+        return Err(OAuthError {
+            status: StatusCode::NOT_FOUND,
+            error: "invalid_request",
+            description: "OAuth is disabled".into(),
+        });
+    }
+    if !valid_resource(&config, args.resource.as_deref()) {
+        return Err(OAuthError::new("invalid_target", "Unknown resource"));
+    }
+    let client_info = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::OAUTH2_TOKEN, outcome)
+            .target(args.client_id.as_deref().unwrap_or_default())
+            .client(&client_info.ip, &client_info.user_agent)
+            .detail("grant_type", args.grant_type.clone())
+    };
+    let tokens = match args.grant_type.as_str() {
+        "authorization_code" => {
+            exchange_code(
+                &config,
+                &redis,
+                &state.jwt_keys,
+                &pg,
+                &args,
+                client_info.clone(),
+            )
+            .await
+        }
+        "refresh_token" => {
+            refresh_grant(
+                &config,
+                &redis,
+                &state.jwt_keys,
+                &pg,
+                &args,
+                client_info.clone(),
+            )
+            .await
+        }
+        _ => Err(OAuthError::new(
+            "unsupported_grant_type",
+            "Only authorization_code and refresh_token are supported",
+        )),
+    };
+    match tokens {
+        Ok(tokens) => {
+            state.audit.record(audit(Outcome::Success));
+            Ok(([(CACHE_CONTROL, "no-store")], Json(tokens)))
+        }
+        Err(err) => {
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.error));
+            Err(err)
+        }
+    }
+}
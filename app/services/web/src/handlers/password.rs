@@ -0,0 +1,118 @@
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    password_reset::{request_password_reset, reset_password},
+    session::ClientInfo,
+};
+use app_dto::auth::password::{
+    PostForgotPasswordInput, PostResetPasswordInput, ResetPasswordQuery,
+};
+use app_error::AppError;
+use app_state::AppState;
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Json, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Html,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tracing::*;
+
+#[derive(Template)]
+#[template(path = "password.html")]
+struct PasswordPage {
+    token: String, // Empty: ask for the email address
+}
+
+// #[get("/password/forgot")]
+pub async fn get_forgot_password() -> Result<Html<String>, AppError> {
+    let page = PasswordPage {
+        token: String::new(),
+    };
+    Ok(Html(page.render()?))
+}
+
+// #[get("/password/reset?token=")]
+pub async fn get_reset_password(
+    Query(query): Query<ResetPasswordQuery>,
+) -> Result<Html<String>, AppError> {
+    let page = PasswordPage {
+        token: query.token.unwrap_or_default(),
+    };
+    Ok(Html(page.render()?))
+}
+
+// #[post("/password/forgot")]
+pub async fn post_forgot_password(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostForgotPasswordInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let config = state.config.clone();
+    let email = args.email.trim();
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::PASSWORD_FORGOT, outcome)
+            .target(email)
+            .client(&client.ip, &client.user_agent)
+    };
+    // The answer must not reveal whether the account exists
+    match request_password_reset(&pg, state.mailer.as_ref(), &config, email).await {
+        Ok(()) => state.audit.record(audit(Outcome::Success)),
+        Err(err) => {
+            error!("Cannot send password reset: {}", err.message);
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message));
+        }
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+// #[post("/password/reset")]
+pub async fn post_reset_password(
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PostResetPasswordInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    if args.new_password.is_empty() {
+        return Err(AppError::new(
+            "New password is required!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::PASSWORD_RESET, outcome).client(&client.ip, &client.user_agent)
+    };
+    match reset_password(
+        &pg,
+        &redis,
+        &state.hasher,
+        &state.password_policy,
+        &args.token,
+        &args.new_password,
+    )
+    .await
+    {
+        Ok(user) => {
+            state.audit.record(
+                audit(Outcome::Success)
+                    .actor(&user.email)
+                    .actor_id(&user.id.to_string()),
+            );
+            Ok(StatusCode::OK)
+        }
+        Err(err) => {
+            state
+                .audit
+                .record(audit(Outcome::Failure).detail("reason", err.message.clone()));
+            Err(err)
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use app_auth::session::{get_session_info, list_sessions, revoke_all_sessions, revoke_session};
+use app_cryptography::jwt::RedisInfo;
+use app_dto::auth::session::{DeleteSessionsOutput, SessionOutput};
+use app_error::AppError;
+use app_middleware::AuthUser;
+use app_state::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+};
+use std::sync::Arc;
+use tracing::*;
+
+const SESSION_NOT_FOUND_MESSAGE: &str = "Session not found!";
+
+fn parse_session(session: &str) -> Result<u64, AppError> {
+    session
+        .parse::<u64>()
+        .map_err(|_| AppError::new(SESSION_NOT_FOUND_MESSAGE, StatusCode::NOT_FOUND, 3))
+}
+
+fn session_output(info: RedisInfo, current: Option<u64>) -> SessionOutput {
+    SessionOutput {
+        session: info.session.to_string(),
+        current: current == Some(info.session),
+        source: info.source,
+        device: info.device,
+        ip: info.ip,
+        user_agent: info.user_agent,
+        created_at: info.created_at,
+        last_seen: info.last_seen,
+        expires_at: info.expires_at,
+    }
+}
+
+// #[get("/auth/sessions")]
+pub async fn get_sessions(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SessionOutput>>, AppError> {
+    let redis = state.redis.clone();
+    let (email, current) = (caller.email, caller.session);
+    let res = list_sessions(&redis, &email)
+        .await?
+        .into_iter()
+        .map(|s| session_output(s, Some(current)))
+        .collect();
+    Ok(Json(res))
+}
+
+// #[delete("/auth/sessions")]
+pub async fn delete_sessions(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<DeleteSessionsOutput>, AppError> {
+    let redis = state.redis.clone();
+    let email = caller.email;
+    let revoked = revoke_all_sessions(&redis, &email).await?;
+    Ok(Json(DeleteSessionsOutput { revoked }))
+}
+
+// #[get("/auth/sessions/{session}")]
+pub async fn get_session_by_id(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(session): Path<String>,
+) -> Result<Json<SessionOutput>, AppError> {
+    let redis = state.redis.clone();
+    let (email, current) = (caller.email, caller.session);
+    let session = parse_session(&session)?;
+    match get_session_info(&redis, &email, session).await? {
+        None => Err(AppError::new(
+            SESSION_NOT_FOUND_MESSAGE,
+            StatusCode::NOT_FOUND,
+            3,
+        )),
+        Some(info) => Ok(Json(session_output(info, Some(current)))),
+    }
+}
+
+// #[delete("/auth/sessions/{session}")]
+pub async fn delete_session_by_id(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(session): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let redis = state.redis.clone();
+    let email = caller.email;
+    let session = parse_session(&session)?;
+    if !revoke_session(&redis, &email, session).await? {
+        return Err(AppError::new(
+            SESSION_NOT_FOUND_MESSAGE,
+            StatusCode::NOT_FOUND,
+            3,
+        ));
+    }
+    Ok(StatusCode::OK)
+}
+
+// Admin endpoints below are guarded by the `sessions:admin` permission in the router
+
+// #[get("/auth/sessions/user/{email}")]
+pub async fn get_user_sessions(
+    State(state): State<Arc<AppState>>,
+    Path(email): Path<String>,
+) -> Result<Json<Vec<SessionOutput>>, AppError> {
+    let redis = state.redis.clone();
+    let res = list_sessions(&redis, &email)
+        .await?
+        .into_iter()
+        .map(|s| session_output(s, None))
+        .collect();
+    Ok(Json(res))
+}
+
+// #[delete("/auth/sessions/user/{email}")]
+pub async fn delete_user_sessions(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path(email): Path<String>,
+) -> Result<Json<DeleteSessionsOutput>, AppError> {
+    let redis = state.redis.clone();
+    let admin = caller.email;
+    let revoked = revoke_all_sessions(&redis, &email).await?;
+    info!(
+        "Admin {} revoked {} sessions of {}",
+        &admin, revoked, &email
+    );
+    Ok(Json(DeleteSessionsOutput { revoked }))
+}
+
+// #[delete("/auth/sessions/user/{email}/{session}")]
+pub async fn delete_user_session_by_id(
+    caller: AuthUser,
+    State(state): State<Arc<AppState>>,
+    Path((email, session)): Path<(String, String)>,
+) -> Result<StatusCode, AppError> {
+    let redis = state.redis.clone();
+    let admin = caller.email;
+    let session = parse_session(&session)?;
+    if !revoke_session(&redis, &email, session).await? {
+        return Err(AppError::new(
+            SESSION_NOT_FOUND_MESSAGE,
+            StatusCode::NOT_FOUND,
+            3,
+        ));
+    }
+    info!("Admin {} revoked session {} of {}", &admin, session, &email);
+    Ok(StatusCode::OK)
+}
@@ -1,84 +1,483 @@
-use app_cryptography::hash::{hash, verify};
-use app_dto::auth::user::{PatchChangePasswordInput, PostUserInput};
+use app_audit::{AuditEntry, Outcome, action};
+use app_auth::{
+    email_verification::send_verification,
+    rbac::{DEFAULT_ROLE, replace_roles},
+    session::{ClientInfo, revoke_all_sessions},
+    throttle::{check_password, record_login_success},
+};
+use app_config::AppConfig;
+use app_dto::auth::user::{
+    PatchChangePasswordInput, PatchUserInput, PostUserInput, PostUserPasswordInput, PublicUser,
+    PutUserRolesInput, UsersPageOutput, UsersQuery,
+};
 use app_error::AppError;
-use app_middleware::get_session;
+use app_middleware::AuthUser;
 use app_redis::Redis;
-use app_schema::auth::users::User;
+use app_schema::auth::{roles::Role, users::User};
 use app_state::AppState;
 use axum::{
-    extract::{Json, State},
+    extract::{ConnectInfo, Json, Path, Query, State},
     http::{HeaderMap, StatusCode},
 };
-use std::sync::Arc;
+use sqlx::{Pool, Postgres};
+use std::{net::SocketAddr, sync::Arc};
 use tracing::*;
+use uuid::Uuid;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const MAX_PER_PAGE: i64 = 500;
 
-pub async fn get_user(State(state): State<Arc<AppState>>) -> Result<Json<Vec<User>>, AppError> {
+fn user_not_found() -> AppError {
+    AppError::new("User not found!", StatusCode::NOT_FOUND, 3)
+}
+
+/// Loads a user by id, deleted users included
+async fn find_user(pg: &Pool<Postgres>, id: &Uuid) -> Result<User, AppError> {
+    match sqlx::query_as::<_, User>(&format!("{} WHERE id = $1", User::select_query()))
+        .bind(id)
+        .fetch_optional(pg)
+        .await?
+    {
+        None => Err(user_not_found()),
+        Some(user) => Ok(user),
+    }
+}
+
+// #[get("/auth/user?email=&name=&disabled=&deleted=&page=&per_page=")]
+pub async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsersQuery>,
+) -> Result<Json<UsersPageOutput>, AppError> {
     let pg = state.pg.clone();
-    let res = sqlx::query_as::<_, User>(&User::select_query())
-        .fetch_all(&pg)
-        .await?;
-    debug!("{:?}", &res);
-    Ok(Json(res))
+    let deleted = query.deleted.unwrap_or(false);
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+    let total: i64 =
+        sqlx::query_scalar(&format!("{} {}", User::count_query(), User::filter_query()))
+            .bind(&query.email)
+            .bind(&query.name)
+            .bind(query.disabled)
+            .bind(deleted)
+            .fetch_one(&pg)
+            .await?;
+    let items = sqlx::query_as::<_, User>(&format!(
+        "{} {} ORDER BY created_at DESC, id LIMIT $5 OFFSET $6",
+        User::select_query(),
+        User::filter_query()
+    ))
+    .bind(&query.email)
+    .bind(&query.name)
+    .bind(query.disabled)
+    .bind(deleted)
+    .bind(per_page)
+    .bind((page - 1).saturating_mul(per_page))
+    .fetch_all(&pg)
+    .await?
+    .into_iter()
+    .map(PublicUser::from)
+    .collect();
+    Ok(Json(UsersPageOutput {
+        items,
+        page,
+        per_page,
+        total,
+    }))
+}
+
+// #[get("/auth/user/{id}")]
+pub async fn get_user_by_id(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PublicUser>, AppError> {
+    let pg = state.pg.clone();
+    Ok(Json(find_user(&pg, &id).await?.into()))
 }
 
-const AUTH_FAILD_MESSAGE: &'static str = "Provided information is wrong!";
+/// Audit entry of an admin action, attributed to the caller
+fn admin_entry(
+    config: &AppConfig,
+    caller: &AuthUser,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    action: &str,
+    target: &str,
+) -> AuditEntry {
+    let client = ClientInfo::new(config, headers, peer, None);
+    AuditEntry::new(action, Outcome::Success)
+        .actor(&caller.email)
+        .actor_id(&caller.id.to_string())
+        .target(target)
+        .client(&client.ip, &client.user_agent)
+}
 
 pub async fn post_user(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
     Json(args): Json<PostUserInput>,
 ) -> Result<StatusCode, AppError> {
     let pg = state.pg.clone();
-    let hash_password = hash(&args.password)?.to_string();
+    state
+        .password_policy
+        .validate(&args.password, &args.email)?;
+    let hash_password = state.hasher.hash(&args.password)?;
+    let roles = match &args.roles {
+        None => vec![DEFAULT_ROLE.to_string()],
+        Some(roles) => roles.clone(),
+    };
+    let mut tx = pg.begin().await?;
     sqlx::query(&User::insert_query())
         .bind(&args.name)
         .bind(&args.email)
         .bind(&hash_password)
-        .execute(&pg)
+        .execute(&mut *tx)
+        .await?;
+    replace_roles(&mut tx, &args.email, &roles).await?;
+    let user = sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+        .bind(&args.email)
+        .fetch_one(&mut *tx)
         .await?;
-    debug!("{}", format!("INSERT user: {:#?}", &args));
+    tx.commit().await?;
+    debug!("INSERT user: {} with roles {:?}", &args.email, &roles);
+    // The account exists either way; a lost mail can be resent from `/verify_email`
+    if let Err(err) = send_verification(&pg, state.mailer.as_ref(), &state.config, &user).await {
+        error!("Cannot send verification mail: {}", err.message);
+    }
+    state.audit.record(
+        admin_entry(
+            &state.config,
+            &caller,
+            &headers,
+            peer,
+            action::USER_CREATE,
+            &args.email,
+        )
+        .detail("roles", roles),
+    );
     Ok(StatusCode::CREATED)
 }
 
+pub async fn get_roles(State(state): State<Arc<AppState>>) -> Result<Json<Vec<Role>>, AppError> {
+    let pg = state.pg.clone();
+    let res = sqlx::query_as::<_, Role>(&format!("{} ORDER BY name", Role::select_query()))
+        .fetch_all(&pg)
+        .await?;
+    Ok(Json(res))
+}
+
+pub async fn put_user_roles(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Json(args): Json<PutUserRolesInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let mut tx = pg.begin().await?;
+    replace_roles(&mut tx, &args.email, &args.roles).await?;
+    tx.commit().await?;
+    // New roles reach the user's token at the next refresh
+    info!("Roles of {} set to {:?}", &args.email, &args.roles);
+    state.audit.record(
+        admin_entry(
+            &state.config,
+            &caller,
+            &headers,
+            peer,
+            action::USER_ROLES,
+            &args.email,
+        )
+        .detail("roles", args.roles.clone()),
+    );
+    Ok(StatusCode::OK)
+}
+
 pub async fn patch_change_password(
+    caller: AuthUser,
     headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
     Json(args): Json<PatchChangePasswordInput>,
 ) -> Result<StatusCode, AppError> {
     let pg = state.pg.clone();
     let redis = state.redis.clone();
-    // Search for user
-    let user_info =
-        match sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
-            .bind(&args.email)
-            .fetch_optional(&pg)
-            .await?
-        {
-            Some(user) => user,
-            None => {
-                return Err(AppError::new(AUTH_FAILD_MESSAGE, StatusCode::FORBIDDEN, 2));
-            }
-        };
-    // Verify current password
-    if !verify(&args.password, &user_info.password_hash)? {
-        return Err(AppError::new(AUTH_FAILD_MESSAGE, StatusCode::FORBIDDEN, 2));
+    let client = ClientInfo::new(&state.config, &headers, peer, None);
+    let audit = |outcome| {
+        AuditEntry::new(action::PASSWORD_CHANGE, outcome)
+            .actor(&args.email)
+            .actor_id(&caller.id.to_string())
+            .client(&client.ip, &client.user_agent)
+    };
+    // Verify current password under the brute-force throttle
+    if let Err(err) = check_password(
+        &redis,
+        &pg,
+        &state.config.login_throttle,
+        &state.hasher,
+        &args.email,
+        &args.password,
+        &client,
+    )
+    .await
+    {
+        state
+            .audit
+            .record(audit(Outcome::Failure).detail("reason", err.message.clone()));
+        return Err(err);
     }
-    // Hash new password
-    let hash_password = hash(&args.new_password)?.to_string();
+    record_login_success(&redis, &args.email).await?;
+    // Check and hash new password
+    state
+        .password_policy
+        .validate(&args.new_password, &args.email)?;
+    let hash_password = state.hasher.hash(&args.new_password)?;
     // Update record
     sqlx::query(&User::change_password_query())
         .bind(&hash_password)
         .bind(&args.email)
         .execute(&pg)
         .await?;
+    state.audit.record(audit(Outcome::Success));
     // Clean Redis login sessions
-    let session = match get_session(&headers) {
-        None => {
-            return Err(AppError::internal(
-                "Password has been changed, but session information is worng!",
+    Redis::del(
+        &redis,
+        vec![&format!("{}:{}", &args.email, &caller.session)],
+    )
+    .await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+// #[patch("/auth/user/{id}")]
+pub async fn patch_user(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(args): Json<PatchUserInput>,
+) -> Result<Json<PublicUser>, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let current = find_user(&pg, &id).await?;
+    if current.deleted_at.is_some() {
+        return Err(user_not_found());
+    }
+    let email = args.email.as_deref().map(str::trim);
+    if let Some(email) = email {
+        if !email.contains('@') {
+            return Err(AppError::new(
+                "Invalid email address!",
+                StatusCode::BAD_REQUEST,
+                6,
+            ));
+        }
+        if email != current.email
+            && sqlx::query_as::<_, User>(&format!("{} WHERE email = $1", User::select_query()))
+                .bind(email)
+                .fetch_optional(&pg)
+                .await?
+                .is_some()
+        {
+            return Err(AppError::new(
+                "Email is already in use!",
+                StatusCode::CONFLICT,
+                7,
             ));
         }
-        Some(s) => s,
+    }
+    let user = match sqlx::query_as::<_, User>(User::update_profile_query())
+        .bind(id)
+        .bind(&args.name)
+        .bind(email)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => return Err(user_not_found()),
+        Some(user) => user,
+    };
+    // Sessions are keyed by email, so a renamed account has to sign in again and verify the
+    // new address
+    if user.email != current.email {
+        revoke_all_sessions(&redis, &current.email).await?;
+        if let Err(err) = send_verification(&pg, state.mailer.as_ref(), &state.config, &user).await
+        {
+            error!("Cannot send verification mail: {}", err.message);
+        }
+    }
+    info!("User {} updated by {}", &user.email, &caller.email);
+    state.audit.record(
+        admin_entry(
+            &state.config,
+            &caller,
+            &headers,
+            peer,
+            action::USER_UPDATE,
+            &current.email,
+        )
+        .detail("name", user.name.clone())
+        .detail("email", user.email.clone()),
+    );
+    Ok(Json(user.into()))
+}
+
+/// Disables or enables a user; disabling revokes their sessions
+async fn set_disabled(
+    caller: &AuthUser,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    state: &AppState,
+    id: &Uuid,
+    disabled: bool,
+) -> Result<PublicUser, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    if disabled && id == &caller.id {
+        return Err(AppError::new(
+            "You cannot disable your own account!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    let user = match sqlx::query_as::<_, User>(User::set_disabled_query())
+        .bind(id)
+        .bind(disabled)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => return Err(user_not_found()),
+        Some(user) => user,
+    };
+    let action = if disabled {
+        let revoked = revoke_all_sessions(&redis, &user.email).await?;
+        info!(
+            "User {} disabled by {}, {} sessions revoked",
+            &user.email, &caller.email, revoked
+        );
+        action::USER_DISABLE
+    } else {
+        info!("User {} enabled by {}", &user.email, &caller.email);
+        action::USER_ENABLE
+    };
+    state.audit.record(admin_entry(
+        &state.config,
+        caller,
+        headers,
+        peer,
+        action,
+        &user.email,
+    ));
+    Ok(user.into())
+}
+
+// #[post("/auth/user/{id}/disable")]
+pub async fn post_disable_user(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PublicUser>, AppError> {
+    Ok(Json(
+        set_disabled(&caller, &headers, peer, &state, &id, true).await?,
+    ))
+}
+
+// #[post("/auth/user/{id}/enable")]
+pub async fn post_enable_user(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<PublicUser>, AppError> {
+    Ok(Json(
+        set_disabled(&caller, &headers, peer, &state, &id, false).await?,
+    ))
+}
+
+// #[delete("/auth/user/{id}")]
+pub async fn delete_user(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    if id == caller.id {
+        return Err(AppError::new(
+            "You cannot delete your own account!",
+            StatusCode::BAD_REQUEST,
+            6,
+        ));
+    }
+    // Soft delete: the row stays for the audit trail and can no longer sign in
+    let user = match sqlx::query_as::<_, User>(User::soft_delete_query())
+        .bind(id)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => return Err(user_not_found()),
+        Some(user) => user,
+    };
+    let revoked = revoke_all_sessions(&redis, &user.email).await?;
+    info!(
+        "User {} deleted by {}, {} sessions revoked",
+        &user.email, &caller.email, revoked
+    );
+    state.audit.record(admin_entry(
+        &state.config,
+        &caller,
+        &headers,
+        peer,
+        action::USER_DELETE,
+        &user.email,
+    ));
+    Ok(StatusCode::OK)
+}
+
+// #[post("/auth/user/{id}/password")]
+pub async fn post_user_password(
+    caller: AuthUser,
+    headers: HeaderMap,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(args): Json<PostUserPasswordInput>,
+) -> Result<StatusCode, AppError> {
+    let pg = state.pg.clone();
+    let redis = state.redis.clone();
+    let current = find_user(&pg, &id).await?;
+    state
+        .password_policy
+        .validate(&args.password, &current.email)?;
+    let hash_password = state.hasher.hash(&args.password)?;
+    let user = match sqlx::query_as::<_, User>(User::set_password_query())
+        .bind(id)
+        .bind(&hash_password)
+        .fetch_optional(&pg)
+        .await?
+    {
+        None => return Err(user_not_found()),
+        Some(user) => user,
     };
-    let _ = Redis::del(&redis, vec![&format!("{}:{}", &args.email, &session)]).await?;
+    let revoked = revoke_all_sessions(&redis, &user.email).await?;
+    info!(
+        "Password of {} set by {}, {} sessions revoked",
+        &user.email, &caller.email, revoked
+    );
+    state.audit.record(admin_entry(
+        &state.config,
+        &caller,
+        &headers,
+        peer,
+        action::USER_PASSWORD,
+        &user.email,
+    ));
     Ok(StatusCode::ACCEPTED)
 }
@@ -2,14 +2,19 @@ pub mod handlers;
 pub mod routings;
 
 use crate::routings::router;
+use app_adk_utils::session::approval::ApprovalGate;
 use app_agent::builder::agent_builder;
+use app_audit::AuditLog;
+use app_auth::password_policy::PasswordPolicy;
 use app_config::AppConfig;
+use app_cryptography::{hash::Hasher, jwt::JwtKeySet};
 use app_log::init_tracing;
+use app_mailer::mail_sender;
 use app_redis::Redis;
 use app_state::AppState;
 use dotenv::dotenv;
 use sqlx::postgres::PgPoolOptions;
-use std::{env, sync::Arc};
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 use tracing::*;
 
 pub async fn app_service() {
@@ -38,14 +43,47 @@ pub async fn app_service() {
         Err(err) => panic!("Cannot connect Redis\n{}", err),
     };
     // Generate Agent
-    let (agent_runner, agent_session) = agent_builder(&config).await.unwrap();
+    // Tool calls waiting for the user's approval
+    let agent_approvals = Arc::new(ApprovalGate::new(Duration::from_secs(
+        config.agent_approval.timeout_seconds,
+    )));
+    let (agents, agent_session) = agent_builder(&config, Some(agent_approvals.clone()))
+        .await
+        .unwrap();
+    // Access token keys
+    let jwt_keys = match JwtKeySet::from_config(&config) {
+        Ok(keys) => Arc::new(keys),
+        Err(err) => panic!("Cannot load JWT keys\n{}", err),
+    };
+    // Password hashing and policy
+    let hasher = match Hasher::from_config(&config.password) {
+        Ok(hasher) => Arc::new(hasher),
+        Err(err) => panic!("Cannot create password hasher\n{}", err),
+    };
+    let password_policy = match PasswordPolicy::from_config(&config.password) {
+        Ok(policy) => Arc::new(policy),
+        Err(err) => panic!("Cannot load breached password list\n{}", err),
+    };
+    // Outgoing mail
+    let mailer = match mail_sender(&config, pg.clone()) {
+        Ok(mailer) => mailer,
+        Err(err) => panic!("Cannot create mail sender\n{}", err.message),
+    };
+    // Security audit log
+    let audit = AuditLog::spawn(pg.clone(), &config.audit);
     // Generating AppState
     let app_state = Arc::new(AppState {
         config: config.clone(),
         pg,
         redis,
-        agent_runner: Some(agent_runner),
+        jwt_keys,
+        mailer,
+        hasher,
+        password_policy,
+        audit,
+        agents: Some(Arc::new(agents)),
         agent_session: Some(agent_session),
+        agent_approvals: Some(agent_approvals),
     });
     // Loading Routes
     let routes = router(app_state);
@@ -53,5 +91,10 @@ pub async fn app_service() {
     let tcp_listener = tokio::net::TcpListener::bind(&bind).await.unwrap();
     // Running Server ...
     info!("Serving web server on {}", &bind);
-    let _ = axum::serve(tcp_listener, routes).await;
+    // Client address is recorded on login sessions
+    let _ = axum::serve(
+        tcp_listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await;
 }
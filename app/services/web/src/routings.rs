@@ -1,15 +1,50 @@
 use crate::handlers::{
-    agent::*, customer::*, google::*, index::*, knowledge_based::*, login::*, ping::*, user::*,
+    agent::*, agent_ws::*, api_key::*, audit::*, customer::*, email::*, identity::*, index::*,
+    jwks::*, knowledge_based::*, lockout::*, login::*, mcp_client::*, mfa::*, oauth::*, oauth2::*,
+    password::*, ping::*, session::*, user::*,
 };
-use app_middleware::web_auth_middleware;
+use app_auth::rbac::{
+    AGENT_USE, AUDIT_READ, CUSTOMERS_READ, KB_READ, MCP_ADMIN, SESSIONS_ADMIN, USERS_READ,
+    USERS_WRITE,
+};
+use app_middleware::{require_permission, require_session, web_auth_middleware};
 use app_state::AppState;
 use axum::{
     Router, middleware,
-    routing::{get, patch, post},
+    routing::{delete, get, patch, post, put},
 };
 use std::sync::Arc;
 use tower_http::{services::ServeDir, trace::TraceLayer};
 
+/// Self-service routes of the caller's own account. They need a login session: an API key
+/// could otherwise link an identity, add a second factor or mint keys for its owner.
+fn account_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/sessions", get(get_sessions).delete(delete_sessions))
+        .route(
+            "/sessions/{session}",
+            get(get_session_by_id).delete(delete_session_by_id),
+        )
+        .route("/api_keys", get(get_api_keys).post(post_api_key))
+        .route(
+            "/api_keys/{id}",
+            get(get_api_key_by_id)
+                .patch(patch_api_key)
+                .delete(delete_api_key),
+        )
+        .route("/mfa/totp/enroll", post(post_totp_enroll))
+        .route("/mfa/totp/confirm", post(post_totp_confirm))
+        .route("/mfa/totp/disable", post(post_totp_disable))
+        .route("/mfa/recovery_codes", post(post_recovery_codes))
+        .route("/identities", get(get_identities))
+        .route(
+            "/identities/{provider}",
+            post(post_link_identity).delete(delete_identity),
+        )
+        .route("/change_password", patch(patch_change_password))
+        .route_layer(middleware::from_fn(require_session))
+}
+
 pub fn router(state: Arc<AppState>) -> Router {
     let asset_path = state.config.asset_path.clone();
     let asset_service = ServeDir::new(&asset_path).append_index_html_on_directories(true);
@@ -20,13 +55,38 @@ pub fn router(state: Arc<AppState>) -> Router {
         .route("/kb", get(get_kb))
         .route("/agent", get(get_agent))
         .route("/login", get(get_login).post(post_login))
+        .route("/login/mfa", post(post_login_mfa))
+        .route(
+            "/password/forgot",
+            get(get_forgot_password).post(post_forgot_password),
+        )
+        .route(
+            "/password/reset",
+            get(get_reset_password).post(post_reset_password),
+        )
+        .route("/verify_email", get(get_verify_email))
+        .route("/verify_email/resend", post(post_resend_verification))
         .route("/ping", get(ping).post(ping))
+        .route("/.well-known/jwks.json", get(get_jwks))
+        .route(
+            "/.well-known/oauth-authorization-server",
+            get(get_authorization_server),
+        )
+        .nest(
+            "/oauth2",
+            Router::new()
+                .route("/register", post(post_oauth2_register))
+                .route(
+                    "/authorize",
+                    get(get_oauth2_authorize).post(post_oauth2_authorize),
+                )
+                .route("/token", post(post_oauth2_token)),
+        )
         .nest(
-            "/google",
+            "/oauth",
             Router::new()
-                .route("/auth", get(get_google_auth))
-                .route("/callback", get(get_google_callback))
-                .route("/token", get(page_google_token)),
+                .route("/{provider}/auth", get(get_oauth_auth))
+                .route("/{provider}/callback", get(get_oauth_callback)),
         )
         // .layer(TraceLayer::new_for_http())
         // .with_state(state.clone())
@@ -35,15 +95,127 @@ pub fn router(state: Arc<AppState>) -> Router {
             Router::new()
                 .route("/ping", get(ping).post(ping))
                 .route("/logout", post(post_logout))
-                .route("/user", get(get_user).post(post_user))
-                .route("/change_password", patch(patch_change_password))
-                .route("/customer", post(post_customer))
-                .route("/kb", post(post_kb))
-                .route("/agent", post(post_agent))
+                .merge(account_router())
+                .route(
+                    "/sessions/user/{email}",
+                    get(get_user_sessions)
+                        .delete(delete_user_sessions)
+                        .route_layer(require_permission(SESSIONS_ADMIN)),
+                )
+                .route(
+                    "/sessions/user/{email}/{session}",
+                    delete(delete_user_session_by_id)
+                        .route_layer(require_permission(SESSIONS_ADMIN)),
+                )
+                .route(
+                    "/lockouts/{email}",
+                    get(get_lockout)
+                        .delete(delete_lockout)
+                        .route_layer(require_permission(SESSIONS_ADMIN)),
+                )
+                .route(
+                    "/login_failures",
+                    get(get_login_failures).route_layer(require_permission(SESSIONS_ADMIN)),
+                )
+                .route(
+                    "/audit",
+                    get(get_audit).route_layer(require_permission(AUDIT_READ)),
+                )
+                .route(
+                    "/mcp_clients",
+                    get(get_mcp_clients)
+                        .post(post_mcp_client)
+                        .route_layer(require_permission(MCP_ADMIN)),
+                )
+                .route(
+                    "/mcp_clients/{id}",
+                    get(get_mcp_client_by_id)
+                        .patch(patch_mcp_client)
+                        .delete(delete_mcp_client)
+                        .route_layer(require_permission(MCP_ADMIN)),
+                )
+                .route(
+                    "/user",
+                    get(get_user)
+                        .route_layer(require_permission(USERS_READ))
+                        .merge(post(post_user).route_layer(require_permission(USERS_WRITE))),
+                )
+                .route(
+                    "/user/{id}",
+                    get(get_user_by_id)
+                        .route_layer(require_permission(USERS_READ))
+                        .merge(
+                            patch(patch_user)
+                                .delete(delete_user)
+                                .route_layer(require_permission(USERS_WRITE)),
+                        ),
+                )
+                .route(
+                    "/user/{id}/disable",
+                    post(post_disable_user).route_layer(require_permission(USERS_WRITE)),
+                )
+                .route(
+                    "/user/{id}/enable",
+                    post(post_enable_user).route_layer(require_permission(USERS_WRITE)),
+                )
+                .route(
+                    "/user/{id}/password",
+                    post(post_user_password).route_layer(require_permission(USERS_WRITE)),
+                )
+                .route(
+                    "/user/roles",
+                    put(put_user_roles).route_layer(require_permission(USERS_WRITE)),
+                )
+                .route(
+                    "/roles",
+                    get(get_roles).route_layer(require_permission(USERS_READ)),
+                )
+                .route(
+                    "/customer",
+                    post(post_customer).route_layer(require_permission(CUSTOMERS_READ)),
+                )
+                .route(
+                    "/kb",
+                    post(post_kb).route_layer(require_permission(KB_READ)),
+                )
+                .route(
+                    "/agents",
+                    get(get_agents).route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent",
+                    post(post_agent).route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent/stream",
+                    post(post_agent_stream).route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent/ws",
+                    get(get_agent_ws).route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent/sessions",
+                    get(get_agent_sessions).route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent/sessions/{id}",
+                    get(get_agent_session)
+                        .patch(patch_agent_session)
+                        .delete(delete_agent_session)
+                        .route_layer(require_permission(AGENT_USE)),
+                )
+                .route(
+                    "/agent/sessions/{id}/approvals/{call_id}",
+                    post(post_agent_approval).route_layer(require_permission(AGENT_USE)),
+                )
+                // `route_layer` guards run inside this layer, after the token is validated
                 .layer(middleware::from_fn_with_state(
                     state.clone(),
                     web_auth_middleware,
-                )),
+                ))
+                // Routes below are added after the layer, so they skip `web_auth_middleware`
+                .route("/refresh", post(post_refresh)),
         )
         .nest_service("/assets", asset_service)
         .layer(TraceLayer::new_for_http())